@@ -0,0 +1,181 @@
+// Bundles the public directory into the binary when the `embed` feature is enabled.
+//
+// Every file is written to `$OUT_DIR/embedded_assets.rs` as an `EmbeddedFile` (see
+// src/embedded.rs), with its media type and entity tag computed here. Text files get gzip
+// and deflate versions compressed ahead of time, and precompressed `.br` and `.gz` siblings
+// are attached to the file they belong to, so the server never compresses anything itself.
+
+#[path = "src/compression/checksum.rs"]
+mod checksum;
+#[path = "src/compression/container.rs"]
+mod container;
+#[path = "src/compression/deflate.rs"]
+mod deflate;
+#[path = "src/http/mime.rs"]
+mod mime;
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Same threshold as `DEFAULT_COMPRESSION_MIN_SIZE` in src/website_handler.rs.
+const COMPRESSION_MIN_SIZE: u64 = 1024;
+
+/// Same limit as `MAX_COMPRESSION_SIZE` in src/website_handler.rs.
+const MAX_COMPRESSION_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Precompressed siblings, most preferred first, named after their `ContentEncoding` variant.
+const PRECOMPRESSED_ENCODINGS: [(&str, &str, &str); 2] =
+    [("Brotli", "br", "br"), ("Gzip", "gzip", "gz")];
+
+/// Content codings applied ahead of time, named after their `ContentEncoding` variant.
+const DYNAMIC_ENCODINGS: [(&str, &str); 2] = [("Gzip", "gzip"), ("Deflate", "deflate")];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=EMBED_PUBLIC_PATH");
+    if env::var_os("CARGO_FEATURE_EMBED").is_none() {
+        return;
+    }
+
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let public_path = env::var("EMBED_PUBLIC_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| Path::new(&manifest_dir).join("public"));
+    let public_path = fs::canonicalize(&public_path).unwrap_or_else(|e| {
+        panic!(
+            "cannot embed the public directory {}: {}",
+            public_path.display(),
+            e
+        )
+    });
+    println!("cargo:rerun-if-changed={}", public_path.display());
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let code = generate(&public_path, &out_dir.join("embedded"))
+        .unwrap_or_else(|e| panic!("cannot embed {}: {}", public_path.display(), e));
+    fs::write(out_dir.join("embedded_assets.rs"), code).unwrap();
+}
+
+/// Generates the expression listing every file under `public_path`, writing the compressed
+/// versions of the files to `variants_dir` so they can be included with `include_bytes!`.
+fn generate(public_path: &Path, variants_dir: &Path) -> IoResult<String> {
+    fs::create_dir_all(variants_dir)?;
+    let mut files = Vec::new();
+    collect_files(public_path, &mut files)?;
+    files.sort();
+
+    let mut code = String::from("&[\n");
+    for (index, path) in files.iter().enumerate() {
+        let relative = path.strip_prefix(public_path).unwrap();
+        let relative: Vec<String> = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let relative = relative.join("/");
+
+        let metadata = fs::metadata(path)?;
+        let bytes = fs::read(path)?;
+        let content_type = mime::mime_type(&relative);
+        let etag = etag_for(&bytes);
+
+        let mut variants = String::new();
+        let mut encoded = Vec::new();
+        for (variant, token, extension) in PRECOMPRESSED_ENCODINGS {
+            let sibling = PathBuf::from(format!("{}.{}", path.display(), extension));
+            let Ok(sibling_metadata) = fs::metadata(&sibling) else {
+                continue;
+            };
+            // like WebsiteHandler, ignore siblings compressed from a previous version of the file
+            let fresh = match (sibling_metadata.modified(), metadata.modified()) {
+                (Ok(sibling_time), Ok(original_time)) => sibling_time >= original_time,
+                _ => true,
+            };
+            if sibling_metadata.is_file() && fresh {
+                let sibling_etag = encoded_etag(&etag_for(&fs::read(&sibling)?), token);
+                write_variant(&mut variants, variant, &sibling, &sibling_etag);
+                encoded.push(variant);
+            }
+        }
+        let compressible = mime::is_compressible(content_type)
+            && (COMPRESSION_MIN_SIZE..=MAX_COMPRESSION_SIZE).contains(&metadata.len());
+        if compressible {
+            for (variant, token) in DYNAMIC_ENCODINGS {
+                if encoded.contains(&variant) {
+                    continue;
+                }
+                let compressed = match variant {
+                    "Gzip" => container::gzip(&bytes),
+                    _ => container::zlib(&bytes),
+                };
+                let variant_path = variants_dir.join(format!("{}.{}", index, token));
+                fs::write(&variant_path, compressed)?;
+                write_variant(
+                    &mut variants,
+                    variant,
+                    &variant_path,
+                    &encoded_etag(&etag, token),
+                );
+            }
+        }
+
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs());
+        writeln!(
+            code,
+            "    EmbeddedFile {{\n        path: {:?},\n        content_type: {:?},\n        etag: {:?},\n        modified: {:?},\n        bytes: include_bytes!({:?}),\n        variants: &[{}],\n    }},",
+            relative,
+            content_type,
+            etag,
+            modified,
+            path.display().to_string(),
+            variants,
+        )
+        .unwrap();
+    }
+    code.push(']');
+    Ok(code)
+}
+
+/// Appends every regular file under `dir` to `files`, following symbolic links.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> IoResult<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let metadata = fs::metadata(&path)?;
+        if metadata.is_dir() {
+            collect_files(&path, files)?;
+        } else if metadata.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Appends an `EmbeddedVariant` to the list of variants of a file.
+fn write_variant(variants: &mut String, variant: &str, path: &Path, etag: &str) {
+    write!(
+        variants,
+        "EmbeddedVariant {{ encoding: ContentEncoding::{}, bytes: include_bytes!({:?}), etag: {:?} }}, ",
+        variant,
+        path.display().to_string(),
+        etag
+    )
+    .unwrap();
+}
+
+/// Computes a strong entity tag from the contents of a file, since modification times
+/// are meaningless once the file is part of the binary.
+fn etag_for(bytes: &[u8]) -> String {
+    format!("\"{:08x}-{:x}\"", checksum::crc32(bytes), bytes.len())
+}
+
+/// Same as `encoded_etag` in src/representation.rs.
+fn encoded_etag(etag: &str, token: &str) -> String {
+    format!("{}-{}\"", etag.trim_end_matches('"'), token)
+}
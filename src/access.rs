@@ -0,0 +1,279 @@
+use crate::config::Cidr;
+use crate::handler_error::HandlerError;
+use crate::http::{Request, Response, StatusCode};
+use crate::prefix_table::PrefixTable;
+use crate::server::{Handler, delegate_handler};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::net::IpAddr;
+
+/// Whether a rule lets the matching clients in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Allow,
+    Deny,
+}
+
+/// A rule of an access list, e.g. `allow 10.0.0.0/8` or `deny all`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessRule {
+    pub action: Action,
+    pub range: Option<Cidr>, // the client addresses the rule applies to, None for all clients
+}
+
+impl AccessRule {
+    /// Returns true if the rule applies to a client, whose address is unknown for requests
+    /// over Unix domain sockets that no proxy reported on. Only rules for all clients apply to those.
+    fn matches(&self, client: Option<IpAddr>) -> bool {
+        match (self.range, client) {
+            (None, _) => true,
+            (Some(range), Some(client)) => range.contains(client),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+// Written the way configuration files write rules.
+impl Display for AccessRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let action = match self.action {
+            Action::Allow => "allow",
+            Action::Deny => "deny",
+        };
+        match self.range {
+            Some(range) => write!(f, "{} {}", action, range),
+            None => write!(f, "{} all", action),
+        }
+    }
+}
+
+/// The rules protecting the requests under a path prefix, tried in order until one matches the client.
+/// Clients that no rule matches are allowed, so lists restricting access end with `deny_all`.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::access::AccessList;
+///
+/// // the office network, and nobody else
+/// let admin = AccessList::new()
+///     .allow("192.0.2.0/24".parse().unwrap())
+///     .allow("2001:db8:1::/48".parse().unwrap())
+///     .deny_all();
+/// assert_eq!(admin.rules.len(), 3);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AccessList {
+    pub rules: Vec<AccessRule>,
+}
+
+impl AccessList {
+    /// Creates a list without rules, letting every client in.
+    pub fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    pub fn allow(self, range: Cidr) -> Self {
+        self.with_rule(Action::Allow, Some(range))
+    }
+
+    pub fn deny(self, range: Cidr) -> Self {
+        self.with_rule(Action::Deny, Some(range))
+    }
+
+    pub fn allow_all(self) -> Self {
+        self.with_rule(Action::Allow, None)
+    }
+
+    pub fn deny_all(self) -> Self {
+        self.with_rule(Action::Deny, None)
+    }
+
+    pub fn with_rule(mut self, action: Action, range: Option<Cidr>) -> Self {
+        self.rules.push(AccessRule { action, range });
+        self
+    }
+}
+
+/// A handler letting clients reach another handler according to their IP address,
+/// and answering the others with 403 Forbidden.
+///
+/// # Note
+///
+/// A request is checked against the list with the longest prefix covering its path only,
+/// so a list for `/admin` replaces the one for the whole site under `/admin` (see
+/// `prefix_table::PrefixTable`).
+/// The client is the one reported by trusted proxies (see `Request::client_ip`).
+///
+/// The decision is added to the access log line of the response, e.g. `access=deny:all`
+/// or `access=allow:192.0.2.0/24`, along with `access=allow:default` when no rule matched.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::WebsiteHandler;
+/// use rust_server::access::{AccessControl, AccessList};
+///
+/// let handler = AccessControl::new(WebsiteHandler::new("public".to_string()))
+///     .with_list("/", AccessList::new().deny("203.0.113.0/24".parse().unwrap()))
+///     .with_list(
+///         "/admin",
+///         AccessList::new()
+///             .allow("10.0.0.0/8".parse().unwrap())
+///             .deny_all(),
+///     );
+/// ```
+pub struct AccessControl<H> {
+    handler: H,
+    lists: PrefixTable<AccessList>,
+}
+
+impl<H: Handler> AccessControl<H> {
+    /// Creates a handler letting every client reach `handler`, until lists are added.
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            lists: PrefixTable::new(),
+        }
+    }
+
+    /// Protects the requests under a prefix with an access list. The prefix matches the path itself
+    /// and every path under it (`/admin` matches `/admin/users` but not `/administrator`), and `/`
+    /// protects the whole site. A list for a prefix that already has one replaces it.
+    pub fn with_list(mut self, prefix: &str, list: AccessList) -> Self {
+        self.lists.insert(prefix, list);
+        self
+    }
+
+    /// Returns the rule deciding whether a client can request a path, if one matches,
+    /// and whether an access list covers the path at all.
+    fn decide(&self, path: &str, client: Option<IpAddr>) -> (Option<&AccessRule>, bool) {
+        let Some(list) = self.lists.lookup(path) else {
+            return (None, false);
+        };
+        (list.rules.iter().find(|rule| rule.matches(client)), true)
+    }
+}
+
+impl<H: Handler> Handler for AccessControl<H> {
+    fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+        let (rule, protected) = self.decide(request.path(), request.client_ip());
+        let note = match rule {
+            Some(rule) => {
+                let range = rule
+                    .range
+                    .map_or("all".to_string(), |range| range.to_string());
+                match rule.action {
+                    Action::Allow => format!("access=allow:{}", range),
+                    Action::Deny => {
+                        let mut response = Response::new(StatusCode::Forbidden, None);
+                        response.add_log_note(format!("access=deny:{}", range));
+                        return Ok(response);
+                    }
+                }
+            }
+            None if protected => "access=allow:default".to_string(),
+            None => return self.handler.handle_request(request),
+        };
+        let mut response = self.handler.handle_request(request)?;
+        response.add_log_note(note);
+        Ok(response)
+    }
+
+    delegate_handler!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::OkHandler;
+
+    fn cidr(range: &str) -> Cidr {
+        range.parse().unwrap()
+    }
+
+    fn ip(addr: &str) -> Option<IpAddr> {
+        Some(addr.parse().unwrap())
+    }
+
+    fn office() -> AccessControl<OkHandler> {
+        AccessControl::new(OkHandler)
+            .with_list(
+                "/admin/",
+                AccessList::new()
+                    .deny(cidr("10.0.9.0/24"))
+                    .allow(cidr("10.0.0.0/16"))
+                    .allow(cidr("2001:db8:1::/48"))
+                    .deny_all(),
+            )
+            .with_list("/", AccessList::new().deny(cidr("203.0.113.0/24")))
+    }
+
+    /// Returns the action taken for a request, as logged.
+    fn decision(control: &AccessControl<OkHandler>, path: &str, client: Option<IpAddr>) -> String {
+        match control.decide(path, client) {
+            (Some(rule), _) => rule.to_string(),
+            (None, true) => "allow default".to_string(),
+            (None, false) => "unprotected".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_rules_are_tried_in_order() {
+        let control = office();
+        assert_eq!(
+            decision(&control, "/admin/users", ip("10.0.1.7")),
+            "allow 10.0.0.0/16"
+        );
+        // the first matching rule wins, even if a later one allows the client
+        assert_eq!(
+            decision(&control, "/admin", ip("10.0.9.7")),
+            "deny 10.0.9.0/24"
+        );
+        assert_eq!(
+            decision(&control, "/admin/", ip("2001:db8:1:2::5")),
+            "allow 2001:db8:1::/48"
+        );
+        assert_eq!(
+            decision(&control, "/admin", ip("::ffff:10.0.3.3")),
+            "allow 10.0.0.0/16"
+        );
+        assert_eq!(decision(&control, "/admin", ip("192.0.2.1")), "deny all");
+        // clients of a Unix domain socket have no address, only `all` applies to them
+        assert_eq!(decision(&control, "/admin", None), "deny all");
+        // the nested list replaces the global one
+        assert_eq!(decision(&control, "/admin", ip("203.0.113.5")), "deny all");
+        assert_eq!(
+            decision(&control, "/index.html", ip("203.0.113.5")),
+            "deny 203.0.113.0/24"
+        );
+        assert_eq!(
+            decision(&control, "/administrator", ip("192.0.2.1")),
+            "allow default"
+        );
+        assert_eq!(
+            decision(&AccessControl::new(OkHandler), "/", ip("192.0.2.1")),
+            "unprotected"
+        );
+    }
+
+    #[test]
+    fn test_paths_are_normalized() {
+        let control = office();
+        for path in ["/public/../admin/x", "//admin", "/./admin/", "/../admin"] {
+            assert_eq!(decision(&control, path, ip("192.0.2.1")), "deny all");
+        }
+    }
+
+    #[test]
+    fn test_denied_requests_get_403() {
+        let control = office();
+        let request = Request::try_from(&b"GET /admin HTTP/1.1\r\n\r\n"[..]).unwrap();
+        let response = control.handle_request(&request).unwrap();
+        assert_eq!(response.status_code(), StatusCode::Forbidden);
+        assert_eq!(response.log_notes(), ["access=deny:all"]);
+        let request = Request::try_from(&b"GET /about HTTP/1.1\r\n\r\n"[..]).unwrap();
+        let response = control.handle_request(&request).unwrap();
+        assert_eq!(response.status_code(), StatusCode::Ok);
+        assert_eq!(response.log_notes(), ["access=allow:default"]);
+    }
+}
@@ -0,0 +1,111 @@
+// Base64 (RFC 4648), in the standard alphabet of HTTP Basic credentials and the URL-safe one of
+// JSON Web Tokens.
+
+const STANDARD: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes in the standard alphabet, with padding.
+pub(crate) fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = u32::from_be_bytes([
+            0,
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - 6 * i)) & 0x3F;
+                encoded.push(char::from(STANDARD[index as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes the standard alphabet, with or without padding.
+///
+/// # Returns
+///
+/// The bytes, or `None` if `text` has characters outside of the alphabet or a length that
+/// no encoding has.
+pub(crate) fn decode(text: &str) -> Option<Vec<u8>> {
+    decode_with(text, |byte| match byte {
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    })
+}
+
+/// Decodes the URL-safe alphabet, where `-` and `_` replace `+` and `/`, with or without padding.
+pub(crate) fn decode_url_safe(text: &str) -> Option<Vec<u8>> {
+    decode_with(text, |byte| match byte {
+        b'-' => Some(62),
+        b'_' => Some(63),
+        _ => None,
+    })
+}
+
+fn decode_with(text: &str, extra: impl Fn(u8) -> Option<u32>) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    let unpadded = match text.len() % 4 {
+        0 => text
+            .strip_suffix(b"==")
+            .or_else(|| text.strip_suffix(b"="))
+            .unwrap_or(text),
+        _ => text,
+    };
+    if unpadded.len() % 4 == 1 {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(unpadded.len() * 3 / 4);
+    for chunk in unpadded.chunks(4) {
+        let mut group = 0;
+        for (i, byte) in chunk.iter().enumerate() {
+            let value = match byte {
+                b'A'..=b'Z' => u32::from(byte - b'A'),
+                b'a'..=b'z' => u32::from(byte - b'a') + 26,
+                b'0'..=b'9' => u32::from(byte - b'0') + 52,
+                _ => extra(*byte)?,
+            };
+            group |= value << (18 - 6 * i);
+        }
+        let bytes = group.to_be_bytes();
+        decoded.extend_from_slice(&bytes[1..chunk.len()]);
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        // RFC 4648, section 10
+        for (bytes, text) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode(bytes.as_bytes()), text);
+            assert_eq!(decode(text).unwrap(), bytes.as_bytes());
+            assert_eq!(
+                decode(text.trim_end_matches('=')).unwrap(),
+                bytes.as_bytes()
+            );
+        }
+        assert_eq!(decode("+/8").unwrap(), [0xFB, 0xFF]);
+        assert_eq!(decode("-_8"), None);
+        assert_eq!(decode_url_safe("-_8").unwrap(), [0xFB, 0xFF]);
+        assert_eq!(decode_url_safe("+/8"), None);
+        assert_eq!(decode("Zm9vY"), None);
+        assert_eq!(decode("Zm9v===="), None);
+    }
+}
@@ -0,0 +1,434 @@
+use super::base64;
+use super::sha256::{hmac_sha256, pbkdf2_sha256};
+use super::{CredentialsError, InvalidLine, constant_time_eq, quoted_string};
+use crate::handler_error::HandlerError;
+use crate::http::{Principal, Request, Response, StatusCode};
+use crate::prefix_table::PrefixTable;
+use crate::server::{Handler, delegate_handler};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+/// The number of PBKDF2 iterations of new password hashes, as recommended by OWASP for PBKDF2-HMAC-SHA256.
+pub const DEFAULT_ITERATIONS: u32 = 600_000;
+
+/// The scheme of the password hashes, named as in passlib so that its hashes can be used too.
+const SCHEME: &str = "$pbkdf2-sha256$";
+
+/// A password hash of a credentials file: `$pbkdf2-sha256$<iterations>$<salt>$<hash>`.
+struct PasswordHash {
+    iterations: u32,
+    salt: Vec<u8>,
+    hash: [u8; 32], // PBKDF2-HMAC-SHA256 of the password and salt
+}
+
+impl PasswordHash {
+    fn parse(text: &str) -> Result<Self, &'static str> {
+        let Some(fields) = text.strip_prefix(SCHEME) else {
+            return Err("unsupported password hash, expected one starting with `$pbkdf2-sha256$`");
+        };
+        let invalid = "invalid password hash, expected `$pbkdf2-sha256$<iterations>$<salt>$<hash>`";
+        let [iterations, salt, hash] = fields.split('$').collect::<Vec<_>>()[..] else {
+            return Err(invalid);
+        };
+        let iterations = iterations
+            .parse()
+            .ok()
+            .filter(|iterations| *iterations > 0)
+            .ok_or(invalid)?;
+        let salt = decode_ab64(salt).ok_or(invalid)?;
+        let hash = decode_ab64(hash)
+            .and_then(|hash| <[u8; 32]>::try_from(hash).ok())
+            .ok_or(invalid)?;
+        Ok(Self {
+            iterations,
+            salt,
+            hash,
+        })
+    }
+
+    fn matches(&self, password: &str) -> bool {
+        let derived = pbkdf2_sha256(password.as_bytes(), &self.salt, self.iterations);
+        constant_time_eq(&derived, &self.hash)
+    }
+}
+
+/// Encodes bytes in the base64 variant of passlib: the standard alphabet with `.` instead of `+`, unpadded.
+fn encode_ab64(bytes: &[u8]) -> String {
+    base64::encode(bytes)
+        .trim_end_matches('=')
+        .replace('+', ".")
+}
+
+fn decode_ab64(text: &str) -> Option<Vec<u8>> {
+    base64::decode(&text.replace('.', "+"))
+}
+
+/// Hashes a password for a credentials file.
+///
+/// # Arguments
+///
+/// * `password` - The password.
+/// * `salt` - Random bytes, different for every password; 16 are plenty.
+/// * `iterations` - The number of PBKDF2 iterations, `DEFAULT_ITERATIONS` unless the server is too slow for it.
+///
+/// # Returns
+///
+/// The hash, written as `$pbkdf2-sha256$<iterations>$<salt>$<hash>`.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::auth::{Credentials, hash_password};
+///
+/// let hash = hash_password("correct horse", b"0123456789abcdef", 1000);
+/// assert!(hash.starts_with("$pbkdf2-sha256$1000$"));
+/// let credentials = Credentials::parse(&format!("alice:{}", hash)).unwrap();
+/// assert!(credentials.verify("alice", "correct horse"));
+/// ```
+pub fn hash_password(password: &str, salt: &[u8], iterations: u32) -> String {
+    let hash = pbkdf2_sha256(password.as_bytes(), salt, iterations);
+    format!(
+        "{}{}${}${}",
+        SCHEME,
+        iterations,
+        encode_ab64(salt),
+        encode_ab64(&hash)
+    )
+}
+
+/// The users of a credentials file and their password hashes.
+///
+/// # Note
+///
+/// The file has a `user:hash` line per user, in the manner of htpasswd files, with the hashes made by
+/// `hash_password` (or `rust_server hash-password`). Empty lines and lines starting with `#` are skipped.
+///
+/// Hashing a password takes a while on purpose, so the last password verified for each user is
+/// remembered (as a keyed digest, not in clear), and clients sending it again with every request
+/// are not slowed down.
+pub struct Credentials {
+    users: HashMap<String, PasswordHash>,
+    dummy: PasswordHash, // checked against for unknown users, so that they take as long as the others
+    verified: Mutex<HashMap<String, [u8; 32]>>, // user to digest of the last password verified
+}
+
+impl Credentials {
+    /// Parses the contents of a credentials file.
+    ///
+    /// # Returns
+    ///
+    /// The credentials, or the first invalid line: one without a colon, a hash of another scheme,
+    /// or a user given twice.
+    pub fn parse(source: &str) -> Result<Self, InvalidLine> {
+        let mut users = HashMap::new();
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: &str| InvalidLine {
+                line: index + 1,
+                reason: reason.to_string(),
+            };
+            let Some((user, hash)) = line.split_once(':') else {
+                return Err(invalid("expected `user:hash`"));
+            };
+            if user.is_empty() {
+                return Err(invalid("the user name is empty"));
+            }
+            let hash = PasswordHash::parse(hash).map_err(invalid)?;
+            if users.insert(user.to_string(), hash).is_some() {
+                return Err(invalid(&format!("`{}` is already in the file", user)));
+            }
+        }
+        // as slow as the slowest hash, and not slow at all when there is nobody to hide
+        let iterations = users
+            .values()
+            .map(|hash| hash.iterations)
+            .max()
+            .unwrap_or(1);
+        Ok(Self {
+            users,
+            dummy: PasswordHash {
+                iterations,
+                salt: vec![0; 16],
+                hash: [0; 32],
+            },
+            verified: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Reads and parses a credentials file.
+    pub fn load(path: &Path) -> Result<Self, CredentialsError> {
+        let source = fs::read_to_string(path).map_err(|error| CredentialsError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        Self::parse(&source).map_err(|error| CredentialsError::Invalid {
+            path: path.to_path_buf(),
+            error,
+        })
+    }
+
+    /// Returns the number of users.
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    /// Returns true if `user` is in the file.
+    pub fn contains(&self, user: &str) -> bool {
+        self.users.contains_key(user)
+    }
+
+    /// Returns true if `user` is in the file and `password` is theirs.
+    pub fn verify(&self, user: &str, password: &str) -> bool {
+        let Some(hash) = self.users.get(user) else {
+            self.dummy.matches(password);
+            return false;
+        };
+        // keyed with the stored hash, so the cache is no shortcut to guessing passwords
+        let digest = hmac_sha256(&hash.hash, password.as_bytes());
+        let verified = self.verified.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(known) = verified.get(user)
+            && constant_time_eq(known, &digest)
+        {
+            return true;
+        }
+        drop(verified); // other requests are not held up while hashing
+        if !hash.matches(password) {
+            return false;
+        }
+        self.verified
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(user.to_string(), digest);
+        true
+    }
+}
+
+/// The requests under a path prefix, reserved to the users of a credentials file.
+pub struct Realm {
+    pub name: String, // shown by browsers when they ask for a password
+    pub credentials: Credentials,
+}
+
+impl Realm {
+    /// Returns the `WWW-Authenticate` challenge of the realm.
+    fn challenge(&self) -> String {
+        format!(
+            "Basic realm={}, charset=\"UTF-8\"",
+            quoted_string(&self.name)
+        )
+    }
+}
+
+/// Returns the user and password of an `Authorization: Basic` header (RFC 7617), if it is one.
+fn basic_credentials(authorization: &str) -> Option<(String, String)> {
+    let (scheme, token) = authorization.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("Basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(base64::decode(token.trim())?).ok()?;
+    // user names cannot contain colons, passwords can
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+/// A handler asking for a user name and password before letting requests under some path prefixes
+/// reach another handler, with HTTP Basic authentication.
+///
+/// # Note
+///
+/// A request is checked against the realm with the longest prefix covering its path only
+/// (see `prefix_table::PrefixTable`). Requests without valid credentials get 401 Unauthorized with
+/// a `WWW-Authenticate` challenge naming the realm, which makes browsers ask for a password.
+///
+/// The handler sees the user as the `Request::principal` of the request, and the user is added
+/// to the access log line of the response, e.g. `auth=user:alice`, while rejected
+/// requests get `auth=missing`, or `auth=fail` along with the user if it is in the file.
+///
+/// Basic credentials are sent in clear with every request: the server should be reached through
+/// a proxy terminating TLS. Every wrong password and unknown user costs a whole password hash, which
+/// takes a worker a good fraction of a second with `DEFAULT_ITERATIONS`, so a few clients could keep
+/// every worker busy: wrap this handler in a `RateLimiter` keyed by client IP, which also stops
+/// password guessing.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::WebsiteHandler;
+/// use rust_server::auth::{BasicAuth, Credentials, hash_password};
+///
+/// let hash = hash_password("s3cret", b"a random salt", 1000);
+/// let credentials = Credentials::parse(&format!("admin:{}", hash)).unwrap();
+/// let handler = BasicAuth::new(WebsiteHandler::new("public".to_string()))
+///     .with_realm("/admin", "Administration", credentials);
+/// ```
+pub struct BasicAuth<H> {
+    handler: H,
+    realms: PrefixTable<Realm>,
+}
+
+impl<H: Handler> BasicAuth<H> {
+    /// Creates a handler letting every request reach `handler`, until realms are added.
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            realms: PrefixTable::new(),
+        }
+    }
+
+    /// Reserves the requests under a prefix to the users of `credentials`. The prefix matches the
+    /// path itself and every path under it, and `/` protects the whole site. A realm for a prefix
+    /// that already has one replaces it.
+    pub fn with_realm(mut self, prefix: &str, name: &str, credentials: Credentials) -> Self {
+        let realm = Realm {
+            name: name.to_string(),
+            credentials,
+        };
+        self.realms.insert(prefix, realm);
+        self
+    }
+}
+
+impl<H: Handler> Handler for BasicAuth<H> {
+    fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+        let Some(realm) = self.realms.lookup(request.path()) else {
+            return self.handler.handle_request(request);
+        };
+        let note = match request.header("Authorization").and_then(basic_credentials) {
+            Some((user, password)) if realm.credentials.verify(&user, &password) => {
+                let principal = Principal::new(&user);
+                let request = request.clone().with_principal(&principal);
+                let mut response = self.handler.handle_request(&request)?;
+                response.add_log_note(format!("auth=user:{}", user));
+                return Ok(response);
+            }
+            // names that are not in the file are not logged, as they come straight from the client
+            Some((user, _)) if realm.credentials.contains(&user) => format!("auth=fail:{}", user),
+            Some(_) => "auth=fail".to_string(),
+            None => "auth=missing".to_string(),
+        };
+        let mut response = Response::new(StatusCode::Unauthorized, None)
+            .with_header("WWW-Authenticate", realm.challenge());
+        response.add_log_note(note);
+        Ok(response)
+    }
+
+    delegate_handler!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{OkHandler, get};
+
+    /// Credentials with few iterations, to keep the tests fast.
+    fn credentials() -> Credentials {
+        let source = format!(
+            "# users of the admin pages\n\nalice:{}\nbob:{}\n",
+            hash_password("wonderland", b"salt of alice", 10),
+            hash_password("pa:ss", b"salt of bob", 10)
+        );
+        Credentials::parse(&source).unwrap()
+    }
+
+    #[test]
+    fn test_credentials_files() {
+        // a hash of "password" made with Python's hashlib, encoded as passlib does
+        let passlib = "$pbkdf2-sha256$29000$9t7be09prfXee2/NOUeotQ$yIPphVdF97u.XzygN1ToroW.pJoenSO2MPWQIaDuH7Y";
+        let imported = Credentials::parse(&format!("user:{}", passlib)).unwrap();
+        assert!(imported.verify("user", "password"));
+
+        let credentials = credentials();
+        assert_eq!(credentials.len(), 2);
+        assert!(credentials.verify("alice", "wonderland"));
+        assert!(credentials.verify("bob", "pa:ss"));
+        assert!(!credentials.verify("alice", "Wonderland"));
+        assert!(!credentials.verify("carol", "wonderland"));
+        // the remembered password is checked like the others
+        assert!(credentials.verify("alice", "wonderland"));
+        assert!(!credentials.verify("alice", "wonderland!"));
+
+        for (source, error) in [
+            ("alice", "1: expected `user:hash`"),
+            (
+                "# comment\nalice:{SHA}abc",
+                "2: unsupported password hash, expected one starting with `$pbkdf2-sha256$`",
+            ),
+            (
+                "alice:$pbkdf2-sha256$0$c2FsdA$abc",
+                "1: invalid password hash, expected `$pbkdf2-sha256$<iterations>$<salt>$<hash>`",
+            ),
+        ] {
+            assert_eq!(Credentials::parse(source).err().unwrap().to_string(), error);
+        }
+        let hash = hash_password("x", b"salt", 1);
+        let twice = format!("alice:{}\nalice:{}", hash, hash);
+        assert_eq!(
+            Credentials::parse(&twice).err().unwrap().to_string(),
+            "2: `alice` is already in the file"
+        );
+    }
+
+    #[test]
+    fn test_basic_credentials() {
+        assert_eq!(
+            basic_credentials("Basic YWxhZGRpbjpvcGVuc2VzYW1l"),
+            Some(("aladdin".to_string(), "opensesame".to_string()))
+        );
+        assert_eq!(
+            basic_credentials("basic dXNlcjpwYTpzcw=="),
+            Some(("user".to_string(), "pa:ss".to_string()))
+        );
+        assert_eq!(basic_credentials("Bearer YWxhZGRpbjpvcGVuc2VzYW1l"), None);
+        assert_eq!(basic_credentials("Basic !!!"), None);
+        assert_eq!(basic_credentials("Basic bm9jb2xvbg=="), None); // "nocolon"
+    }
+
+    #[test]
+    fn test_realms_ask_for_credentials() {
+        let handler = BasicAuth::new(OkHandler)
+            .with_realm("/admin/", "Admin \"area\"", credentials())
+            .with_realm("/", "Site", Credentials::parse("").unwrap());
+        let response = get(&handler, "/admin/users", None);
+        assert_eq!(response.status_code(), StatusCode::Unauthorized);
+        assert_eq!(
+            response.header("WWW-Authenticate"),
+            Some("Basic realm=\"Admin \\\"area\\\"\", charset=\"UTF-8\"")
+        );
+        assert_eq!(response.log_notes(), ["auth=missing"]);
+
+        let alice = format!(
+            "Authorization: Basic {}",
+            base64::encode(b"alice:wonderland")
+        );
+        let response = get(&handler, "/admin", Some(&alice));
+        assert_eq!(response.status_code(), StatusCode::Ok);
+        assert_eq!(response.log_notes(), ["auth=user:alice"]);
+
+        let wrong = format!("Authorization: Basic {}", base64::encode(b"alice:alice"));
+        let response = get(&handler, "/public/../admin/", Some(&wrong));
+        assert_eq!(response.status_code(), StatusCode::Unauthorized);
+        assert_eq!(response.log_notes(), ["auth=fail:alice"]);
+
+        // the whole site is a realm of its own, which nobody can enter
+        let response = get(&handler, "/administrator", Some(&alice));
+        assert_eq!(
+            response.header("WWW-Authenticate"),
+            Some("Basic realm=\"Site\", charset=\"UTF-8\"")
+        );
+        assert_eq!(response.log_notes(), ["auth=fail"]);
+        let open = BasicAuth::new(OkHandler).with_realm("/admin", "Admin", credentials());
+        assert_eq!(
+            get(&open, "/index.html", None).status_code(),
+            StatusCode::Ok
+        );
+    }
+}
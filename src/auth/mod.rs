@@ -0,0 +1,92 @@
+// Authentication of the clients: HTTP Basic credentials checked against password hashes, bearer
+// tokens checked against a token store and JSON Web Tokens checked against shared keys, along with
+// the hash functions and encodings they need, written for the crate.
+
+pub use crate::http::Principal;
+pub use basic::{BasicAuth, Credentials, DEFAULT_ITERATIONS};
+pub use basic::{Realm, hash_password};
+pub use jwt::{JwtAuth, JwtError, JwtPolicy, load_jwt_key};
+pub use token::{TokenAuth, TokenError, TokenPolicy, TokenStore, hash_token};
+
+mod base64;
+mod basic;
+mod jwt;
+mod sha256;
+mod token;
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::Error as IoError;
+use std::path::PathBuf;
+
+/// An invalid line of a credentials file or token store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidLine {
+    pub line: usize, // starting at 1
+    pub reason: String,
+}
+
+impl Display for InvalidLine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}: {}", self.line, self.reason)
+    }
+}
+
+/// A credentials file, token store or JWT key that could not be loaded.
+#[derive(Debug)]
+pub enum CredentialsError {
+    Io { path: PathBuf, error: IoError }, // the file cannot be read
+    Invalid { path: PathBuf, error: InvalidLine }, // the file is read but a line is invalid
+    KeyTooShort { path: PathBuf, len: usize }, // the JWT key is read but too short for HS256
+}
+
+impl Display for CredentialsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            CredentialsError::Io { path, error } => {
+                write!(f, "cannot read {}: {}", path.display(), error)
+            }
+            CredentialsError::Invalid { path, error } => write!(f, "{}:{}", path.display(), error),
+            CredentialsError::KeyTooShort { path, len } => write!(
+                f,
+                "{}: the key has {} bytes, HS256 keys need at least {}",
+                path.display(),
+                len,
+                jwt::MIN_KEY_LENGTH
+            ),
+        }
+    }
+}
+
+impl Error for CredentialsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CredentialsError::Io { error, .. } => Some(error),
+            CredentialsError::Invalid { .. } | CredentialsError::KeyTooShort { .. } => None,
+        }
+    }
+}
+
+/// Writes a value as a quoted string of a `WWW-Authenticate` parameter, escaping quotes and backslashes.
+pub(crate) fn quoted_string(value: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Compares two secrets in a time that depends on their length only, not on where they differ,
+/// so that response times do not tell how much of a guess was right.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let difference = a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y));
+    // keeps the compiler from turning the fold into an early return
+    std::hint::black_box(difference) == 0
+}
@@ -0,0 +1,548 @@
+use super::sha256::sha256;
+use super::{CredentialsError, InvalidLine, constant_time_eq, quoted_string};
+use crate::handler_error::HandlerError;
+use crate::http::{Principal, Request, Response, StatusCode, Value, parse_rfc3339};
+use crate::prefix_table::PrefixTable;
+use crate::server::{Handler, delegate_handler};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Returns the hash of a token, as written in token stores: `sha256:` and the digest in hex.
+///
+/// # Note
+///
+/// Tokens are long random strings, unlike passwords, so a plain digest is enough to keep
+/// a leaked store from giving them away.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::auth::hash_token;
+///
+/// assert_eq!(
+///     hash_token("abc"),
+///     "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+/// );
+/// ```
+pub fn hash_token(token: &str) -> String {
+    let hex: String = sha256(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256:{}", hex)
+}
+
+fn parse_hash(text: &str) -> Option<[u8; 32]> {
+    let hex = text.strip_prefix("sha256:")?;
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut hash = [0; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(hash)
+}
+
+/// A token of a token store.
+struct StoredToken {
+    name: String,
+    hash: [u8; 32],
+    scopes: Vec<String>,
+    expires: Option<SystemTime>,
+}
+
+/// Why a token was not accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenError {
+    Unknown,                  // no token of the store has this hash
+    Expired { name: String }, // the token is in the store, but past its expiry
+}
+
+impl Display for TokenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            TokenError::Unknown => write!(f, "The access token is unknown"),
+            TokenError::Expired { .. } => write!(f, "The access token expired"),
+        }
+    }
+}
+
+/// The tokens clients can authenticate with, by their hashes, along with their scopes and expiry.
+///
+/// # Note
+///
+/// The file has a line per token: a name, the hash made by `hash_token` (or `rust_server generate-token`),
+/// and optionally `scopes=` with a comma-separated list and `expires=` with a date or a time in UTC,
+/// e.g. `2026-12-31` or `2026-12-31T18:00:00Z`. Empty lines and lines starting with `#` are skipped.
+///
+/// ```text
+/// ci      sha256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08 scopes=read,deploy
+/// partner sha256:60303ae22b998861bce3b28f33eec1be758a213c86c93c076dbe9f558c11c752 scopes=read expires=2026-12-31
+/// ```
+pub struct TokenStore {
+    tokens: Vec<StoredToken>,
+}
+
+impl TokenStore {
+    /// Parses the contents of a token store.
+    ///
+    /// # Returns
+    ///
+    /// The store, or the first invalid line: one without a hash, with an unknown field or an invalid date,
+    /// or with a name or hash already given.
+    pub fn parse(source: &str) -> Result<Self, InvalidLine> {
+        let mut tokens: Vec<StoredToken> = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: &str| InvalidLine {
+                line: index + 1,
+                reason: reason.to_string(),
+            };
+            let mut fields = line.split_whitespace();
+            let name = fields.next().unwrap_or_default();
+            let hash = fields.next().and_then(parse_hash).ok_or_else(|| {
+                invalid("expected a name and a hash such as `sha256:<64 hex digits>`")
+            })?;
+            let mut token = StoredToken {
+                name: name.to_string(),
+                hash,
+                scopes: Vec::new(),
+                expires: None,
+            };
+            for field in fields {
+                match field.split_once('=') {
+                    Some(("scopes", scopes)) => {
+                        token.scopes = scopes
+                            .split(',')
+                            .filter(|scope| !scope.is_empty())
+                            .map(str::to_string)
+                            .collect();
+                    }
+                    Some(("expires", date)) => {
+                        token.expires = Some(parse_rfc3339(date).ok_or_else(|| {
+                            invalid("expected a date such as 2026-12-31 or 2026-12-31T18:00:00Z")
+                        })?);
+                    }
+                    _ => {
+                        return Err(invalid(&format!(
+                            "unknown field `{}`, expected `scopes=` or `expires=`",
+                            field
+                        )));
+                    }
+                }
+            }
+            if tokens.iter().any(|other| other.name == token.name) {
+                return Err(invalid(&format!("`{}` is already in the file", name)));
+            }
+            if tokens.iter().any(|other| other.hash == token.hash) {
+                return Err(invalid("the hash is already in the file"));
+            }
+            tokens.push(token);
+        }
+        Ok(Self { tokens })
+    }
+
+    /// Reads and parses a token store.
+    pub fn load(path: &Path) -> Result<Self, CredentialsError> {
+        let source = fs::read_to_string(path).map_err(|error| CredentialsError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        Self::parse(&source).map_err(|error| CredentialsError::Invalid {
+            path: path.to_path_buf(),
+            error,
+        })
+    }
+
+    /// Returns the number of tokens.
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Looks up the token a client sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The token, as sent.
+    /// * `now` - The current time, to check the expiry against.
+    ///
+    /// # Returns
+    ///
+    /// The principal of the token, with its name and scopes, or why it is not accepted.
+    pub fn authenticate(&self, token: &str, now: SystemTime) -> Result<Principal, TokenError> {
+        let hash = sha256(token.as_bytes());
+        // every hash is compared, so the time taken does not tell which one was close
+        let mut found = None;
+        for stored in &self.tokens {
+            if constant_time_eq(&stored.hash, &hash) {
+                found = Some(stored);
+            }
+        }
+        let stored = found.ok_or(TokenError::Unknown)?;
+        if stored.expires.is_some_and(|expires| expires <= now) {
+            return Err(TokenError::Expired {
+                name: stored.name.clone(),
+            });
+        }
+        Ok(Principal {
+            name: stored.name.clone(),
+            scopes: stored.scopes.clone(),
+            expires: stored.expires,
+            claims: Vec::new(),
+        })
+    }
+}
+
+/// The requests under a path prefix, reserved to the tokens of a store that have the required scopes.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::auth::{TokenPolicy, TokenStore};
+/// use std::sync::Arc;
+///
+/// let tokens = Arc::new(TokenStore::parse("").unwrap());
+/// let policy = TokenPolicy::new(tokens)
+///     .with_realm("admin")
+///     .with_scope("admin")
+///     .with_header("X-API-Key");
+/// assert_eq!(policy.scopes, ["admin"]);
+/// ```
+pub struct TokenPolicy {
+    pub realm: String,           // named in the `WWW-Authenticate` challenges
+    pub scopes: Vec<String>,     // every one of them must be granted to the token
+    pub header: Option<String>,  // a header carrying the token, besides `Authorization: Bearer`
+    pub query: Option<String>,   // a query parameter carrying the token
+    pub tokens: Arc<TokenStore>, // may be shared by several policies
+}
+
+impl TokenPolicy {
+    /// Creates a policy letting any valid token of `tokens` in, from `Authorization: Bearer` headers.
+    pub fn new(tokens: Arc<TokenStore>) -> Self {
+        Self {
+            realm: "api".to_string(),
+            scopes: Vec::new(),
+            header: None,
+            query: None,
+            tokens,
+        }
+    }
+
+    pub fn with_realm(mut self, realm: &str) -> Self {
+        self.realm = realm.to_string();
+        self
+    }
+
+    /// Requires a scope, in addition to the ones already required.
+    pub fn with_scope(mut self, scope: &str) -> Self {
+        self.scopes.push(scope.to_string());
+        self
+    }
+
+    /// Also takes the token from a header, such as `X-API-Key`, when there is no bearer token.
+    pub fn with_header(mut self, name: &str) -> Self {
+        self.header = Some(name.to_string());
+        self
+    }
+
+    /// Also takes the token from a query parameter, such as `access_token`, when there is no bearer
+    /// token nor header. The value is taken as is, without percent-decoding.
+    pub fn with_query(mut self, name: &str) -> Self {
+        self.query = Some(name.to_string());
+        self
+    }
+
+    /// Returns the token of a request: the bearer token, or else the configured header or query parameter.
+    fn token<'r>(&self, request: &Request<'r>) -> Option<&'r str> {
+        let bearer = request.header("Authorization").and_then(|authorization| {
+            let (scheme, token) = authorization.split_once(' ')?;
+            scheme.eq_ignore_ascii_case("Bearer").then(|| token.trim())
+        });
+        let header = || request.header(self.header.as_deref()?);
+        let query = || match request.query_string()?.get(self.query.as_deref()?)? {
+            Value::Single(token) => Some(*token),
+            Value::Multiple(_) => None, // ambiguous
+        };
+        bearer
+            .or_else(header)
+            .or_else(query)
+            .filter(|token| !token.is_empty())
+    }
+
+    /// Returns the `WWW-Authenticate` challenge of the policy, with the error parameters of RFC 6750.
+    fn challenge(&self, error: Option<(&str, String)>) -> String {
+        let mut challenge = format!("Bearer realm={}", quoted_string(&self.realm));
+        if !self.scopes.is_empty() {
+            challenge.push_str(&format!(
+                ", scope={}",
+                quoted_string(&self.scopes.join(" "))
+            ));
+        }
+        if let Some((code, description)) = error {
+            challenge.push_str(&format!(
+                ", error={}, error_description={}",
+                quoted_string(code),
+                quoted_string(&description)
+            ));
+        }
+        challenge
+    }
+}
+
+/// A handler letting requests under some path prefixes reach another handler only with a valid token,
+/// such as an API key, sent as a bearer token (RFC 6750) or in a configured header or query parameter.
+///
+/// # Note
+///
+/// A request is checked against the policy with the longest prefix covering its path only
+/// (see `prefix_table::PrefixTable`). Requests without a token get 401 Unauthorized with a
+/// `WWW-Authenticate` challenge, those with an unknown or expired token get 401 with
+/// `error="invalid_token"`, and those whose token lacks a required scope get 403 Forbidden
+/// with `error="insufficient_scope"`.
+///
+/// The handler sees the token as the `Request::principal` of the request, and its name is added to
+/// the access log line of the response, e.g. `auth=token:ci`, while rejected requests get `auth=missing`,
+/// `auth=invalid`, `auth=expired:<name>` or `auth=scope:<name>`. Tokens in query strings appear in
+/// the request lines of access logs, so headers are better when clients can send them.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::WebsiteHandler;
+/// use rust_server::auth::{TokenAuth, TokenPolicy, TokenStore, hash_token};
+/// use std::sync::Arc;
+///
+/// let store = format!("ci {} scopes=read,deploy", hash_token("a long random token"));
+/// let tokens = Arc::new(TokenStore::parse(&store).unwrap());
+/// let handler = TokenAuth::new(WebsiteHandler::new("public".to_string()))
+///     .with_policy("/api", TokenPolicy::new(Arc::clone(&tokens)).with_scope("read"))
+///     .with_policy("/api/deploy", TokenPolicy::new(tokens).with_scope("deploy"));
+/// ```
+pub struct TokenAuth<H> {
+    handler: H,
+    policies: PrefixTable<TokenPolicy>,
+}
+
+impl<H: Handler> TokenAuth<H> {
+    /// Creates a handler letting every request reach `handler`, until policies are added.
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            policies: PrefixTable::new(),
+        }
+    }
+
+    /// Protects the requests under a prefix with a policy. The prefix matches the path itself and
+    /// every path under it, and `/` protects the whole site. A policy for a prefix that already has
+    /// one replaces it.
+    pub fn with_policy(mut self, prefix: &str, policy: TokenPolicy) -> Self {
+        self.policies.insert(prefix, policy);
+        self
+    }
+}
+
+impl<H: Handler> Handler for TokenAuth<H> {
+    fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+        let Some(policy) = self.policies.lookup(request.path()) else {
+            return self.handler.handle_request(request);
+        };
+        let (status, error, note) = match policy.token(request) {
+            None => (StatusCode::Unauthorized, None, "auth=missing".to_string()),
+            Some(token) => match policy.tokens.authenticate(token, SystemTime::now()) {
+                Err(e) => {
+                    let note = match &e {
+                        TokenError::Unknown => "auth=invalid".to_string(),
+                        TokenError::Expired { name } => format!("auth=expired:{}", name),
+                    };
+                    let error = ("invalid_token", e.to_string());
+                    (StatusCode::Unauthorized, Some(error), note)
+                }
+                Ok(principal) => {
+                    if policy.scopes.iter().all(|scope| principal.has_scope(scope)) {
+                        let request = request.clone().with_principal(&principal);
+                        let mut response = self.handler.handle_request(&request)?;
+                        response.add_log_note(format!("auth=token:{}", principal.name));
+                        return Ok(response);
+                    }
+                    let description = "The access token lacks a required scope".to_string();
+                    let note = format!("auth=scope:{}", principal.name);
+                    (
+                        StatusCode::Forbidden,
+                        Some(("insufficient_scope", description)),
+                        note,
+                    )
+                }
+            },
+        };
+        let mut response =
+            Response::new(status, None).with_header("WWW-Authenticate", policy.challenge(error));
+        response.add_log_note(note);
+        Ok(response)
+    }
+
+    delegate_handler!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{WhoAmI, get};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn store() -> Arc<TokenStore> {
+        let source = format!(
+            "# CI and partners\n\nci {} scopes=read,deploy\npartner {} scopes=read expires=2001-09-09\n",
+            hash_token("ci-token"),
+            hash_token("partner-token")
+        );
+        Arc::new(TokenStore::parse(&source).unwrap())
+    }
+
+    #[test]
+    fn test_token_stores() {
+        let store = store();
+        assert_eq!(store.len(), 2);
+        let before = UNIX_EPOCH + Duration::from_secs(1_000_000_000); // 2001-09-09T01:46:40Z
+        let principal = store.authenticate("ci-token", before).unwrap();
+        assert_eq!(principal.name, "ci");
+        assert!(principal.has_scope("deploy"));
+        assert!(!principal.has_scope("admin"));
+        let day_before = parse_rfc3339("2001-09-08T23:59:59Z").unwrap();
+        let partner = store.authenticate("partner-token", day_before);
+        assert_eq!(partner.unwrap().expires, parse_rfc3339("2001-09-09"));
+        assert_eq!(
+            store.authenticate("partner-token", before),
+            Err(TokenError::Expired {
+                name: "partner".to_string()
+            })
+        );
+        assert_eq!(
+            store.authenticate("ci-token ", before),
+            Err(TokenError::Unknown)
+        );
+
+        let hash = hash_token("x");
+        for (source, error) in [
+            (
+                "ci",
+                "1: expected a name and a hash such as `sha256:<64 hex digits>`",
+            ),
+            (
+                "ci sha256:abcd",
+                "1: expected a name and a hash such as `sha256:<64 hex digits>`",
+            ),
+            (
+                &format!("ci {} scope=read", hash),
+                "1: unknown field `scope=read`, expected `scopes=` or `expires=`",
+            ),
+            (
+                &format!("ci {} expires=tomorrow", hash),
+                "1: expected a date such as 2026-12-31 or 2026-12-31T18:00:00Z",
+            ),
+            (
+                &format!("ci {}\n#\nbot {}", hash, hash),
+                "3: the hash is already in the file",
+            ),
+        ] {
+            assert_eq!(TokenStore::parse(source).err().unwrap().to_string(), error);
+        }
+    }
+
+    #[test]
+    fn test_tokens_are_taken_from_the_request() {
+        let handler = TokenAuth::new(WhoAmI).with_policy(
+            "/",
+            TokenPolicy::new(store())
+                .with_header("X-API-Key")
+                .with_query("access_token"),
+        );
+        let note = |target: &str, header: Option<&str>| {
+            get(&handler, target, header).log_notes().join(" ")
+        };
+        assert_eq!(
+            note("/", Some("Authorization: bearer  ci-token")),
+            "auth=token:ci"
+        );
+        assert_eq!(note("/", Some("X-API-Key: ci-token")), "auth=token:ci");
+        assert_eq!(note("/?access_token=ci-token", None), "auth=token:ci");
+        assert_eq!(
+            note("/?access_token=ci-token&access_token=ci-token", None),
+            "auth=missing"
+        );
+        assert_eq!(
+            note("/", Some("Authorization: Basic ci-token")),
+            "auth=missing"
+        );
+        assert_eq!(note("/?access_token=", None), "auth=missing");
+    }
+
+    #[test]
+    fn test_policies_check_tokens_and_scopes() {
+        let handler = TokenAuth::new(WhoAmI)
+            .with_policy("/api/", TokenPolicy::new(store()).with_scope("read"))
+            .with_policy(
+                "/api/admin",
+                TokenPolicy::new(store())
+                    .with_realm("admin \"zone\"")
+                    .with_scope("admin"),
+            );
+
+        let response = get(&handler, "/api/items", None);
+        assert_eq!(response.status_code(), StatusCode::Unauthorized);
+        assert_eq!(
+            response.header("WWW-Authenticate"),
+            Some("Bearer realm=\"api\", scope=\"read\"")
+        );
+        assert_eq!(response.log_notes(), ["auth=missing"]);
+
+        let response = get(&handler, "/api", Some("Authorization: Bearer ci-token"));
+        assert_eq!(response.status_code(), StatusCode::Ok);
+        assert_eq!(response.body(), Some(&b"ci"[..]));
+        assert_eq!(response.log_notes(), ["auth=token:ci"]);
+
+        let response = get(
+            &handler,
+            "/api",
+            Some("Authorization: Bearer partner-token"),
+        );
+        assert_eq!(
+            response.header("WWW-Authenticate"),
+            Some(
+                "Bearer realm=\"api\", scope=\"read\", error=\"invalid_token\", error_description=\"The access token expired\""
+            )
+        );
+        assert_eq!(response.log_notes(), ["auth=expired:partner"]);
+        let response = get(&handler, "/api", Some("Authorization: Bearer guess"));
+        assert_eq!(response.status_code(), StatusCode::Unauthorized);
+        assert_eq!(response.log_notes(), ["auth=invalid"]);
+
+        let response = get(
+            &handler,
+            "/api/x/../admin/users",
+            Some("Authorization: Bearer ci-token"),
+        );
+        assert_eq!(response.status_code(), StatusCode::Forbidden);
+        assert_eq!(
+            response.header("WWW-Authenticate"),
+            Some(
+                "Bearer realm=\"admin \\\"zone\\\"\", scope=\"admin\", error=\"insufficient_scope\", error_description=\"The access token lacks a required scope\""
+            )
+        );
+        assert_eq!(response.log_notes(), ["auth=scope:ci"]);
+
+        let response = get(&handler, "/index.html", None);
+        assert_eq!(response.status_code(), StatusCode::Ok);
+        assert_eq!(response.body(), None);
+    }
+}
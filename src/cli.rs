@@ -0,0 +1,408 @@
+// Command-line arguments of the server binary.
+
+use rust_server::auth::DEFAULT_ITERATIONS;
+use rust_server::http::parse_rfc3339;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::PathBuf;
+
+/// What the command line asks the binary to do.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Serve(Options),       // run the server (the default when no command is given)
+    CheckConfig(Options), // validate the configuration and print the effective settings
+    HashPassword {
+        user: String,
+        iterations: u32,
+    }, // print a credentials line for a password read from stdin
+    GenerateToken {
+        name: String,
+        scopes: Vec<String>,
+        expires: Option<String>,
+    }, // print a token store line for a new random token
+    Help,
+    Version,
+}
+
+/// Settings given as flags, which take precedence over environment variables and the configuration file.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Options {
+    pub config: Option<PathBuf>, // --config: the configuration file
+    pub host: Option<String>,    // --host: the host to listen on
+    pub port: Option<u16>,       // --port: the port to listen on
+    pub root: Option<PathBuf>,   // --root: the directory of the files to serve
+    pub workers: Option<usize>,  // --workers: the number of worker threads
+}
+
+/// An invalid command line.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CliError(String);
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Returns the help text printed by `--help`.
+pub fn usage() -> String {
+    let name = env!("CARGO_PKG_NAME");
+    format!(
+        "\
+A small HTTP/1.1 server for static files.
+
+Usage:
+    {name} [serve] [OPTIONS]
+    {name} check-config [OPTIONS]
+    {name} hash-password <USER> [--iterations <N>]
+    {name} generate-token <NAME> [--scopes <A,B>] [--expires <DATE>]
+    {name} --help | --version
+
+Commands:
+    serve           Run the server (the default)
+    check-config    Validate the configuration and print the effective settings
+    hash-password   Read a password from stdin and print the credentials file line of USER
+                    (hashed with {iterations} PBKDF2 iterations unless --iterations is given)
+    generate-token  Print a new token on stderr and its token store line on stdout, with the
+                    given scopes and expiry (e.g. 2026-12-31 or 2026-12-31T18:00:00Z)
+
+Options:
+    -c, --config <FILE>    Configuration file (default: $CONFIG_FILE)
+        --host <HOST>      Host to listen on (default: $HOST, or the configuration file)
+    -p, --port <PORT>      Port to listen on (default: $PORT, or the configuration file)
+    -r, --root <DIR>       Directory of the files to serve (default: $PUBLIC_PATH, or the configuration file)
+    -w, --workers <N>      Number of worker threads (default: the configuration file, or one per CPU)
+    -h, --help             Print this help
+    -V, --version          Print the version
+
+Flags take precedence over environment variables, which take precedence over the configuration file.
+Other environment variables: CACHE_SIZE_MB, SPA_FALLBACK.
+",
+        iterations = DEFAULT_ITERATIONS
+    )
+}
+
+/// Parses the command-line arguments, without the program name.
+///
+/// # Returns
+///
+/// The `Command` to run, or a `CliError` describing the first invalid argument.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, CliError> {
+    let mut args = args.into_iter().peekable();
+    let check_config = match args.peek().map(String::as_str) {
+        Some("serve") => {
+            args.next();
+            false
+        }
+        Some("check-config") => {
+            args.next();
+            true
+        }
+        Some("hash-password") => {
+            args.next();
+            return parse_hash_password(args);
+        }
+        Some("generate-token") => {
+            args.next();
+            return parse_generate_token(args);
+        }
+        Some("help") => return Ok(Command::Help),
+        Some(arg) if !arg.starts_with('-') => {
+            return Err(CliError(format!("unknown command `{}`", arg)));
+        }
+        _ => false,
+    };
+
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        // both `--port 8080` and `--port=8080` are accepted
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+        if inline_value.is_some() && !flag_takes_value(&flag) {
+            return Err(CliError(format!("`{}` does not take a value", flag)));
+        }
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError(format!("`{}` needs a value", flag)))
+        };
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-c" | "--config" => options.config = Some(PathBuf::from(value()?)),
+            "--host" => options.host = Some(value()?),
+            "-p" | "--port" => {
+                let port = value()?;
+                options.port = Some(port.parse().map_err(|_| {
+                    CliError(format!(
+                        "invalid port `{}`: expected a number between 0 and 65535",
+                        port
+                    ))
+                })?);
+            }
+            "-r" | "--root" => options.root = Some(PathBuf::from(value()?)),
+            "-w" | "--workers" => {
+                let workers = value()?;
+                options.workers =
+                    Some(workers.parse().map_err(|_| {
+                        CliError(format!("invalid number of workers `{}`", workers))
+                    })?);
+            }
+            _ if flag.starts_with('-') => {
+                return Err(CliError(format!("unknown option `{}`", flag)));
+            }
+            _ => return Err(CliError(format!("unexpected argument `{}`", flag))),
+        }
+    }
+    Ok(if check_config {
+        Command::CheckConfig(options)
+    } else {
+        Command::Serve(options)
+    })
+}
+
+/// Parses the arguments of `hash-password`: the user name and an optional `--iterations`.
+fn parse_hash_password(args: impl Iterator<Item = String>) -> Result<Command, CliError> {
+    let mut user = None;
+    let mut iterations = DEFAULT_ITERATIONS;
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--iterations" => {
+                let value = inline_value
+                    .or_else(|| args.next())
+                    .ok_or_else(|| CliError("`--iterations` needs a value".to_string()))?;
+                iterations =
+                    value.parse().ok().filter(|n| *n > 0).ok_or_else(|| {
+                        CliError(format!("invalid number of iterations `{}`", value))
+                    })?;
+            }
+            _ if flag.starts_with('-') => {
+                return Err(CliError(format!("unknown option `{}`", flag)));
+            }
+            // Basic credentials are split at the first colon, so names cannot have one
+            _ if flag.contains(':') => {
+                return Err(CliError(format!(
+                    "invalid user `{}`: it contains `:`",
+                    flag
+                )));
+            }
+            _ if user.is_none() => user = Some(flag),
+            _ => return Err(CliError(format!("unexpected argument `{}`", flag))),
+        }
+    }
+    match user {
+        Some(user) => Ok(Command::HashPassword { user, iterations }),
+        None => Err(CliError("`hash-password` needs a user name".to_string())),
+    }
+}
+
+/// Parses the arguments of `generate-token`: the token name, and optional `--scopes` and `--expires`.
+fn parse_generate_token(args: impl Iterator<Item = String>) -> Result<Command, CliError> {
+    let mut name = None;
+    let mut scopes = Vec::new();
+    let mut expires = None;
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError(format!("`{}` needs a value", flag)))
+        };
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--scopes" => {
+                let value = value()?;
+                scopes = value
+                    .split(',')
+                    .filter(|scope| !scope.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                if scopes
+                    .iter()
+                    .any(|scope| scope.contains(char::is_whitespace))
+                {
+                    return Err(CliError(format!("invalid scopes `{}`", value)));
+                }
+            }
+            "--expires" => {
+                let value = value()?;
+                if parse_rfc3339(&value).is_none() {
+                    return Err(CliError(format!(
+                        "invalid date `{}`: expected e.g. 2026-12-31 or 2026-12-31T18:00:00Z",
+                        value
+                    )));
+                }
+                expires = Some(value);
+            }
+            _ if flag.starts_with('-') => {
+                return Err(CliError(format!("unknown option `{}`", flag)));
+            }
+            _ if name.is_none() && !flag.starts_with('#') => name = Some(flag),
+            _ => return Err(CliError(format!("unexpected argument `{}`", flag))),
+        }
+    }
+    match name {
+        Some(name) => Ok(Command::GenerateToken {
+            name,
+            scopes,
+            expires,
+        }),
+        None => Err(CliError("`generate-token` needs a name".to_string())),
+    }
+}
+
+fn flag_takes_value(flag: &str) -> bool {
+    !matches!(flag, "-h" | "--help" | "-V" | "--version")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, CliError> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_serve_is_the_default_command() {
+        assert_eq!(parse(&[]), Ok(Command::Serve(Options::default())));
+        let expected = Options {
+            host: Some("0.0.0.0".to_string()),
+            port: Some(8000),
+            workers: Some(4),
+            ..Options::default()
+        };
+        assert_eq!(
+            parse(&["--host", "0.0.0.0", "--port=8000", "-w", "4"]),
+            Ok(Command::Serve(expected.clone()))
+        );
+        assert_eq!(
+            parse(&["serve", "--host=0.0.0.0", "-p", "8000", "--workers", "4"]),
+            Ok(Command::Serve(expected))
+        );
+    }
+
+    #[test]
+    fn test_check_config_and_informational_flags() {
+        let expected = Options {
+            config: Some(PathBuf::from("server.toml")),
+            root: Some(PathBuf::from("dist")),
+            ..Options::default()
+        };
+        assert_eq!(
+            parse(&["check-config", "-c", "server.toml", "--root", "dist"]),
+            Ok(Command::CheckConfig(expected))
+        );
+        assert_eq!(parse(&["--help"]), Ok(Command::Help));
+        assert_eq!(parse(&["serve", "-V"]), Ok(Command::Version));
+        assert_eq!(parse(&["help"]), Ok(Command::Help));
+    }
+
+    #[test]
+    fn test_hash_password() {
+        assert_eq!(
+            parse(&["hash-password", "alice"]),
+            Ok(Command::HashPassword {
+                user: "alice".to_string(),
+                iterations: DEFAULT_ITERATIONS
+            })
+        );
+        assert_eq!(
+            parse(&["hash-password", "--iterations=1000", "bob"]),
+            Ok(Command::HashPassword {
+                user: "bob".to_string(),
+                iterations: 1000
+            })
+        );
+        assert_eq!(
+            parse(&["hash-password"]),
+            Err(CliError("`hash-password` needs a user name".to_string()))
+        );
+        assert_eq!(
+            parse(&["hash-password", "a:b"]),
+            Err(CliError("invalid user `a:b`: it contains `:`".to_string()))
+        );
+        assert_eq!(
+            parse(&["hash-password", "alice", "--iterations", "0"]),
+            Err(CliError("invalid number of iterations `0`".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_generate_token() {
+        assert_eq!(
+            parse(&[
+                "generate-token",
+                "ci",
+                "--scopes",
+                "read,deploy",
+                "--expires=2026-12-31"
+            ]),
+            Ok(Command::GenerateToken {
+                name: "ci".to_string(),
+                scopes: vec!["read".to_string(), "deploy".to_string()],
+                expires: Some("2026-12-31".to_string())
+            })
+        );
+        assert_eq!(
+            parse(&["generate-token", "ci", "--expires", "next week"]),
+            Err(CliError(
+                "invalid date `next week`: expected e.g. 2026-12-31 or 2026-12-31T18:00:00Z"
+                    .to_string()
+            ))
+        );
+        assert_eq!(
+            parse(&["generate-token", "--scopes=read"]),
+            Err(CliError("`generate-token` needs a name".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        assert_eq!(
+            parse(&["start"]),
+            Err(CliError("unknown command `start`".to_string()))
+        );
+        assert_eq!(
+            parse(&["--verbose"]),
+            Err(CliError("unknown option `--verbose`".to_string()))
+        );
+        assert_eq!(
+            parse(&["--port"]),
+            Err(CliError("`--port` needs a value".to_string()))
+        );
+        assert_eq!(
+            parse(&["--port", "http"]),
+            Err(CliError(
+                "invalid port `http`: expected a number between 0 and 65535".to_string()
+            ))
+        );
+        assert_eq!(
+            parse(&["--help=yes"]),
+            Err(CliError("`--help` does not take a value".to_string()))
+        );
+        assert_eq!(
+            parse(&["serve", "extra"]),
+            Err(CliError("unexpected argument `extra`".to_string()))
+        );
+    }
+}
@@ -0,0 +1,27 @@
+// The gzip (RFC 1952) and zlib (RFC 1950) containers around DEFLATE data.
+//
+// This file only depends on the standard library and its sibling modules, so the build script
+// can include it as well.
+
+use super::checksum::{adler32, crc32};
+use super::deflate::deflate;
+
+/// Compresses `data` into the gzip format (RFC 1952).
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    // magic number, compression method (8 = deflate), no flags, no modification time,
+    // no extra flags, and an "unknown" operating system, so the output is reproducible
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes()); // the length modulo 2^32
+    out
+}
+
+/// Compresses `data` into the zlib format (RFC 1950).
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+    // deflate with a 32 KiB window (0x78), default compression level, and a valid header checksum
+    let mut out = vec![0x78, 0x9c];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
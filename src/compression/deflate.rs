@@ -0,0 +1,849 @@
+// A DEFLATE (RFC 1951) encoder: LZ77 matching over a 32 KiB window with hash chains,
+// followed by Huffman coding. Every block is emitted with whichever of the dynamic Huffman,
+// fixed Huffman or stored encodings turns out smallest, so incompressible data never grows
+// by more than a few bytes per 64 KiB.
+//
+// This file only depends on the standard library, so the build script can include it as well.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+const WINDOW_SIZE: usize = 32 * 1024; // the largest distance a match may reach back
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+const MAX_CHAIN: usize = 128; // how many earlier positions are tried per match search
+const GOOD_MATCH: usize = 32; // matches at least this long are not improved by lazy matching
+const BLOCK_TOKENS: usize = 16 * 1024; // tokens per block, after which a new Huffman table is built
+const MAX_STORED: usize = 65_535; // the largest payload of a single stored block
+const NONE: u32 = u32::MAX;
+
+const END_OF_BLOCK: usize = 256;
+const LITERAL_CODES: usize = 286;
+const DISTANCE_CODES: usize = 30;
+const CODE_LENGTH_CODES: usize = 19;
+
+// Base values and extra bits of the length codes 257..=285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+// Base values and extra bits of the distance codes 0..=29
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// The order in which code length code lengths are written in a dynamic block header
+const CODE_LENGTH_ORDER: [usize; CODE_LENGTH_CODES] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Compresses `data` into a raw DEFLATE stream, without any zlib or gzip framing.
+///
+/// # Arguments
+///
+/// * `data` - The bytes to compress.
+///
+/// # Returns
+///
+/// The compressed stream, ending with a final block.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let tokens = Matcher::new(data).tokens();
+    let mut writer = BitWriter::default();
+    let mut position = 0; // offset in `data` of the first byte covered by the current block
+    let mut chunks = tokens.chunks(BLOCK_TOKENS).peekable();
+    if chunks.peek().is_none() {
+        write_block(&mut writer, &[], &[], true); // empty input still needs a final block
+    }
+    while let Some(chunk) = chunks.next() {
+        let covered: usize = chunk.iter().map(Token::len).sum();
+        let last = chunks.peek().is_none();
+        write_block(
+            &mut writer,
+            chunk,
+            &data[position..position + covered],
+            last,
+        );
+        position += covered;
+    }
+    writer.finish()
+}
+
+/// A single LZ77 output symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+impl Token {
+    /// Returns how many bytes of the input the token stands for.
+    fn len(&self) -> usize {
+        match self {
+            Token::Literal(_) => 1,
+            Token::Match { length, .. } => *length as usize,
+        }
+    }
+}
+
+/// Finds repeated byte sequences using hash chains: `head` holds the most recent position of
+/// every 3-byte hash and `prev` links each position to the previous one with the same hash.
+struct Matcher<'a> {
+    data: &'a [u8],
+    head: Vec<u32>,
+    prev: Vec<u32>,
+    inserted: usize, // positions below this offset are already in the hash chains
+}
+
+impl<'a> Matcher<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            head: vec![NONE; 1 << HASH_BITS],
+            prev: vec![NONE; WINDOW_SIZE],
+            inserted: 0,
+        }
+    }
+
+    fn hash(&self, position: usize) -> usize {
+        let bytes = &self.data[position..position + MIN_MATCH];
+        let value = (u32::from(bytes[0]) << 16) | (u32::from(bytes[1]) << 8) | u32::from(bytes[2]);
+        // multiplicative hashing spreads similar prefixes across the table
+        (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    /// Adds every position below `end` to the hash chains.
+    fn insert_until(&mut self, end: usize) {
+        let last = self.data.len().saturating_sub(MIN_MATCH - 1); // positions past this cannot start a match
+        while self.inserted < end.min(last) {
+            let hash = self.hash(self.inserted);
+            self.prev[self.inserted % WINDOW_SIZE] = self.head[hash];
+            self.head[hash] = self.inserted as u32;
+            self.inserted += 1;
+        }
+        self.inserted = self.inserted.max(end);
+    }
+
+    /// Returns the (length, distance) of the longest earlier match for the bytes at `position`,
+    /// or a length of 0 if there is no match of at least `MIN_MATCH` bytes.
+    fn longest_match(&mut self, position: usize) -> (usize, usize) {
+        self.insert_until(position);
+        let max_length = MAX_MATCH.min(self.data.len() - position);
+        if max_length < MIN_MATCH {
+            return (0, 0);
+        }
+        let (mut best_length, mut best_distance) = (MIN_MATCH - 1, 0);
+        let mut candidate = self.head[self.hash(position)];
+        let mut chain = MAX_CHAIN;
+        while candidate != NONE && chain > 0 {
+            let start = candidate as usize;
+            let distance = position - start;
+            if distance > WINDOW_SIZE {
+                break;
+            }
+            // only compare the whole sequence if it could beat the best match so far
+            if self.data[start + best_length] == self.data[position + best_length] {
+                let length = self.data[start..start + max_length]
+                    .iter()
+                    .zip(&self.data[position..position + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    (best_length, best_distance) = (length, distance);
+                    if length == max_length {
+                        break;
+                    }
+                }
+            }
+            candidate = self.prev[start % WINDOW_SIZE];
+            chain -= 1;
+        }
+        if best_distance == 0 {
+            (0, 0)
+        } else {
+            (best_length, best_distance)
+        }
+    }
+
+    /// Turns the input into literals and matches, using lazy matching: a match is deferred by one
+    /// byte whenever the next position has a longer match.
+    fn tokens(mut self) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut position = 0;
+        let mut pending = self.longest_match(0);
+        while position < self.data.len() {
+            let (length, distance) = pending;
+            if length < MIN_MATCH {
+                tokens.push(Token::Literal(self.data[position]));
+                position += 1;
+                pending = self.longest_match(position);
+                continue;
+            }
+            let next = if length < GOOD_MATCH && position + 1 < self.data.len() {
+                self.longest_match(position + 1)
+            } else {
+                (0, 0)
+            };
+            if next.0 > length {
+                tokens.push(Token::Literal(self.data[position]));
+                position += 1;
+                pending = next;
+            } else {
+                tokens.push(Token::Match {
+                    length: length as u16,
+                    distance: distance as u16,
+                });
+                position += length;
+                pending = self.longest_match(position);
+            }
+        }
+        tokens
+    }
+}
+
+/// Returns the index (into `LENGTH_BASE`) of the length code for a match length.
+fn length_code(length: usize) -> usize {
+    if length == MAX_MATCH {
+        return 28; // 258 has a dedicated code, even though 227 + 31 would also reach it
+    }
+    LENGTH_BASE[..28].partition_point(|&base| base as usize <= length) - 1
+}
+
+/// Returns the distance code for a match distance.
+fn distance_code(distance: usize) -> usize {
+    DISTANCE_BASE.partition_point(|&base| base as usize <= distance) - 1
+}
+
+/// Writes bits least significant bit first, as DEFLATE requires.
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.buffer |= u64::from(value) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Pads the output with zero bits up to the next byte boundary.
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.write_bits(0, 8 - self.count);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.align();
+        self.out
+    }
+}
+
+/// Computes Huffman code lengths for the given symbol frequencies, limited to `max_length` bits.
+///
+/// # Note
+///
+/// Unused symbols get a length of 0. If fewer than two symbols are used, two codes of length 1
+/// are produced anyway, because a single-code tree is not complete and some decoders reject it.
+fn huffman_lengths(frequencies: &[u32], max_length: usize) -> Vec<u8> {
+    let mut lengths = vec![0u8; frequencies.len()];
+    let mut symbols: Vec<usize> = (0..frequencies.len())
+        .filter(|&symbol| frequencies[symbol] > 0)
+        .collect();
+    if symbols.len() < 2 {
+        let used = symbols.first().copied().unwrap_or(0);
+        lengths[used] = 1;
+        lengths[if used == 0 { 1 } else { 0 }] = 1;
+        return lengths;
+    }
+
+    // Build the Huffman tree: leaves are 0..n, internal nodes are appended after them,
+    // so a parent always has a larger index than its children
+    let leaves = symbols.len();
+    let mut parent = vec![0usize; 2 * leaves - 1];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = symbols
+        .iter()
+        .enumerate()
+        .map(|(node, &symbol)| Reverse((u64::from(frequencies[symbol]), node)))
+        .collect();
+    let mut next = leaves;
+    while let (Some(Reverse((a_weight, a))), Some(Reverse((b_weight, b)))) =
+        (heap.pop(), heap.pop())
+    {
+        parent[a] = next;
+        parent[b] = next;
+        heap.push(Reverse((a_weight + b_weight, next)));
+        next += 1;
+    }
+    let root = next - 1;
+    let mut depth = vec![0usize; 2 * leaves - 1];
+    for node in (0..root).rev() {
+        depth[node] = depth[parent[node]] + 1;
+    }
+
+    // Count leaves per depth, clamping overly long codes, then restore the Kraft equality
+    // by repeatedly pushing a shorter leaf one level down (the approach used by miniz)
+    let mut per_length = vec![0u32; max_length + 1];
+    for &d in &depth[..leaves] {
+        per_length[d.min(max_length)] += 1;
+    }
+    let mut total: u32 = (1..=max_length)
+        .map(|length| per_length[length] << (max_length - length))
+        .sum();
+    while total > 1 << max_length {
+        per_length[max_length] -= 1;
+        if let Some(length) = (1..max_length).rev().find(|&length| per_length[length] > 0) {
+            per_length[length] -= 1;
+            per_length[length + 1] += 2;
+        }
+        total -= 1;
+    }
+
+    // The most frequent symbols get the shortest codes
+    symbols.sort_by_key(|&symbol| Reverse(frequencies[symbol]));
+    let mut symbols = symbols.into_iter();
+    for (length, &count) in per_length.iter().enumerate().skip(1) {
+        for symbol in symbols.by_ref().take(count as usize) {
+            lengths[symbol] = length as u8;
+        }
+    }
+    lengths
+}
+
+/// Computes the canonical Huffman codes for the given code lengths (RFC 1951, section 3.2.2).
+/// The codes are returned bit-reversed, ready to be written least significant bit first.
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut per_length = [0u16; 16];
+    for &length in lengths {
+        per_length[length as usize] += 1;
+    }
+    per_length[0] = 0;
+    let mut next_code = [0u16; 16];
+    let mut code = 0u16;
+    for length in 1..16 {
+        code = (code + per_length[length - 1]) << 1;
+        next_code[length] = code;
+    }
+    lengths
+        .iter()
+        .map(|&length| {
+            if length == 0 {
+                return 0;
+            }
+            let code = next_code[length as usize];
+            next_code[length as usize] += 1;
+            code.reverse_bits() >> (16 - length)
+        })
+        .collect()
+}
+
+/// The Huffman codes used to write the tokens of a block.
+struct Codes {
+    literal_lengths: Vec<u8>,
+    literal_codes: Vec<u16>,
+    distance_lengths: Vec<u8>,
+    distance_codes: Vec<u16>,
+}
+
+impl Codes {
+    fn new(literal_lengths: Vec<u8>, distance_lengths: Vec<u8>) -> Self {
+        Self {
+            literal_codes: canonical_codes(&literal_lengths),
+            distance_codes: canonical_codes(&distance_lengths),
+            literal_lengths,
+            distance_lengths,
+        }
+    }
+
+    /// The fixed Huffman codes defined in RFC 1951, section 3.2.6.
+    fn fixed() -> Self {
+        let literal_lengths = (0..288)
+            .map(|symbol| match symbol {
+                0..=143 => 8,
+                144..=255 => 9,
+                256..=279 => 7,
+                _ => 8,
+            })
+            .collect();
+        Self::new(literal_lengths, vec![5; 32])
+    }
+
+    /// Returns the number of bits needed to write the tokens (plus the end-of-block code).
+    fn cost(&self, literal_frequencies: &[u32], distance_frequencies: &[u32]) -> u64 {
+        let literals: u64 = literal_frequencies
+            .iter()
+            .zip(&self.literal_lengths)
+            .map(|(&frequency, &length)| u64::from(frequency) * u64::from(length))
+            .sum();
+        let distances: u64 = distance_frequencies
+            .iter()
+            .zip(&self.distance_lengths)
+            .map(|(&frequency, &length)| u64::from(frequency) * u64::from(length))
+            .sum();
+        literals + distances
+    }
+
+    fn write_tokens(&self, writer: &mut BitWriter, tokens: &[Token]) {
+        for token in tokens {
+            match *token {
+                Token::Literal(byte) => self.write_literal(writer, byte as usize),
+                Token::Match { length, distance } => {
+                    let (length, distance) = (length as usize, distance as usize);
+                    let code = length_code(length);
+                    self.write_literal(writer, 257 + code);
+                    writer.write_bits(
+                        (length - LENGTH_BASE[code] as usize) as u32,
+                        u32::from(LENGTH_EXTRA[code]),
+                    );
+                    let code = distance_code(distance);
+                    writer.write_bits(
+                        u32::from(self.distance_codes[code]),
+                        u32::from(self.distance_lengths[code]),
+                    );
+                    writer.write_bits(
+                        (distance - DISTANCE_BASE[code] as usize) as u32,
+                        u32::from(DISTANCE_EXTRA[code]),
+                    );
+                }
+            }
+        }
+        self.write_literal(writer, END_OF_BLOCK);
+    }
+
+    fn write_literal(&self, writer: &mut BitWriter, symbol: usize) {
+        writer.write_bits(
+            u32::from(self.literal_codes[symbol]),
+            u32::from(self.literal_lengths[symbol]),
+        );
+    }
+}
+
+/// Run-length encodes a sequence of code lengths with the repeat codes 16, 17 and 18.
+///
+/// # Returns
+///
+/// The (symbol, extra bits value) pairs to write.
+fn encode_code_lengths(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut encoded = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let length = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&l| l == length).count();
+        let mut remaining = run;
+        if length == 0 {
+            while remaining >= 11 {
+                let repeat = remaining.min(138);
+                encoded.push((18, (repeat - 11) as u8));
+                remaining -= repeat;
+            }
+            if remaining >= 3 {
+                encoded.push((17, (remaining - 3) as u8));
+                remaining = 0;
+            }
+        } else {
+            encoded.push((length, 0));
+            remaining -= 1;
+            while remaining >= 3 {
+                let repeat = remaining.min(6);
+                encoded.push((16, (repeat - 3) as u8));
+                remaining -= repeat;
+            }
+        }
+        encoded.extend(std::iter::repeat_n((length, 0), remaining));
+        i += run;
+    }
+    encoded
+}
+
+/// The number of extra bits following each code length symbol.
+fn code_length_extra_bits(symbol: u8) -> u32 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+/// Writes one block, choosing the smallest of the stored, fixed and dynamic encodings.
+///
+/// # Arguments
+///
+/// * `writer` - The output.
+/// * `tokens` - The tokens of the block.
+/// * `raw` - The input bytes the tokens stand for, used if the block is stored.
+/// * `last` - Whether this is the final block of the stream.
+fn write_block(writer: &mut BitWriter, tokens: &[Token], raw: &[u8], last: bool) {
+    let mut literal_frequencies = vec![0u32; LITERAL_CODES];
+    let mut distance_frequencies = vec![0u32; DISTANCE_CODES];
+    let mut extra_bits = 0u64;
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => literal_frequencies[byte as usize] += 1,
+            Token::Match { length, distance } => {
+                let length_index = length_code(length as usize);
+                let distance_index = distance_code(distance as usize);
+                literal_frequencies[257 + length_index] += 1;
+                distance_frequencies[distance_index] += 1;
+                extra_bits +=
+                    u64::from(LENGTH_EXTRA[length_index] + DISTANCE_EXTRA[distance_index]);
+            }
+        }
+    }
+    literal_frequencies[END_OF_BLOCK] = 1;
+
+    // Dynamic Huffman codes and the header describing them
+    let dynamic = Codes::new(
+        huffman_lengths(&literal_frequencies, 15),
+        huffman_lengths(&distance_frequencies, 15),
+    );
+    let literal_count = 257.max(
+        dynamic
+            .literal_lengths
+            .iter()
+            .rposition(|&l| l > 0)
+            .unwrap_or(0)
+            + 1,
+    );
+    let distance_count = 1.max(
+        dynamic
+            .distance_lengths
+            .iter()
+            .rposition(|&l| l > 0)
+            .unwrap_or(0)
+            + 1,
+    );
+    let all_lengths: Vec<u8> = dynamic.literal_lengths[..literal_count]
+        .iter()
+        .chain(&dynamic.distance_lengths[..distance_count])
+        .copied()
+        .collect();
+    let encoded_lengths = encode_code_lengths(&all_lengths);
+    let mut code_length_frequencies = vec![0u32; CODE_LENGTH_CODES];
+    for &(symbol, _) in &encoded_lengths {
+        code_length_frequencies[symbol as usize] += 1;
+    }
+    let code_length_lengths = huffman_lengths(&code_length_frequencies, 7);
+    let code_length_codes = canonical_codes(&code_length_lengths);
+    let code_length_count = 4.max(
+        CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|&symbol| code_length_lengths[symbol] > 0)
+            .unwrap_or(0)
+            + 1,
+    );
+    let header_bits: u64 = 14
+        + 3 * code_length_count as u64
+        + encoded_lengths
+            .iter()
+            .map(|&(symbol, _)| {
+                u64::from(code_length_lengths[symbol as usize])
+                    + u64::from(code_length_extra_bits(symbol))
+            })
+            .sum::<u64>();
+
+    let fixed = Codes::fixed();
+    let dynamic_bits = header_bits + dynamic.cost(&literal_frequencies, &distance_frequencies);
+    let fixed_bits = fixed.cost(&literal_frequencies, &distance_frequencies);
+    // every stored block needs its 3 header bits, up to 7 padding bits and 4 bytes of lengths
+    let stored_blocks = raw.len().div_ceil(MAX_STORED).max(1) as u64;
+    let stored_bits = stored_blocks * (3 + 7 + 32) + 8 * raw.len() as u64;
+
+    if stored_bits <= dynamic_bits.min(fixed_bits) + extra_bits {
+        write_stored(writer, raw, last);
+    } else if fixed_bits <= dynamic_bits {
+        writer.write_bits(u32::from(last), 1);
+        writer.write_bits(1, 2);
+        fixed.write_tokens(writer, tokens);
+    } else {
+        writer.write_bits(u32::from(last), 1);
+        writer.write_bits(2, 2);
+        writer.write_bits((literal_count - 257) as u32, 5);
+        writer.write_bits((distance_count - 1) as u32, 5);
+        writer.write_bits((code_length_count - 4) as u32, 4);
+        for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+            writer.write_bits(u32::from(code_length_lengths[symbol]), 3);
+        }
+        for &(symbol, extra) in &encoded_lengths {
+            writer.write_bits(
+                u32::from(code_length_codes[symbol as usize]),
+                u32::from(code_length_lengths[symbol as usize]),
+            );
+            writer.write_bits(u32::from(extra), code_length_extra_bits(symbol));
+        }
+        dynamic.write_tokens(writer, tokens);
+    }
+}
+
+/// Writes `raw` as one or more stored (uncompressed) blocks.
+fn write_stored(writer: &mut BitWriter, raw: &[u8], last: bool) {
+    if raw.is_empty() {
+        write_stored_chunk(writer, raw, last);
+        return;
+    }
+    let mut chunks = raw.chunks(MAX_STORED).peekable();
+    while let Some(chunk) = chunks.next() {
+        write_stored_chunk(writer, chunk, last && chunks.peek().is_none());
+    }
+}
+
+fn write_stored_chunk(writer: &mut BitWriter, chunk: &[u8], last: bool) {
+    writer.write_bits(u32::from(last), 1);
+    writer.write_bits(0, 2);
+    writer.align();
+    let len = chunk.len() as u16;
+    writer.out.extend_from_slice(&len.to_le_bytes());
+    writer.out.extend_from_slice(&(!len).to_le_bytes());
+    writer.out.extend_from_slice(chunk);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small, slow DEFLATE decoder (modelled on zlib's puff.c), used to check the encoder output.
+    struct Inflater<'a> {
+        data: &'a [u8],
+        position: usize, // in bits
+    }
+
+    /// Canonical Huffman decoding table: the number of codes per length and the symbols ordered by code.
+    struct Table {
+        counts: [u16; 16],
+        symbols: Vec<u16>,
+    }
+
+    impl Table {
+        fn new(lengths: &[u8]) -> Self {
+            let mut counts = [0u16; 16];
+            for &length in lengths {
+                counts[length as usize] += 1;
+            }
+            counts[0] = 0;
+            let mut symbols: Vec<u16> = (0..lengths.len() as u16)
+                .filter(|&symbol| lengths[symbol as usize] > 0)
+                .collect();
+            symbols.sort_by_key(|&symbol| lengths[symbol as usize]);
+            Self { counts, symbols }
+        }
+    }
+
+    impl<'a> Inflater<'a> {
+        fn bits(&mut self, count: u32) -> u32 {
+            let mut value = 0;
+            for i in 0..count {
+                let byte = self.data[self.position / 8];
+                value |= u32::from((byte >> (self.position % 8)) & 1) << i;
+                self.position += 1;
+            }
+            value
+        }
+
+        fn decode(&mut self, table: &Table) -> u16 {
+            let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+            for length in 1..16 {
+                code |= self.bits(1) as i32;
+                let count = i32::from(table.counts[length]);
+                if code - count < first {
+                    return table.symbols[(index + code - first) as usize];
+                }
+                index += count;
+                first = (first + count) << 1;
+                code <<= 1;
+            }
+            panic!("invalid Huffman code");
+        }
+
+        fn inflate(mut self) -> Vec<u8> {
+            let mut out = Vec::new();
+            loop {
+                let last = self.bits(1) == 1;
+                match self.bits(2) {
+                    0 => {
+                        self.position = self.position.div_ceil(8) * 8;
+                        let start = self.position / 8;
+                        let len = u16::from_le_bytes([self.data[start], self.data[start + 1]]);
+                        let nlen = u16::from_le_bytes([self.data[start + 2], self.data[start + 3]]);
+                        assert_eq!(len, !nlen);
+                        out.extend_from_slice(&self.data[start + 4..start + 4 + len as usize]);
+                        self.position = (start + 4 + len as usize) * 8;
+                    }
+                    1 => {
+                        let fixed = Codes::fixed();
+                        let literals = Table::new(&fixed.literal_lengths);
+                        let distances = Table::new(&fixed.distance_lengths);
+                        self.inflate_block(&mut out, &literals, &distances);
+                    }
+                    2 => {
+                        let literal_count = self.bits(5) as usize + 257;
+                        let distance_count = self.bits(5) as usize + 1;
+                        let code_length_count = self.bits(4) as usize + 4;
+                        let mut code_lengths = [0u8; CODE_LENGTH_CODES];
+                        for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+                            code_lengths[symbol] = self.bits(3) as u8;
+                        }
+                        let code_length_table = Table::new(&code_lengths);
+                        let mut lengths = Vec::new();
+                        while lengths.len() < literal_count + distance_count {
+                            match self.decode(&code_length_table) {
+                                16 => {
+                                    let previous = *lengths.last().unwrap();
+                                    let repeat = 3 + self.bits(2) as usize;
+                                    lengths.extend(std::iter::repeat_n(previous, repeat));
+                                }
+                                17 => {
+                                    let repeat = 3 + self.bits(3) as usize;
+                                    lengths.extend(std::iter::repeat_n(0, repeat));
+                                }
+                                18 => {
+                                    let repeat = 11 + self.bits(7) as usize;
+                                    lengths.extend(std::iter::repeat_n(0, repeat));
+                                }
+                                length => lengths.push(length as u8),
+                            }
+                        }
+                        assert_eq!(lengths.len(), literal_count + distance_count);
+                        let literals = Table::new(&lengths[..literal_count]);
+                        let distances = Table::new(&lengths[literal_count..]);
+                        self.inflate_block(&mut out, &literals, &distances);
+                    }
+                    _ => panic!("invalid block type"),
+                }
+                if last {
+                    return out;
+                }
+            }
+        }
+
+        fn inflate_block(&mut self, out: &mut Vec<u8>, literals: &Table, distances: &Table) {
+            loop {
+                let symbol = self.decode(literals) as usize;
+                match symbol {
+                    0..=255 => out.push(symbol as u8),
+                    END_OF_BLOCK => return,
+                    _ => {
+                        let code = symbol - 257;
+                        let length = LENGTH_BASE[code] as usize
+                            + self.bits(u32::from(LENGTH_EXTRA[code])) as usize;
+                        let code = self.decode(distances) as usize;
+                        let distance = DISTANCE_BASE[code] as usize
+                            + self.bits(u32::from(DISTANCE_EXTRA[code])) as usize;
+                        assert!(distance <= out.len(), "distance too far back");
+                        for _ in 0..length {
+                            out.push(out[out.len() - distance]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn inflate(data: &[u8]) -> Vec<u8> {
+        Inflater { data, position: 0 }.inflate()
+    }
+
+    /// Deterministic pseudo-random bytes (xorshift), which do not compress.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn assert_round_trip(data: &[u8]) -> usize {
+        let compressed = deflate(data);
+        assert_eq!(inflate(&compressed), data);
+        compressed.len()
+    }
+
+    #[test]
+    fn test_empty_input() {
+        assert_eq!(deflate(b""), vec![0x03, 0x00]);
+        assert_round_trip(b"");
+    }
+
+    #[test]
+    fn test_short_inputs() {
+        assert_round_trip(b"a");
+        assert_round_trip(b"ab");
+        assert_round_trip(b"abcabcabc");
+        assert_round_trip("héllo wörld, héllo wörld".as_bytes());
+    }
+
+    #[test]
+    fn test_repetitive_text_compresses() {
+        let html = "<li><a href=\"/page\">A link to a page</a></li>\n".repeat(500);
+        let compressed = assert_round_trip(html.as_bytes());
+        assert!(compressed * 20 < html.len(), "{} bytes", compressed);
+    }
+
+    #[test]
+    fn test_long_runs() {
+        assert_round_trip(&[0u8; 100_000]);
+        let mut data = vec![7u8; 259];
+        data.extend_from_slice(&[8u8; 517]);
+        assert_round_trip(&data);
+    }
+
+    #[test]
+    fn test_incompressible_data_is_stored() {
+        let data = noise(200_000);
+        let compressed = assert_round_trip(&data);
+        // one stored block per BLOCK_TOKENS tokens, each adding 5 bytes of overhead
+        assert!(
+            compressed <= data.len() + data.len() / 1000,
+            "{} bytes",
+            compressed
+        );
+    }
+
+    #[test]
+    fn test_mixed_input_spanning_many_blocks() {
+        let mut data = Vec::new();
+        for i in 0..40 {
+            data.extend_from_slice(&noise(1000 + i));
+            data.extend_from_slice(
+                format!("line {} of some text\n", i % 7)
+                    .repeat(200)
+                    .as_bytes(),
+            );
+        }
+        assert_round_trip(&data);
+    }
+
+    #[test]
+    fn test_huffman_lengths_respect_limit() {
+        // Fibonacci frequencies produce the deepest possible Huffman tree
+        let mut frequencies = vec![1u32, 1];
+        while frequencies.len() < 30 {
+            let next = frequencies[frequencies.len() - 1] + frequencies[frequencies.len() - 2];
+            frequencies.push(next);
+        }
+        let lengths = huffman_lengths(&frequencies, 15);
+        assert!(lengths.iter().all(|&length| (1..=15).contains(&length)));
+        let kraft: f64 = lengths
+            .iter()
+            .map(|&length| 0.5f64.powi(length.into()))
+            .sum();
+        assert_eq!(kraft, 1.0);
+    }
+}
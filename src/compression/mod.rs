@@ -0,0 +1,151 @@
+pub use checksum::{adler32, crc32};
+pub use container::{gzip, zlib};
+pub use deflate::deflate;
+
+mod checksum;
+mod container;
+mod deflate;
+
+use crate::http::parse_quality_list;
+
+/// A content coding the server can apply to a response body (RFC 9110, section 8.4.1).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ContentEncoding {
+    Brotli, // Brotli (RFC 7932); only served from precompressed files, as there is no encoder in the crate
+    Gzip,   // DEFLATE data in the gzip container (RFC 1952)
+    Deflate, // DEFLATE data in the zlib container (RFC 1950), which is what HTTP calls "deflate"
+}
+
+impl ContentEncoding {
+    /// Returns the token used for this coding in `Accept-Encoding` and `Content-Encoding`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentEncoding::Brotli => "br",
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Deflate => "deflate",
+        }
+    }
+
+    /// Compresses `data` with this coding.
+    ///
+    /// # Returns
+    ///
+    /// The compressed bytes, or `None` if the crate cannot produce this coding (Brotli).
+    pub fn encode(&self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            ContentEncoding::Brotli => None,
+            ContentEncoding::Gzip => Some(gzip(data)),
+            ContentEncoding::Deflate => Some(zlib(data)),
+        }
+    }
+
+    /// Picks the coding to use for a response, based on the request's `Accept-Encoding` header.
+    ///
+    /// # Arguments
+    ///
+    /// * `accept_encoding` - The value of the `Accept-Encoding` header, if the request has one.
+    /// * `available` - The codings the server can produce, most preferred first.
+    ///
+    /// # Returns
+    ///
+    /// The available coding with the highest quality value (ties go to the earlier one in `available`),
+    /// or `None` if the response should be sent unencoded. A request without `Accept-Encoding`
+    /// is never sent an encoded response, and a coding with `q=0` is never used.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_server::compression::ContentEncoding;
+    ///
+    /// let available = [ContentEncoding::Gzip, ContentEncoding::Deflate];
+    /// let encoding = ContentEncoding::negotiate(Some("deflate, gzip;q=0.5"), &available);
+    /// assert_eq!(encoding, Some(ContentEncoding::Deflate));
+    /// ```
+    pub fn negotiate(
+        accept_encoding: Option<&str>,
+        available: &[ContentEncoding],
+    ) -> Option<ContentEncoding> {
+        let accepted = parse_quality_list(accept_encoding?);
+        let quality_of = |token: &str| {
+            accepted
+                .iter()
+                .find(|(coding, _)| coding.eq_ignore_ascii_case(token))
+                .map(|(_, quality)| *quality)
+        };
+        let wildcard = quality_of("*").unwrap_or(0); // "*" covers codings not listed explicitly
+
+        let mut best: Option<(ContentEncoding, u16)> = None;
+        for &encoding in available {
+            let quality = quality_of(encoding.as_str()).unwrap_or(wildcard);
+            if quality > 0 && best.is_none_or(|(_, best_quality)| quality > best_quality) {
+                best = Some((encoding, quality));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOTH: [ContentEncoding; 2] = [ContentEncoding::Gzip, ContentEncoding::Deflate];
+
+    #[test]
+    fn test_negotiate() {
+        use ContentEncoding::{Deflate, Gzip};
+        assert_eq!(ContentEncoding::negotiate(None, &BOTH), None);
+        assert_eq!(ContentEncoding::negotiate(Some(""), &BOTH), None);
+        assert_eq!(
+            ContentEncoding::negotiate(Some("gzip, deflate, br"), &BOTH),
+            Some(Gzip)
+        );
+        assert_eq!(
+            ContentEncoding::negotiate(Some("GZIP;q=0.5, deflate"), &BOTH),
+            Some(Deflate)
+        );
+        assert_eq!(ContentEncoding::negotiate(Some("*"), &BOTH), Some(Gzip));
+        assert_eq!(
+            ContentEncoding::negotiate(Some("gzip;q=0, *"), &BOTH),
+            Some(Deflate)
+        );
+        assert_eq!(
+            ContentEncoding::negotiate(Some("br, identity"), &BOTH),
+            None
+        );
+        assert_eq!(ContentEncoding::negotiate(Some("gzip"), &[Deflate]), None);
+    }
+
+    #[test]
+    fn test_negotiate_prefers_earlier_available_coding() {
+        use ContentEncoding::{Brotli, Deflate, Gzip};
+        let available = [Brotli, Gzip, Deflate];
+        assert_eq!(
+            ContentEncoding::negotiate(Some("gzip, deflate, br"), &available),
+            Some(Brotli)
+        );
+        assert_eq!(
+            ContentEncoding::negotiate(Some("gzip, br;q=0.9"), &available),
+            Some(Gzip)
+        );
+        assert_eq!(Brotli.encode(b"data"), None);
+    }
+
+    #[test]
+    fn test_gzip_framing() {
+        let data = b"hello hello hello hello";
+        let out = gzip(data);
+        assert_eq!(&out[..3], &[0x1f, 0x8b, 8]);
+        let trailer = &out[out.len() - 8..];
+        assert_eq!(trailer[..4], crc32(data).to_le_bytes());
+        assert_eq!(trailer[4..], (data.len() as u32).to_le_bytes());
+    }
+
+    #[test]
+    fn test_zlib_framing() {
+        let data = b"hello hello hello hello";
+        let out = zlib(data);
+        assert_eq!((u16::from(out[0]) << 8 | u16::from(out[1])) % 31, 0);
+        assert_eq!(out[out.len() - 4..], adler32(data).to_be_bytes());
+    }
+}
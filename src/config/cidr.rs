@@ -0,0 +1,138 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// A range of IP addresses, written in CIDR notation: `10.0.0.0/8`, `2001:db8::/32`,
+/// or a single address such as `192.0.2.7`.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::config::Cidr;
+///
+/// let private: Cidr = "10.0.0.0/8".parse().unwrap();
+/// assert!(private.contains("10.1.2.3".parse().unwrap()));
+/// assert!(!private.contains("192.0.2.7".parse().unwrap()));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr, // the first address of the range, with the host bits cleared
+    prefix: u8,   // number of leading bits shared by the addresses of the range
+}
+
+impl Cidr {
+    /// Returns true if the address is in the range. IPv4 addresses mapped to IPv6
+    /// (`::ffff:a.b.c.d`) are compared as IPv4 addresses.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr.to_canonical()) {
+            (IpAddr::V4(range), IpAddr::V4(addr)) => {
+                mask(u32::from(addr).into(), 32, self.prefix) == u32::from(range).into()
+            }
+            (IpAddr::V6(range), IpAddr::V6(addr)) => {
+                mask(u128::from(addr), 128, self.prefix) == u128::from(range)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Clears the bits of an address after the first `prefix` ones, out of `bits`.
+fn mask(addr: u128, bits: u8, prefix: u8) -> u128 {
+    let host = u32::from(bits - prefix);
+    // a /0 range of IPv6 addresses clears all 128 bits, more than a shift can
+    addr.checked_shr(host)
+        .and_then(|network| network.checked_shl(host))
+        .unwrap_or(0)
+}
+
+impl FromStr for Cidr {
+    type Err = CidrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| CidrError::InvalidAddress)?;
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= bits)
+                .ok_or(CidrError::InvalidPrefix(bits))?,
+            None => bits,
+        };
+        let addr = match addr {
+            IpAddr::V4(v4) => IpAddr::V4((mask(u32::from(v4).into(), 32, prefix) as u32).into()),
+            IpAddr::V6(v6) => IpAddr::V6(mask(u128::from(v6), 128, prefix).into()),
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl Display for Cidr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// A range of IP addresses that cannot be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CidrError {
+    InvalidAddress,    // the part before the slash is not an IPv4 or IPv6 address
+    InvalidPrefix(u8), // the prefix length is not a number up to the given number of bits
+}
+
+impl Display for CidrError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            CidrError::InvalidAddress => {
+                write!(f, "expected an IP address or a range such as 10.0.0.0/8")
+            }
+            CidrError::InvalidPrefix(bits) => {
+                write!(f, "the prefix length must be between 0 and {}", bits)
+            }
+        }
+    }
+}
+
+impl Error for CidrError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cidr_contains() {
+        let range: Cidr = "192.168.1.77/24".parse().unwrap();
+        assert_eq!(range.to_string(), "192.168.1.0/24");
+        assert!(range.contains("192.168.1.1".parse().unwrap()));
+        assert!(range.contains("::ffff:192.168.1.200".parse().unwrap()));
+        assert!(!range.contains("192.168.2.1".parse().unwrap()));
+
+        let range: Cidr = "2001:db8::/32".parse().unwrap();
+        assert!(range.contains("2001:db8:1::5".parse().unwrap()));
+        assert!(!range.contains("2001:db9::5".parse().unwrap()));
+        assert!(!range.contains("10.0.0.1".parse().unwrap()));
+
+        let everything: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(everything.contains("203.0.113.9".parse().unwrap()));
+        let everything: Cidr = "::/0".parse().unwrap();
+        assert!(everything.contains("2001:db8::1".parse().unwrap()));
+        let single: Cidr = "::1".parse().unwrap();
+        assert_eq!(single.to_string(), "::1/128");
+    }
+
+    #[test]
+    fn test_invalid_cidr() {
+        assert_eq!("10.0.0/8".parse::<Cidr>(), Err(CidrError::InvalidAddress));
+        assert_eq!(
+            "10.0.0.0/33".parse::<Cidr>(),
+            Err(CidrError::InvalidPrefix(32))
+        );
+        assert_eq!("::/129".parse::<Cidr>(), Err(CidrError::InvalidPrefix(128)));
+        assert_eq!("::/".parse::<Cidr>(), Err(CidrError::InvalidPrefix(128)));
+    }
+}
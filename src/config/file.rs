@@ -0,0 +1,1706 @@
+use super::toml::{self, Item, Position, SourceError, Table, Value};
+use super::{Cidr, CidrError, ConfigError, ListenerConfig, LogLevel, Overload, ServerConfig};
+use crate::access::{AccessList, Action};
+use crate::json;
+use crate::rate_limit::{Algorithm, RateLimit, RateLimitKey};
+use std::env;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::io::Error as IoError;
+use std::path::{self, Path, PathBuf};
+use std::time::Duration;
+
+/// Settings of a static file handler, serving the whole site or one route.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiteConfig {
+    pub root: Option<PathBuf>, // directory of the files, or None for the default
+    pub cache_size: Option<u64>, // memory budget of the file cache, in bytes, or None for no cache
+    pub spa_fallback: Option<String>, // document served for client-side routes, if enabled
+    pub compression: bool,     // whether text files are compressed on the fly
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        Self {
+            root: None,
+            cache_size: None,
+            spa_fallback: None,
+            compression: true,
+        }
+    }
+}
+
+/// Requests whose path starts with a prefix, served from their own directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteConfig {
+    pub prefix: String, // e.g. "/docs", which serves "/docs/index.html" as "index.html" of the route's root
+    pub site: SiteConfig,
+    pub headers: Vec<(String, String)>, // added to the responses of the route, after the global headers
+    pub rate_limit: Option<RateLimit>, // applied to the requests of the route, after the global one
+}
+
+/// Requests whose path starts with a prefix, reserved to the client networks of an access list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessConfig {
+    pub prefix: String, // without a trailing slash, empty for the whole site
+    pub list: AccessList,
+}
+
+/// Requests whose path starts with a prefix, reserved to the users of a credentials file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicAuthConfig {
+    pub prefix: String,       // without a trailing slash, empty for the whole site
+    pub realm: String,        // the name browsers show when they ask for a password
+    pub credentials: PathBuf, // the `user:hash` file, see `auth::Credentials`
+}
+
+/// Requests whose path starts with a prefix, reserved to the tokens of a token store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenAuthConfig {
+    pub prefix: String,         // without a trailing slash, empty for the whole site
+    pub realm: String,          // named in the `WWW-Authenticate` challenges
+    pub tokens: PathBuf,        // the token store, see `auth::TokenStore`
+    pub scopes: Vec<String>,    // required of every token
+    pub header: Option<String>, // a header carrying the token, besides `Authorization: Bearer`
+    pub query: Option<String>,  // a query parameter carrying the token
+}
+
+/// Requests whose path starts with a prefix, reserved to the JWTs signed with one of some keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JwtAuthConfig {
+    pub prefix: String,           // without a trailing slash, empty for the whole site
+    pub realm: String,            // named in the `WWW-Authenticate` challenges
+    pub issuer: Option<String>,   // the required `iss` claim
+    pub audience: Option<String>, // required in the `aud` claim
+    pub leeway: Duration,         // tolerated clock skew when checking `exp` and `nbf`
+    pub keys: Vec<(String, PathBuf)>, // the files holding the keys, by key ID (`kid`), see `auth::load_jwt_key`
+}
+
+/// The contents of a configuration file.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::config::FileConfig;
+/// use std::path::Path;
+///
+/// let source = r#"
+/// [server]
+/// listen = ["127.0.0.1:${PORT:-8080}"]
+/// workers = 4
+///
+/// [limits]
+/// read_timeout = "10s"
+/// max_header_size = "8KiB"
+///
+/// [site]
+/// root = "public"
+///
+/// [headers]
+/// X-Content-Type-Options = "nosniff"
+///
+/// [rate_limit]
+/// limit = 100
+/// window = "1m"
+///
+/// [[listener]]
+/// name = "nginx"
+/// address = "unix:/run/rust_server.sock"
+/// mode = "660"
+///
+/// [[route]]
+/// prefix = "/docs"
+/// root = "/srv/docs"
+/// headers = { Cache-Control = "max-age=3600" }
+/// "#;
+/// let config = FileConfig::parse(source, Path::new("/etc/rust_server"), |_| None).unwrap();
+/// assert_eq!(config.server.listeners[0].name, "127.0.0.1:8080");
+/// assert_eq!(config.server.listeners[1].name, "nginx");
+/// assert_eq!(config.server.listeners[1].mode, Some(0o660));
+/// assert_eq!(config.site.root.as_deref(), Some(Path::new("/etc/rust_server/public")));
+/// assert_eq!(config.routes[0].prefix, "/docs");
+/// assert_eq!(config.rate_limit.unwrap().limit, 100);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FileConfig {
+    pub server: ServerConfig, // the [server], [limits] and [[listener]] sections, over the defaults
+    pub site: SiteConfig,     // the [site] section
+    pub headers: Vec<(String, String)>, // the [headers] section, added to every response
+    pub routes: Vec<RouteConfig>, // the [[route]] sections, in the order they were written
+    pub rate_limit: Option<RateLimit>, // the [rate_limit] section, applied to every request
+    pub access: Vec<AccessConfig>, // the [[access]] sections, checked before everything else
+    pub basic_auth: Vec<BasicAuthConfig>, // the [[basic_auth]] sections, checked after the rate limit
+    pub token_auth: Vec<TokenAuthConfig>, // the [[token_auth]] sections, checked after the rate limit
+    pub jwt_auth: Vec<JwtAuthConfig>,     // the [[jwt_auth]] sections, checked after the rate limit
+}
+
+impl FileConfig {
+    /// Reads and parses a configuration file, interpolating the process's environment variables.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file. Relative paths in the file, such as roots and credentials files,
+    ///   are resolved against its directory, made absolute.
+    pub fn load(path: &Path) -> Result<Self, ConfigFileError> {
+        let source = fs::read_to_string(path).map_err(|error| ConfigFileError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        // relative paths in the file must keep their meaning if the process changes directory, e.g. when
+        // the file is reloaded, and the parent of a bare file name is empty
+        let absolute = path::absolute(path).map_err(|error| ConfigFileError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let dir = absolute.parent().unwrap_or(Path::new("/"));
+        Self::parse(&source, dir, |name| env::var(name).ok()).map_err(|error| {
+            ConfigFileError::Invalid {
+                path: path.to_path_buf(),
+                error,
+            }
+        })
+    }
+
+    /// Parses the contents of a configuration file.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The contents of the file.
+    /// * `dir` - The directory relative roots are resolved against.
+    /// * `env` - Looks up the environment variables referred to as `${NAME}` or `${NAME:-default}`.
+    ///
+    /// # Returns
+    ///
+    /// The configuration, or the first error with its position: syntax errors, unknown keys,
+    /// values of the wrong type, and settings rejected by `ServerConfig::validate`.
+    pub fn parse(
+        source: &str,
+        dir: &Path,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, SourceError> {
+        let root = toml::parse(source, env)?;
+        let start = Position { line: 1, column: 1 };
+        let document = Section::new(
+            "the file",
+            &root,
+            start,
+            &[
+                "server",
+                "listener",
+                "limits",
+                "site",
+                "headers",
+                "rate_limit",
+                "access",
+                "basic_auth",
+                "token_auth",
+                "jwt_auth",
+                "route",
+            ],
+        )?;
+        let mut config = FileConfig::default();
+        let mut positions = Vec::new(); // where each server setting was set, to report validation errors
+
+        if let Some(server) = document.table("server")? {
+            let server = Section::new(
+                "[server]",
+                server.0,
+                server.1,
+                &["listen", "workers", "log_level", "trusted_proxies"],
+            )?;
+            if let Some(item) = server.get("listen") {
+                config.server.listeners = string_list(item)?
+                    .iter()
+                    .map(|item| checked_listener(ListenerConfig::new(string(item)?), item))
+                    .collect::<Result<_, _>>()?;
+                positions.push(("listeners", item.position));
+            }
+            if let Some((workers, position)) = server.count("workers")? {
+                config.server.workers = workers;
+                positions.push(("workers", position));
+            }
+            if let Some(item) = server.get("log_level") {
+                config.server.log_level = match string(item)? {
+                    "off" => LogLevel::Off,
+                    "access" => LogLevel::Access,
+                    "debug" => LogLevel::Debug,
+                    _ => {
+                        return Err(SourceError::new(
+                            item.position,
+                            "expected \"off\", \"access\" or \"debug\"",
+                        ));
+                    }
+                };
+            }
+            if let Some(item) = server.get("trusted_proxies") {
+                config.server.trusted_proxies = string_list(item)?
+                    .iter()
+                    .map(|item| {
+                        string(item)?
+                            .parse()
+                            .map_err(|e: CidrError| SourceError::new(item.position, e.to_string()))
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+        }
+
+        if let Some(item) = document.get("listener") {
+            let Value::Array(items) = &item.value else {
+                return Err(type_error(item, "an array of tables, written [[listener]]"));
+            };
+            // the [[listener]] sections add to the addresses of `listen`, or replace the default one
+            if document
+                .table("server")?
+                .is_none_or(|server| server.0.get("listen").is_none())
+            {
+                config.server.listeners.clear();
+            }
+            for item in items {
+                let Value::Table(table) = &item.value else {
+                    return Err(type_error(item, "a table"));
+                };
+                let listener = Section::new(
+                    "[[listener]]",
+                    table,
+                    item.position,
+                    &["name", "address", "mode", "proxy_protocol"],
+                )?;
+                config.server.listeners.push(listener_config(&listener)?);
+            }
+            positions.push(("listeners", item.position));
+        }
+
+        if let Some(limits) = document.table("limits")? {
+            let limits = Section::new(
+                "[limits]",
+                limits.0,
+                limits.1,
+                &[
+                    "read_timeout",
+                    "write_timeout",
+                    "read_buffer_size",
+                    "max_header_size",
+                    "max_headers",
+                    "keep_alive",
+                    "keep_alive_timeout",
+                    "max_requests_per_connection",
+                    "max_connections",
+                    "max_connections_per_ip",
+                    "on_overload",
+                    "overload_retry_after",
+                ],
+            )?;
+            let server = &mut config.server;
+            for (name, timeout) in [
+                ("read_timeout", &mut server.read_timeout),
+                ("write_timeout", &mut server.write_timeout),
+            ] {
+                if let Some(item) = limits.get(name) {
+                    // `false` turns the timeout off
+                    *timeout = match item.value {
+                        Value::Boolean(false) => None,
+                        _ => Some(duration(item)?),
+                    };
+                    positions.push((name, item.position));
+                }
+            }
+            if let Some(item) = limits.get("keep_alive_timeout") {
+                server.keep_alive_timeout = duration(item)?;
+                positions.push(("keep_alive_timeout", item.position));
+            }
+            if let Some(item) = limits.get("overload_retry_after") {
+                server.overload_retry_after = duration(item)?;
+            }
+            for (name, max) in [
+                ("max_connections", &mut server.max_connections),
+                ("max_connections_per_ip", &mut server.max_connections_per_ip),
+            ] {
+                if let Some((value, position)) = limits.count(name)? {
+                    *max = Some(value);
+                    positions.push((name, position));
+                }
+            }
+            if let Some(item) = limits.get("on_overload") {
+                server.overload = match string(item)? {
+                    "pause" => Overload::Pause,
+                    "reject" => Overload::Reject,
+                    _ => {
+                        return Err(SourceError::new(
+                            item.position,
+                            "expected \"pause\" or \"reject\"",
+                        ));
+                    }
+                };
+            }
+            for (name, size) in [
+                ("read_buffer_size", &mut server.read_buffer_size),
+                ("max_header_size", &mut server.max_header_size),
+            ] {
+                if let Some(item) = limits.get(name) {
+                    *size = usize::try_from(byte_size(item)?)
+                        .map_err(|_| SourceError::new(item.position, "the size is too large"))?;
+                    positions.push((name, item.position));
+                }
+            }
+            for (name, count) in [
+                ("max_headers", &mut server.max_headers),
+                (
+                    "max_requests_per_connection",
+                    &mut server.max_requests_per_connection,
+                ),
+            ] {
+                if let Some((value, position)) = limits.count(name)? {
+                    *count = value;
+                    positions.push((name, position));
+                }
+            }
+            if let Some(item) = limits.get("keep_alive") {
+                server.keep_alive = boolean(item)?;
+            }
+        }
+
+        if let Err(e) = config.server.validate() {
+            let name = match &e {
+                ConfigError::NoBindAddress | ConfigError::InvalidBindAddress { .. } => "listeners",
+                ConfigError::InvalidValue { name, .. } => name,
+            };
+            let position = positions
+                .iter()
+                .find(|(setting, _)| *setting == name)
+                .map_or(start, |(_, position)| *position);
+            return Err(SourceError::new(position, e.to_string()));
+        }
+
+        if let Some(site) = document.table("site")? {
+            let site = Section::new(
+                "[site]",
+                site.0,
+                site.1,
+                &["root", "cache_size", "spa_fallback", "compression"],
+            )?;
+            config.site = site_config(&site, dir)?;
+        }
+        if let Some(headers) = document.table("headers")? {
+            config.headers = header_list(headers.0)?;
+        }
+        if let Some((table, position)) = document.table("rate_limit")? {
+            config.rate_limit = Some(rate_limit(table, position, "[rate_limit]")?);
+        }
+        if let Some(item) = document.get("access") {
+            let Value::Array(items) = &item.value else {
+                return Err(type_error(item, "an array of tables, written [[access]]"));
+            };
+            for item in items {
+                let Value::Table(table) = &item.value else {
+                    return Err(type_error(item, "a table"));
+                };
+                let section =
+                    Section::new("[[access]]", table, item.position, &["prefix", "rules"])?;
+                config.access.push(access_list(&section, &config.access)?);
+            }
+        }
+        if let Some(item) = document.get("basic_auth") {
+            let Value::Array(items) = &item.value else {
+                return Err(type_error(
+                    item,
+                    "an array of tables, written [[basic_auth]]",
+                ));
+            };
+            for item in items {
+                let Value::Table(table) = &item.value else {
+                    return Err(type_error(item, "a table"));
+                };
+                let section = Section::new(
+                    "[[basic_auth]]",
+                    table,
+                    item.position,
+                    &["prefix", "realm", "credentials"],
+                )?;
+                config
+                    .basic_auth
+                    .push(basic_auth(&section, dir, &config.basic_auth)?);
+            }
+        }
+        if let Some(item) = document.get("token_auth") {
+            let Value::Array(items) = &item.value else {
+                return Err(type_error(
+                    item,
+                    "an array of tables, written [[token_auth]]",
+                ));
+            };
+            for item in items {
+                let Value::Table(table) = &item.value else {
+                    return Err(type_error(item, "a table"));
+                };
+                let section = Section::new(
+                    "[[token_auth]]",
+                    table,
+                    item.position,
+                    &["prefix", "realm", "tokens", "scopes", "header", "query"],
+                )?;
+                config
+                    .token_auth
+                    .push(token_auth(&section, dir, &config.token_auth)?);
+            }
+        }
+        if let Some(item) = document.get("jwt_auth") {
+            let Value::Array(items) = &item.value else {
+                return Err(type_error(item, "an array of tables, written [[jwt_auth]]"));
+            };
+            for item in items {
+                let Value::Table(table) = &item.value else {
+                    return Err(type_error(item, "a table"));
+                };
+                let section = Section::new(
+                    "[[jwt_auth]]",
+                    table,
+                    item.position,
+                    &["prefix", "realm", "issuer", "audience", "leeway", "keys"],
+                )?;
+                config
+                    .jwt_auth
+                    .push(jwt_auth(&section, dir, &config.jwt_auth)?);
+            }
+        }
+        if let Some(item) = document.get("route") {
+            let Value::Array(items) = &item.value else {
+                return Err(type_error(item, "an array of tables, written [[route]]"));
+            };
+            for item in items {
+                let Value::Table(table) = &item.value else {
+                    return Err(type_error(item, "a table"));
+                };
+                let route = Section::new(
+                    "[[route]]",
+                    table,
+                    item.position,
+                    &[
+                        "prefix",
+                        "root",
+                        "cache_size",
+                        "spa_fallback",
+                        "compression",
+                        "headers",
+                        "rate_limit",
+                    ],
+                )?;
+                config
+                    .routes
+                    .push(route_config(&route, dir, &config.routes)?);
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Decodes a [[listener]] section.
+fn listener_config(section: &Section) -> Result<ListenerConfig, SourceError> {
+    let Some(item) = section.get("address") else {
+        return Err(SourceError::new(
+            section.position,
+            "the listener has no `address`",
+        ));
+    };
+    let mut listener = ListenerConfig::new(string(item)?);
+    if let Some(name) = section.get("name") {
+        listener = listener.with_name(string(name)?);
+    }
+    if let Some(mode) = section.get("mode") {
+        // permissions are written in octal, as for chmod
+        let mode = u32::from_str_radix(string(mode)?, 8).map_err(|_| {
+            SourceError::new(
+                mode.position,
+                "expected permissions in octal, such as \"660\"",
+            )
+        })?;
+        listener = listener.with_mode(mode);
+    }
+    if let Some(enabled) = section.get("proxy_protocol") {
+        listener = listener.with_proxy_protocol(boolean(enabled)?);
+    }
+    checked_listener(listener, item)
+}
+
+/// Validates a listener, reporting errors at the position of its address.
+fn checked_listener(listener: ListenerConfig, item: &Item) -> Result<ListenerConfig, SourceError> {
+    listener
+        .validate()
+        .map_err(|e| SourceError::new(item.position, e.to_string()))?;
+    Ok(listener)
+}
+
+/// Decodes the settings shared by [site] and [[route]] sections.
+fn site_config(section: &Section, dir: &Path) -> Result<SiteConfig, SourceError> {
+    let mut site = SiteConfig::default();
+    if let Some(item) = section.get("root") {
+        // joining an absolute path replaces `dir` entirely
+        site.root = Some(dir.join(string(item)?));
+    }
+    if let Some(item) = section.get("cache_size") {
+        site.cache_size = Some(byte_size(item)?);
+    }
+    if let Some(item) = section.get("spa_fallback") {
+        site.spa_fallback = Some(string(item)?.to_string());
+    }
+    if let Some(item) = section.get("compression") {
+        site.compression = boolean(item)?;
+    }
+    Ok(site)
+}
+
+fn route_config(
+    section: &Section,
+    dir: &Path,
+    previous: &[RouteConfig],
+) -> Result<RouteConfig, SourceError> {
+    let Some(item) = section.get("prefix") else {
+        return Err(SourceError::new(
+            section.position,
+            "the route has no `prefix`",
+        ));
+    };
+    // "/docs/" and "/docs" are the same route
+    let prefix = string(item)?.trim_end_matches('/');
+    if !string(item)?.starts_with('/') || prefix.is_empty() {
+        return Err(SourceError::new(
+            item.position,
+            "the prefix must start with `/` and name a directory, e.g. \"/docs\" (use [site] for the whole site)",
+        ));
+    }
+    if previous.iter().any(|route| route.prefix == prefix) {
+        return Err(SourceError::new(
+            item.position,
+            format!("there is already a route for `{}`", prefix),
+        ));
+    }
+    let site = site_config(section, dir)?;
+    if site.root.is_none() {
+        return Err(SourceError::new(
+            section.position,
+            "the route has no `root`",
+        ));
+    }
+    let headers = match section.get("headers") {
+        Some(Item {
+            value: Value::Table(table),
+            ..
+        }) => header_list(table)?,
+        Some(item) => return Err(type_error(item, "a table")),
+        None => Vec::new(),
+    };
+    let rate_limit = match section.get("rate_limit") {
+        Some(Item {
+            value: Value::Table(table),
+            position,
+        }) => Some(rate_limit(table, *position, "the rate limit of the route")?),
+        Some(item) => return Err(type_error(item, "a table")),
+        None => None,
+    };
+    Ok(RouteConfig {
+        prefix: prefix.to_string(),
+        site,
+        headers,
+        rate_limit,
+    })
+}
+
+/// Decodes the settings of a rate limit, in a [rate_limit] section or the `rate_limit` of a route.
+fn rate_limit(table: &Table, position: Position, name: &str) -> Result<RateLimit, SourceError> {
+    let section = Section::new(
+        name,
+        table,
+        position,
+        &["algorithm", "limit", "window", "burst", "key", "max_keys"],
+    )?;
+    // a count of at least 1 that fits the limiter
+    let positive = |key: &str| -> Result<Option<u32>, SourceError> {
+        match section.count(key)? {
+            Some((n, position)) => u32::try_from(n)
+                .ok()
+                .filter(|n| *n > 0)
+                .map(Some)
+                .ok_or_else(|| {
+                    SourceError::new(position, "expected a number from 1 to 4294967295")
+                }),
+            None => Ok(None),
+        }
+    };
+    let Some(limit) = positive("limit")? else {
+        return Err(SourceError::new(position, "the rate limit has no `limit`"));
+    };
+    let window = match section.get("window") {
+        Some(item) => match duration(item)? {
+            Duration::ZERO => {
+                return Err(SourceError::new(item.position, "the window cannot be zero"));
+            }
+            window => window,
+        },
+        None => Duration::from_secs(1),
+    };
+    let mut rate_limit = RateLimit::new(limit, window);
+    if let Some(item) = section.get("algorithm") {
+        rate_limit.algorithm = match string(item)? {
+            "token_bucket" => Algorithm::TokenBucket,
+            "sliding_window" => Algorithm::SlidingWindow,
+            _ => {
+                return Err(SourceError::new(
+                    item.position,
+                    "expected \"token_bucket\" or \"sliding_window\"",
+                ));
+            }
+        };
+    }
+    if let Some(burst) = positive("burst")? {
+        rate_limit.burst = burst;
+    }
+    if let Some(item) = section.get("key") {
+        rate_limit.key = match string(item)? {
+            "client_ip" => RateLimitKey::ClientIp,
+            "route" => RateLimitKey::Route,
+            key => match key.strip_prefix("header:") {
+                Some(header) if !header.trim().is_empty() => {
+                    RateLimitKey::Header(header.trim().to_string())
+                }
+                _ => {
+                    return Err(SourceError::new(
+                        item.position,
+                        "expected \"client_ip\", \"route\" or \"header:<name>\"",
+                    ));
+                }
+            },
+        };
+    }
+    if let Some((max_keys, position)) = section.count("max_keys")? {
+        if max_keys == 0 {
+            return Err(SourceError::new(
+                position,
+                "at least one key must be tracked",
+            ));
+        }
+        rate_limit.max_keys = max_keys;
+    }
+    Ok(rate_limit)
+}
+
+/// Decodes a [[basic_auth]] section, e.g. `prefix = "/admin"` and `credentials = "admin.htpasswd"`.
+fn basic_auth(
+    section: &Section,
+    dir: &Path,
+    previous: &[BasicAuthConfig],
+) -> Result<BasicAuthConfig, SourceError> {
+    let prefix = auth_prefix(section, |prefix| {
+        previous.iter().any(|other| other.prefix == prefix)
+    })?;
+    let realm = realm(section, "Restricted")?;
+    let Some(credentials) = section.get("credentials") else {
+        return Err(SourceError::new(
+            section.position,
+            "the realm has no `credentials` file",
+        ));
+    };
+    Ok(BasicAuthConfig {
+        prefix,
+        realm,
+        // joining an absolute path replaces `dir` entirely
+        credentials: dir.join(string(credentials)?),
+    })
+}
+
+/// Decodes a [[token_auth]] section, e.g. `prefix = "/api"`, `tokens = "api.tokens"` and `scopes = ["read"]`.
+fn token_auth(
+    section: &Section,
+    dir: &Path,
+    previous: &[TokenAuthConfig],
+) -> Result<TokenAuthConfig, SourceError> {
+    let prefix = auth_prefix(section, |prefix| {
+        previous.iter().any(|other| other.prefix == prefix)
+    })?;
+    let realm = realm(section, "api")?;
+    let Some(tokens) = section.get("tokens") else {
+        return Err(SourceError::new(
+            section.position,
+            "the token authentication has no `tokens` file",
+        ));
+    };
+    let mut scopes = Vec::new();
+    if let Some(item) = section.get("scopes") {
+        for item in string_list(item)? {
+            let scope = string(item)?;
+            // scopes are listed in challenges separated by spaces
+            if scope.is_empty()
+                || !scope
+                    .chars()
+                    .all(|c| c.is_ascii_graphic() && c != '"' && c != '\\')
+            {
+                return Err(SourceError::new(
+                    item.position,
+                    "a scope must be a non-empty word, e.g. \"read\"",
+                ));
+            }
+            scopes.push(scope.to_string());
+        }
+    }
+    let header = match section.get("header") {
+        Some(item) => {
+            let name = string(item)?;
+            if !is_header_name(name) {
+                return Err(SourceError::new(
+                    item.position,
+                    format!("`{}` is not a valid header name", name),
+                ));
+            }
+            Some(name.to_string())
+        }
+        None => None,
+    };
+    let query = match section.get("query") {
+        Some(item) => Some(string(item)?.to_string()),
+        None => None,
+    };
+    Ok(TokenAuthConfig {
+        prefix,
+        realm,
+        tokens: dir.join(string(tokens)?),
+        scopes,
+        header,
+        query,
+    })
+}
+
+/// Decodes a [[jwt_auth]] section, e.g. `prefix = "/api"`, `issuer = "https://sso.example.com"` and
+/// `keys = { 2024-06 = "sso.key" }`.
+fn jwt_auth(
+    section: &Section,
+    dir: &Path,
+    previous: &[JwtAuthConfig],
+) -> Result<JwtAuthConfig, SourceError> {
+    let prefix = auth_prefix(section, |prefix| {
+        previous.iter().any(|other| other.prefix == prefix)
+    })?;
+    let realm = realm(section, "api")?;
+    let issuer = match section.get("issuer") {
+        Some(item) => Some(string(item)?.to_string()),
+        None => None,
+    };
+    let audience = match section.get("audience") {
+        Some(item) => Some(string(item)?.to_string()),
+        None => None,
+    };
+    let leeway = match section.get("leeway") {
+        Some(item) => duration(item)?,
+        None => Duration::from_secs(60),
+    };
+    // the keys are read from files, so that the configuration can be shown without them
+    let Some((table, _)) = section.table("keys")? else {
+        return Err(SourceError::new(
+            section.position,
+            "the JWT authentication has no `keys`",
+        ));
+    };
+    let keys = table
+        .entries()
+        .iter()
+        .map(|entry| Ok((entry.key.clone(), dir.join(string(&entry.item)?))))
+        .collect::<Result<Vec<_>, SourceError>>()?;
+    if keys.is_empty() {
+        return Err(SourceError::new(
+            section.position,
+            "the JWT authentication has no `keys`",
+        ));
+    }
+    Ok(JwtAuthConfig {
+        prefix,
+        realm,
+        issuer,
+        audience,
+        leeway,
+        keys,
+    })
+}
+
+/// Decodes the `prefix` of an authentication section, "/" by default, without its trailing slash.
+///
+/// # Arguments
+///
+/// * `section` - The section.
+/// * `taken` - Returns true if an earlier section of the same kind has the prefix.
+fn auth_prefix(section: &Section, taken: impl Fn(&str) -> bool) -> Result<String, SourceError> {
+    let Some(item) = section.get("prefix") else {
+        return Ok(String::new());
+    };
+    let prefix = string(item)?;
+    if !prefix.starts_with('/') {
+        return Err(SourceError::new(
+            item.position,
+            "the prefix must start with `/`, e.g. \"/admin\"",
+        ));
+    }
+    let trimmed = prefix.trim_end_matches('/');
+    if taken(trimmed) {
+        return Err(SourceError::new(
+            item.position,
+            format!("there is already a realm for `{}`", prefix),
+        ));
+    }
+    Ok(trimmed.to_string())
+}
+
+/// Decodes the `realm` of an authentication section.
+fn realm(section: &Section, default: &str) -> Result<String, SourceError> {
+    let Some(item) = section.get("realm") else {
+        return Ok(default.to_string());
+    };
+    let realm = string(item)?;
+    if realm.is_empty() || realm.chars().any(char::is_control) {
+        return Err(SourceError::new(
+            item.position,
+            "the realm must be a non-empty name without control characters",
+        ));
+    }
+    Ok(realm.to_string())
+}
+
+/// Decodes an [[access]] section, e.g. `prefix = "/admin"` and `rules = ["allow 10.0.0.0/8", "deny all"]`.
+fn access_list(section: &Section, previous: &[AccessConfig]) -> Result<AccessConfig, SourceError> {
+    let prefix = match section.get("prefix") {
+        Some(item) => {
+            let prefix = string(item)?;
+            if !prefix.starts_with('/') {
+                return Err(SourceError::new(
+                    item.position,
+                    "the prefix must start with `/`, e.g. \"/admin\"",
+                ));
+            }
+            if previous
+                .iter()
+                .any(|other| other.prefix == prefix.trim_end_matches('/'))
+            {
+                return Err(SourceError::new(
+                    item.position,
+                    format!("there are already access rules for `{}`", prefix),
+                ));
+            }
+            prefix.trim_end_matches('/').to_string()
+        }
+        None => String::new(),
+    };
+    let mut list = AccessList::new();
+    let Some(rules) = section.get("rules") else {
+        return Err(SourceError::new(
+            section.position,
+            "the access list has no `rules`",
+        ));
+    };
+    for item in string_list(rules)? {
+        let invalid = || {
+            SourceError::new(
+                item.position,
+                "expected a rule such as \"allow 10.0.0.0/8\" or \"deny all\"",
+            )
+        };
+        let (action, range) = string(item)?.trim().split_once(' ').ok_or_else(invalid)?;
+        let action = match action {
+            "allow" => Action::Allow,
+            "deny" => Action::Deny,
+            _ => return Err(invalid()),
+        };
+        let range = match range.trim() {
+            "all" => None,
+            range => Some(
+                range
+                    .parse::<Cidr>()
+                    .map_err(|e| SourceError::new(item.position, e.to_string()))?,
+            ),
+        };
+        list = list.with_rule(action, range);
+    }
+    Ok(AccessConfig { prefix, list })
+}
+
+/// Returns true if a header name is a token, as HTTP requires (RFC 9110, section 5.1).
+fn is_header_name(name: &str) -> bool {
+    let is_token = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    !name.is_empty() && name.chars().all(is_token)
+}
+
+/// Decodes a table of response headers, checking that they can be sent as is.
+fn header_list(table: &Table) -> Result<Vec<(String, String)>, SourceError> {
+    table
+        .entries()
+        .iter()
+        .map(|entry| {
+            if !is_header_name(&entry.key) {
+                return Err(SourceError::new(
+                    entry.position,
+                    format!("`{}` is not a valid header name", entry.key),
+                ));
+            }
+            let value = string(&entry.item)?;
+            if value.contains(['\r', '\n']) {
+                return Err(SourceError::new(
+                    entry.item.position,
+                    "header values cannot contain line breaks",
+                ));
+            }
+            Ok((entry.key.clone(), value.to_string()))
+        })
+        .collect()
+}
+
+/// A table being decoded, whose keys have been checked against the ones its section accepts.
+struct Section<'t> {
+    table: &'t Table,
+    position: Position, // where the section starts, for errors about missing keys
+}
+
+impl<'t> Section<'t> {
+    fn new(
+        name: &str,
+        table: &'t Table,
+        position: Position,
+        keys: &[&str],
+    ) -> Result<Self, SourceError> {
+        if let Some(entry) = table
+            .entries()
+            .iter()
+            .find(|entry| !keys.contains(&entry.key.as_str()))
+        {
+            return Err(SourceError::new(
+                entry.position,
+                format!(
+                    "unknown key `{}` in {} (expected one of: {})",
+                    entry.key,
+                    name,
+                    keys.join(", ")
+                ),
+            ));
+        }
+        Ok(Self { table, position })
+    }
+
+    fn get(&self, key: &str) -> Option<&'t Item> {
+        self.table.get(key).map(|entry| &entry.item)
+    }
+
+    /// Returns a nested table, along with its position.
+    fn table(&self, key: &str) -> Result<Option<(&'t Table, Position)>, SourceError> {
+        match self.get(key) {
+            Some(Item {
+                value: Value::Table(table),
+                position,
+            }) => Ok(Some((table, *position))),
+            Some(item) => Err(type_error(item, "a table")),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns a non-negative integer setting, along with its position.
+    fn count(&self, key: &str) -> Result<Option<(usize, Position)>, SourceError> {
+        let Some(item) = self.get(key) else {
+            return Ok(None);
+        };
+        match item.value {
+            Value::Integer(n) => usize::try_from(n)
+                .map(|n| Some((n, item.position)))
+                .map_err(|_| SourceError::new(item.position, "expected a positive number")),
+            _ => Err(type_error(item, "an integer")),
+        }
+    }
+}
+
+fn type_error(item: &Item, expected: &str) -> SourceError {
+    SourceError::new(
+        item.position,
+        format!("expected {}, found {}", expected, item.value.type_name()),
+    )
+}
+
+fn string(item: &Item) -> Result<&str, SourceError> {
+    match &item.value {
+        Value::String(value) => Ok(value),
+        _ => Err(type_error(item, "a string")),
+    }
+}
+
+/// Reads a setting that is a string, or an array of them.
+fn string_list(item: &Item) -> Result<&[Item], SourceError> {
+    match &item.value {
+        Value::String(_) => Ok(std::slice::from_ref(item)),
+        Value::Array(items) => Ok(items.as_slice()),
+        _ => Err(type_error(item, "a string or an array of strings")),
+    }
+}
+
+fn boolean(item: &Item) -> Result<bool, SourceError> {
+    match item.value {
+        Value::Boolean(value) => Ok(value),
+        _ => Err(type_error(item, "a boolean")),
+    }
+}
+
+/// Decodes a duration: a number of seconds, or a string such as "500ms", "30s", "5m" or "1h".
+fn duration(item: &Item) -> Result<Duration, SourceError> {
+    let invalid = || {
+        SourceError::new(
+            item.position,
+            "expected a duration such as \"500ms\", \"30s\", \"5m\" or \"1h\"",
+        )
+    };
+    let text = match &item.value {
+        Value::Integer(secs) => {
+            return u64::try_from(*secs)
+                .map(Duration::from_secs)
+                .map_err(|_| invalid());
+        }
+        Value::String(text) => text.trim(),
+        _ => return Err(type_error(item, "a duration")),
+    };
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let amount: u64 = text[..split].parse().map_err(|_| invalid())?;
+    let millis = match text[split..].trim() {
+        "ms" => Some(amount),
+        "s" => amount.checked_mul(1000),
+        "m" => amount.checked_mul(60 * 1000),
+        "h" => amount.checked_mul(60 * 60 * 1000),
+        _ => None,
+    };
+    millis.map(Duration::from_millis).ok_or_else(invalid)
+}
+
+/// Decodes a size: a number of bytes, or a string such as "512B", "16KiB", "64MiB" or "1GiB".
+fn byte_size(item: &Item) -> Result<u64, SourceError> {
+    let invalid = || {
+        SourceError::new(
+            item.position,
+            "expected a size such as \"512B\", \"16KiB\", \"64MiB\" or \"1GiB\"",
+        )
+    };
+    let text = match &item.value {
+        Value::Integer(bytes) => return u64::try_from(*bytes).map_err(|_| invalid()),
+        Value::String(text) => text.trim(),
+        _ => return Err(type_error(item, "a size")),
+    };
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let amount: u64 = text[..split].parse().map_err(|_| invalid())?;
+    let unit: u64 = match text[split..].trim() {
+        "B" => 1,
+        "KiB" => 1024,
+        "MiB" => 1024 * 1024,
+        "GiB" => 1024 * 1024 * 1024,
+        _ => return Err(invalid()),
+    };
+    amount.checked_mul(unit).ok_or_else(invalid)
+}
+
+// Writes the configuration in the syntax of configuration files, so the output can be saved and loaded again.
+impl Display for FileConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let server = &self.server;
+        // listeners named after their address are written as `listen`, the others as [[listener]]
+        let (plain, named): (Vec<_>, Vec<_>) = server.listeners.iter().partition(|listener| {
+            listener.name == listener.address.to_string()
+                && listener.mode.is_none()
+                && !listener.proxy_protocol
+        });
+        let listen: Vec<String> = plain
+            .iter()
+            .map(|listener| quote(&listener.address.to_string()))
+            .collect();
+        let log_level = match server.log_level {
+            LogLevel::Off => "off",
+            LogLevel::Access => "access",
+            LogLevel::Debug => "debug",
+        };
+        writeln!(f, "[server]")?;
+        if !listen.is_empty() {
+            writeln!(f, "listen = [{}]", listen.join(", "))?;
+        }
+        writeln!(f, "workers = {}", server.workers)?;
+        writeln!(f, "log_level = {}", quote(log_level))?;
+        if !server.trusted_proxies.is_empty() {
+            let ranges: Vec<String> = server
+                .trusted_proxies
+                .iter()
+                .map(|range| quote(&range.to_string()))
+                .collect();
+            writeln!(f, "trusted_proxies = [{}]", ranges.join(", "))?;
+        }
+        writeln!(f)?;
+        for listener in named {
+            writeln!(f, "[[listener]]")?;
+            writeln!(f, "name = {}", quote(&listener.name))?;
+            writeln!(f, "address = {}", quote(&listener.address.to_string()))?;
+            if let Some(mode) = listener.mode {
+                writeln!(f, "mode = \"{:03o}\"", mode)?;
+            }
+            if listener.proxy_protocol {
+                writeln!(f, "proxy_protocol = true")?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "[limits]")?;
+        for (name, timeout) in [
+            ("read_timeout", server.read_timeout),
+            ("write_timeout", server.write_timeout),
+        ] {
+            match timeout {
+                Some(timeout) => writeln!(f, "{} = {}", name, format_duration(timeout))?,
+                None => writeln!(f, "{} = false", name)?,
+            }
+        }
+        writeln!(f, "read_buffer_size = {}", server.read_buffer_size)?;
+        writeln!(f, "max_header_size = {}", server.max_header_size)?;
+        writeln!(f, "max_headers = {}", server.max_headers)?;
+        writeln!(f, "keep_alive = {}", server.keep_alive)?;
+        writeln!(
+            f,
+            "keep_alive_timeout = {}",
+            format_duration(server.keep_alive_timeout)
+        )?;
+        writeln!(
+            f,
+            "max_requests_per_connection = {}",
+            server.max_requests_per_connection
+        )?;
+        for (name, max) in [
+            ("max_connections", server.max_connections),
+            ("max_connections_per_ip", server.max_connections_per_ip),
+        ] {
+            if let Some(max) = max {
+                writeln!(f, "{} = {}", name, max)?;
+            }
+        }
+        let overload = match server.overload {
+            Overload::Pause => "pause",
+            Overload::Reject => "reject",
+        };
+        writeln!(f, "on_overload = {}", quote(overload))?;
+        writeln!(
+            f,
+            "overload_retry_after = {}",
+            format_duration(server.overload_retry_after)
+        )?;
+        writeln!(f)?;
+        writeln!(f, "[site]")?;
+        write_site(f, &self.site)?;
+        if !self.headers.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "[headers]")?;
+            for (name, value) in &self.headers {
+                writeln!(f, "{} = {}", quote(name), quote(value))?;
+            }
+        }
+        for list in &self.access {
+            let rules: Vec<String> = list
+                .list
+                .rules
+                .iter()
+                .map(|rule| quote(&rule.to_string()))
+                .collect();
+            writeln!(f)?;
+            writeln!(f, "[[access]]")?;
+            let prefix = if list.prefix.is_empty() {
+                "/"
+            } else {
+                &list.prefix
+            };
+            writeln!(f, "prefix = {}", quote(prefix))?;
+            writeln!(f, "rules = [{}]", rules.join(", "))?;
+        }
+        for realm in &self.basic_auth {
+            writeln!(f)?;
+            writeln!(f, "[[basic_auth]]")?;
+            let prefix = if realm.prefix.is_empty() {
+                "/"
+            } else {
+                &realm.prefix
+            };
+            writeln!(f, "prefix = {}", quote(prefix))?;
+            writeln!(f, "realm = {}", quote(&realm.realm))?;
+            writeln!(
+                f,
+                "credentials = {}",
+                quote(&realm.credentials.to_string_lossy())
+            )?;
+        }
+        for policy in &self.token_auth {
+            writeln!(f)?;
+            writeln!(f, "[[token_auth]]")?;
+            let prefix = if policy.prefix.is_empty() {
+                "/"
+            } else {
+                &policy.prefix
+            };
+            writeln!(f, "prefix = {}", quote(prefix))?;
+            writeln!(f, "realm = {}", quote(&policy.realm))?;
+            writeln!(f, "tokens = {}", quote(&policy.tokens.to_string_lossy()))?;
+            let scopes: Vec<String> = policy.scopes.iter().map(|scope| quote(scope)).collect();
+            writeln!(f, "scopes = [{}]", scopes.join(", "))?;
+            if let Some(header) = &policy.header {
+                writeln!(f, "header = {}", quote(header))?;
+            }
+            if let Some(query) = &policy.query {
+                writeln!(f, "query = {}", quote(query))?;
+            }
+        }
+        for policy in &self.jwt_auth {
+            writeln!(f)?;
+            writeln!(f, "[[jwt_auth]]")?;
+            let prefix = if policy.prefix.is_empty() {
+                "/"
+            } else {
+                &policy.prefix
+            };
+            writeln!(f, "prefix = {}", quote(prefix))?;
+            writeln!(f, "realm = {}", quote(&policy.realm))?;
+            if let Some(issuer) = &policy.issuer {
+                writeln!(f, "issuer = {}", quote(issuer))?;
+            }
+            if let Some(audience) = &policy.audience {
+                writeln!(f, "audience = {}", quote(audience))?;
+            }
+            writeln!(f, "leeway = {}", format_duration(policy.leeway))?;
+            let keys: Vec<String> = policy
+                .keys
+                .iter()
+                .map(|(kid, path)| format!("{} = {}", quote(kid), quote(&path.to_string_lossy())))
+                .collect();
+            writeln!(f, "keys = {{ {} }}", keys.join(", "))?;
+        }
+        if let Some(rate_limit) = &self.rate_limit {
+            writeln!(f)?;
+            writeln!(f, "[rate_limit]")?;
+            for setting in rate_limit_settings(rate_limit) {
+                writeln!(f, "{}", setting)?;
+            }
+        }
+        for route in &self.routes {
+            writeln!(f)?;
+            writeln!(f, "[[route]]")?;
+            writeln!(f, "prefix = {}", quote(&route.prefix))?;
+            write_site(f, &route.site)?;
+            if !route.headers.is_empty() {
+                let headers: Vec<String> = route
+                    .headers
+                    .iter()
+                    .map(|(name, value)| format!("{} = {}", quote(name), quote(value)))
+                    .collect();
+                writeln!(f, "headers = {{ {} }}", headers.join(", "))?;
+            }
+            if let Some(rate_limit) = &route.rate_limit {
+                let settings = rate_limit_settings(rate_limit);
+                writeln!(f, "rate_limit = {{ {} }}", settings.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the settings of a rate limit, as `key = value` lines.
+fn rate_limit_settings(rate_limit: &RateLimit) -> Vec<String> {
+    let algorithm = match rate_limit.algorithm {
+        Algorithm::TokenBucket => "token_bucket",
+        Algorithm::SlidingWindow => "sliding_window",
+    };
+    let key = match &rate_limit.key {
+        RateLimitKey::ClientIp => "client_ip".to_string(),
+        RateLimitKey::Route => "route".to_string(),
+        RateLimitKey::Header(name) => format!("header:{}", name),
+    };
+    vec![
+        format!("algorithm = {}", quote(algorithm)),
+        format!("limit = {}", rate_limit.limit),
+        format!("window = {}", format_duration(rate_limit.window)),
+        format!("burst = {}", rate_limit.burst),
+        format!("key = {}", quote(&key)),
+        format!("max_keys = {}", rate_limit.max_keys),
+    ]
+}
+
+/// Writes the settings shared by [site] and [[route]] sections, leaving out the unset ones.
+fn write_site(f: &mut Formatter<'_>, site: &SiteConfig) -> FmtResult {
+    if let Some(root) = &site.root {
+        writeln!(f, "root = {}", quote(&root.to_string_lossy()))?;
+    }
+    if let Some(size) = site.cache_size {
+        writeln!(f, "cache_size = {}", size)?;
+    }
+    if let Some(fallback) = &site.spa_fallback {
+        writeln!(f, "spa_fallback = {}", quote(fallback))?;
+    }
+    writeln!(f, "compression = {}", site.compression)
+}
+
+/// Quotes a string as a basic string. JSON escapes are valid in TOML too, and `$` is escaped
+/// so that it is not taken for an environment variable when the output is loaded.
+fn quote(value: &str) -> String {
+    json::quote(value).replace('$', "\\$")
+}
+
+/// Formats a duration the way `duration` parses it, in seconds when that is exact.
+fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    if millis.is_multiple_of(1000) {
+        format!("\"{}s\"", millis / 1000)
+    } else {
+        format!("\"{}ms\"", millis)
+    }
+}
+
+/// A configuration file that could not be loaded.
+#[derive(Debug)]
+pub enum ConfigFileError {
+    Io { path: PathBuf, error: IoError }, // the file cannot be read
+    Invalid { path: PathBuf, error: SourceError }, // the file is read but its contents are invalid
+}
+
+impl Display for ConfigFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            ConfigFileError::Io { path, error } => {
+                write!(f, "cannot read {}: {}", path.display(), error)
+            }
+            // the same form as compiler errors, so editors can jump to the position
+            ConfigFileError::Invalid { path, error } => write!(f, "{}:{}", path.display(), error),
+        }
+    }
+}
+
+impl Error for ConfigFileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigFileError::Io { error, .. } => Some(error),
+            ConfigFileError::Invalid { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<FileConfig, SourceError> {
+        FileConfig::parse(source, Path::new("/srv/site"), |name| {
+            (name == "DOCS").then(|| "/srv/docs".to_string())
+        })
+    }
+
+    fn error(source: &str) -> String {
+        parse(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_parse_full_configuration() {
+        let source = r#"
+[server]
+listen = ["0.0.0.0:80", "[::]:80"]
+workers = 2
+log_level = "off"
+trusted_proxies = ["10.0.0.0/8", "::1"]
+
+[limits]
+read_timeout = "1m"
+write_timeout = false
+keep_alive_timeout = 2
+max_header_size = "32KiB"
+max_headers = 50
+keep_alive = false
+max_connections = 1000
+max_connections_per_ip = 20
+on_overload = "reject"
+overload_retry_after = "10s"
+
+[site]
+root = "public"
+cache_size = "64MiB"
+spa_fallback = "index.html"
+compression = false
+
+[headers]
+X-Frame-Options = "DENY"
+
+[rate_limit]
+algorithm = "sliding_window"
+limit = 600
+window = "1m"
+key = "header:X-API-Key"
+
+[[access]]
+rules = "deny 203.0.113.0/24"
+
+[[access]]
+prefix = "/admin/"
+rules = ["allow 10.0.0.0/8", "allow 2001:db8::/32", "deny all"]
+
+[[basic_auth]]
+prefix = "/admin/"
+realm = "Administration"
+credentials = "admin.htpasswd"
+
+[[token_auth]]
+prefix = "/api"
+tokens = "/etc/api.tokens"
+scopes = ["read", "write"]
+header = "X-API-Key"
+
+[[jwt_auth]]
+prefix = "/sso"
+issuer = "https://sso.example.com"
+audience = "site"
+leeway = "30s"
+keys = { 2024-05 = "keys/old.key", "2024-06" = "/etc/sso.key" }
+
+[[route]]
+prefix = "/docs/"
+root = "${DOCS}"
+headers = { Cache-Control = "max-age=60" }
+rate_limit = { limit = 5, burst = 20, max_keys = 100 }
+"#;
+        let config = parse(source).unwrap();
+        assert_eq!(
+            config.server.listeners,
+            [
+                ListenerConfig::new("0.0.0.0:80"),
+                ListenerConfig::new("[::]:80")
+            ]
+        );
+        assert_eq!(config.server.workers, 2);
+        assert_eq!(config.server.log_level, LogLevel::Off);
+        assert!(config.server.is_trusted_proxy("10.9.8.7".parse().unwrap()));
+        assert!(!config.server.is_trusted_proxy("127.0.0.1".parse().unwrap()));
+        assert_eq!(config.server.read_timeout, Some(Duration::from_secs(60)));
+        assert_eq!(config.server.write_timeout, None);
+        assert_eq!(config.server.keep_alive_timeout, Duration::from_secs(2));
+        assert_eq!(config.server.max_header_size, 32 * 1024);
+        assert_eq!(config.server.max_headers, 50);
+        assert!(!config.server.keep_alive);
+        assert_eq!(config.server.max_connections, Some(1000));
+        assert_eq!(config.server.max_connections_per_ip, Some(20));
+        assert_eq!(config.server.overload, Overload::Reject);
+        assert_eq!(config.server.overload_retry_after, Duration::from_secs(10));
+        assert_eq!(
+            config.site,
+            SiteConfig {
+                root: Some(PathBuf::from("/srv/site/public")),
+                cache_size: Some(64 * 1024 * 1024),
+                spa_fallback: Some("index.html".to_string()),
+                compression: false,
+            }
+        );
+        assert_eq!(
+            config.headers,
+            [("X-Frame-Options".to_string(), "DENY".to_string())]
+        );
+        assert_eq!(config.routes.len(), 1);
+        assert_eq!(config.routes[0].prefix, "/docs");
+        assert_eq!(config.routes[0].site.root, Some(PathBuf::from("/srv/docs")));
+        assert_eq!(
+            config.routes[0].headers,
+            [("Cache-Control".to_string(), "max-age=60".to_string())]
+        );
+        assert_eq!(
+            config.rate_limit,
+            Some(
+                RateLimit::new(600, Duration::from_secs(60))
+                    .with_algorithm(Algorithm::SlidingWindow)
+                    .with_key(RateLimitKey::Header("X-API-Key".to_string()))
+            )
+        );
+        let cidr = |range: &str| range.parse::<Cidr>().unwrap();
+        assert_eq!(
+            config.access,
+            [
+                AccessConfig {
+                    prefix: String::new(),
+                    list: AccessList::new().deny(cidr("203.0.113.0/24")),
+                },
+                AccessConfig {
+                    prefix: "/admin".to_string(),
+                    list: AccessList::new()
+                        .allow(cidr("10.0.0.0/8"))
+                        .allow(cidr("2001:db8::/32"))
+                        .deny_all(),
+                },
+            ]
+        );
+        assert_eq!(
+            config.basic_auth,
+            [BasicAuthConfig {
+                prefix: "/admin".to_string(),
+                realm: "Administration".to_string(),
+                credentials: PathBuf::from("/srv/site/admin.htpasswd"),
+            }]
+        );
+        assert_eq!(
+            config.token_auth,
+            [TokenAuthConfig {
+                prefix: "/api".to_string(),
+                realm: "api".to_string(),
+                tokens: PathBuf::from("/etc/api.tokens"),
+                scopes: vec!["read".to_string(), "write".to_string()],
+                header: Some("X-API-Key".to_string()),
+                query: None,
+            }]
+        );
+        assert_eq!(
+            config.jwt_auth,
+            [JwtAuthConfig {
+                prefix: "/sso".to_string(),
+                realm: "api".to_string(),
+                issuer: Some("https://sso.example.com".to_string()),
+                audience: Some("site".to_string()),
+                leeway: Duration::from_secs(30),
+                keys: vec![
+                    (
+                        "2024-05".to_string(),
+                        PathBuf::from("/srv/site/keys/old.key")
+                    ),
+                    ("2024-06".to_string(), PathBuf::from("/etc/sso.key")),
+                ],
+            }]
+        );
+        assert_eq!(
+            config.routes[0].rate_limit,
+            Some(
+                RateLimit::new(5, Duration::from_secs(1))
+                    .with_burst(20)
+                    .with_max_keys(100)
+            )
+        );
+    }
+
+    #[test]
+    fn test_empty_file_uses_defaults() {
+        assert_eq!(parse("# nothing here\n").unwrap(), FileConfig::default());
+    }
+
+    #[test]
+    fn test_schema_errors_have_positions() {
+        assert_eq!(
+            error("[server]\nport = 80"),
+            "2:1: unknown key `port` in [server] (expected one of: listen, workers, log_level, trusted_proxies)"
+        );
+        assert_eq!(
+            error("[server]\nworkers = \"4\""),
+            "2:11: expected an integer, found a string"
+        );
+        assert_eq!(
+            error("[limits]\nread_timeout = \"soon\""),
+            "2:16: expected a duration such as \"500ms\", \"30s\", \"5m\" or \"1h\""
+        );
+        assert_eq!(
+            error("[[route]]\nroot = \"/srv\""),
+            "1:1: the route has no `prefix`"
+        );
+        assert_eq!(
+            error("[headers]\n\"Bad Name\" = \"x\""),
+            "2:1: `Bad Name` is not a valid header name"
+        );
+        assert_eq!(
+            error("[server]\ntrusted_proxies = [\"10.0.0.0/8\", \"10.0.0.0/40\"]"),
+            "2:34: the prefix length must be between 0 and 32"
+        );
+        assert_eq!(
+            error("[rate_limit]\nlimit = 0"),
+            "2:9: expected a number from 1 to 4294967295"
+        );
+        assert_eq!(
+            error("[rate_limit]\nlimit = 1\nkey = \"cookie\""),
+            "3:7: expected \"client_ip\", \"route\" or \"header:<name>\""
+        );
+        assert_eq!(
+            error("[[access]]\nrules = [\"deny all\", \"permit 10.0.0.1\"]"),
+            "2:22: expected a rule such as \"allow 10.0.0.0/8\" or \"deny all\""
+        );
+        assert_eq!(
+            error(
+                "[[access]]\nprefix = \"/a\"\nrules = []\n[[access]]\nprefix = \"/a/\"\nrules = []"
+            ),
+            "5:10: there are already access rules for `/a/`"
+        );
+        assert_eq!(
+            error("[[basic_auth]]\nprefix = \"/a\""),
+            "1:1: the realm has no `credentials` file"
+        );
+        assert_eq!(
+            error("[[basic_auth]]\nrealm = \"\"\ncredentials = \"a\""),
+            "2:9: the realm must be a non-empty name without control characters"
+        );
+        assert_eq!(
+            error("[[token_auth]]\ntokens = \"t\"\nscopes = \"read write\""),
+            "3:10: a scope must be a non-empty word, e.g. \"read\""
+        );
+        assert_eq!(
+            error("[[token_auth]]\ntokens = \"t\"\n[[token_auth]]\nprefix = \"/\"\ntokens = \"u\""),
+            "4:10: there is already a realm for `/`"
+        );
+        assert_eq!(
+            error("[[jwt_auth]]\nissuer = \"sso\"\nkeys = {}"),
+            "1:1: the JWT authentication has no `keys`"
+        );
+        assert_eq!(
+            error("[[jwt_auth]]\nkeys = { k1 = 1 }"),
+            "2:15: expected a string, found an integer"
+        );
+    }
+
+    #[test]
+    fn test_display_can_be_loaded_again() {
+        let source = "[server]\ntrusted_proxies = \"192.168.0.0/16\"\n[limits]\nread_timeout = \"1500ms\"\nwrite_timeout = false\nmax_connections = 5\n[site]\nspa_fallback = \"$index.html\"\n[headers]\nX-Test = \"a \\\"b\\\"\"\n[[route]]\nprefix = \"/a\"\nroot = \"a\"\nheaders = { X-Route = \"1\" }\nrate_limit = { limit = 3, window = \"1500ms\", key = \"route\" }\n[rate_limit]\nlimit = 10\n[[access]]\nrules = [\"allow ::1\", \"deny all\"]\n[[access]]\nprefix = \"/a\"\nrules = []\n[[basic_auth]]\nrealm = \"Team \\\"A\\\"\"\ncredentials = \"/etc/users\"\n[[token_auth]]\nprefix = \"/api/\"\ntokens = \"/etc/tokens\"\nquery = \"key\"\n[[jwt_auth]]\naudience = \"a \\\"b\\\"\"\nleeway = 5\nkeys = { k1 = \"/etc/k1\", \"k 2\" = \"/etc/k2\" }\n";
+        let config = parse(source).unwrap();
+        assert_eq!(parse(&config.to_string()).unwrap(), config);
+        assert!(config.to_string().contains("read_timeout = \"1500ms\"\n"));
+    }
+
+    #[test]
+    fn test_listener_sections() {
+        let source = "[server]\nlisten = \"[::]:80\"\n[[listener]]\nname = \"nginx\"\naddress = \"unix:/run/app.sock\"\nmode = \"0660\"\n[[listener]]\naddress = \"0.0.0.0:8443\"\nproxy_protocol = true\n";
+        let config = parse(source).unwrap();
+        assert_eq!(
+            config.server.listeners,
+            [
+                ListenerConfig::new("[::]:80"),
+                ListenerConfig::new("unix:/run/app.sock")
+                    .with_name("nginx")
+                    .with_mode(0o660),
+                ListenerConfig::new("0.0.0.0:8443").with_proxy_protocol(true),
+            ]
+        );
+        assert_eq!(parse(&config.to_string()).unwrap(), config);
+        // without `listen`, the listener sections replace the default address
+        let config = parse("[[listener]]\naddress = \"unix:app.sock\"").unwrap();
+        assert_eq!(
+            config.server.listeners,
+            [ListenerConfig::new("unix:app.sock")]
+        );
+        assert_eq!(
+            error("[[listener]]\naddress = \"unix:a\"\nmode = \"rw\""),
+            "3:8: expected permissions in octal, such as \"660\""
+        );
+        assert_eq!(
+            error("[[listener]]\naddress = \"unix:a\"\n[[listener]]\naddress = \"unix:a\""),
+            "1:1: invalid listeners: two listeners are named `unix:a`"
+        );
+    }
+
+    #[test]
+    fn test_validation_errors_point_at_the_setting() {
+        assert_eq!(
+            error("[server]\nworkers = 0"),
+            "2:11: invalid workers: at least one worker is needed"
+        );
+        assert_eq!(
+            error("[limits]\nmax_connections_per_ip = 0"),
+            "2:26: invalid max_connections_per_ip: must allow at least one connection"
+        );
+        assert_eq!(
+            error("[server]\nlisten = \"localhost\""),
+            "2:10: invalid bind address `localhost`: expected host:port"
+        );
+    }
+}
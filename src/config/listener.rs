@@ -0,0 +1,155 @@
+use super::ConfigError;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::PathBuf;
+
+/// Where a listener accepts connections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddress {
+    Tcp(String),   // "host:port", e.g. "0.0.0.0:8080" or "[::]:8080"
+    Unix(PathBuf), // path of a Unix domain socket, written "unix:/run/rust_server.sock"
+}
+
+impl From<&str> for ListenAddress {
+    /// Reads an address as written in configurations: `unix:` followed by a path for a Unix
+    /// domain socket, and `host:port` otherwise.
+    fn from(addr: &str) -> Self {
+        match addr.strip_prefix("unix:") {
+            Some(path) => ListenAddress::Unix(PathBuf::from(path)),
+            None => ListenAddress::Tcp(addr.to_string()),
+        }
+    }
+}
+
+impl Display for ListenAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            ListenAddress::Tcp(addr) => write!(f, "{}", addr),
+            ListenAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// A socket the server accepts connections on, and the name handlers see it by.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::config::{ListenerConfig, ServerConfig};
+///
+/// let config = ServerConfig::new().with_listeners([
+///     ListenerConfig::new("[::]:8080").with_name("public"),
+///     ListenerConfig::new("unix:/run/rust_server.sock")
+///         .with_name("nginx")
+///         .with_mode(0o660),
+/// ]);
+/// assert!(config.validate().is_ok());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenerConfig {
+    pub name: String, // tells listeners apart in handlers and logs; unique within a configuration
+    pub address: ListenAddress,
+    pub mode: Option<u32>, // permissions given to the file of a Unix socket, e.g. 0o660
+    pub proxy_protocol: bool, // whether connections start with a PROXY protocol header
+}
+
+impl ListenerConfig {
+    /// Creates a listener named after its address.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address to listen on: `host:port` for TCP (e.g. `0.0.0.0:8080` or `[::]:8080`),
+    ///   or `unix:` followed by the path of a Unix domain socket (e.g. `unix:/run/rust_server.sock`).
+    pub fn new(addr: &str) -> Self {
+        Self {
+            name: addr.to_string(),
+            address: ListenAddress::from(addr),
+            mode: None,
+            proxy_protocol: false,
+        }
+    }
+
+    /// Sets the name handlers see the listener by.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Sets the permissions of the socket file of a Unix listener, e.g. `0o660` to let
+    /// a proxy running in the same group connect.
+    pub fn with_mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Expects every connection to start with a PROXY protocol header (version 1 or 2), as sent by
+    /// load balancers such as HAProxy. The addresses in the header replace the ones of the socket
+    /// for handlers and logs, and connections without a valid header are closed.
+    ///
+    /// # Note
+    ///
+    /// Only enable it on listeners that nothing but the load balancer can reach,
+    /// since anyone connecting could claim any address.
+    pub fn with_proxy_protocol(mut self, enabled: bool) -> Self {
+        self.proxy_protocol = enabled;
+        self
+    }
+
+    /// Checks the address and permissions of the listener.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())`, or a `ConfigError` describing the problem.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |reason: &str| ConfigError::InvalidBindAddress {
+            addr: self.address.to_string(),
+            reason: reason.to_string(),
+        };
+        if self.name.is_empty() {
+            return Err(ConfigError::invalid("listeners", "a listener has no name"));
+        }
+        // names are passed on in environment variables when the server upgrades
+        if self.name.contains(char::is_control) {
+            return Err(ConfigError::invalid(
+                "listeners",
+                "listener names cannot contain control characters",
+            ));
+        }
+        match &self.address {
+            ListenAddress::Tcp(addr) => check_tcp_addr(addr).map_err(invalid)?,
+            ListenAddress::Unix(path) => {
+                if path.as_os_str().is_empty() {
+                    return Err(invalid("the path of the socket is missing"));
+                }
+                if !cfg!(unix) {
+                    return Err(invalid(
+                        "Unix domain sockets are not supported on this system",
+                    ));
+                }
+            }
+        }
+        match self.mode {
+            Some(_) if !matches!(self.address, ListenAddress::Unix(_)) => {
+                Err(invalid("only Unix domain sockets have permissions"))
+            }
+            Some(mode) if mode > 0o777 => Err(invalid(
+                "the permissions must be between 000 and 777 (in octal)",
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks that a TCP address has the `host:port` form, with a valid port number.
+fn check_tcp_addr(addr: &str) -> Result<(), &'static str> {
+    let (host, port) = addr.rsplit_once(':').ok_or("expected host:port")?;
+    if host.is_empty() {
+        return Err("the host is missing");
+    }
+    // IPv6 addresses must be bracketed, otherwise their colons would be mistaken for the port separator
+    if host.contains(':') && !(host.starts_with('[') && host.ends_with(']')) {
+        return Err("IPv6 addresses must be written in brackets, e.g. [::1]:8080");
+    }
+    port.parse::<u16>()
+        .map_err(|_| "the port must be a number between 0 and 65535")?;
+    Ok(())
+}
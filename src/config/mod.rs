@@ -0,0 +1,485 @@
+pub use cidr::{Cidr, CidrError};
+pub use file::{
+    AccessConfig, BasicAuthConfig, ConfigFileError, FileConfig, JwtAuthConfig, RouteConfig,
+    SiteConfig, TokenAuthConfig,
+};
+pub use handler::BuildError;
+pub use listener::{ListenAddress, ListenerConfig};
+pub use toml::{Position, SourceError};
+
+mod cidr;
+mod file;
+mod handler;
+mod listener;
+mod toml;
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::net::IpAddr;
+use std::thread;
+use std::time::Duration;
+
+/// Smallest accepted `max_header_size`, enough for a request line and a few typical headers.
+pub const MIN_HEADER_SIZE: usize = 1024;
+
+/// How much the server logs about the requests it serves.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LogLevel {
+    Off,    // only errors are logged
+    Access, // one line per request: peer, request line, status and body size
+    Debug,  // the access log, plus every raw request as received
+}
+
+/// What the server does with new connections while `max_connections` are open.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Overload {
+    Pause,  // stop accepting until a connection closes, leaving new ones in the listen backlog
+    Reject, // accept them and answer 503 Service Unavailable with `Retry-After`, then close them
+}
+
+/// Settings of a `Server`: where it listens, how many requests it serves at once,
+/// and the limits protecting it from slow or abusive clients.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::config::{LogLevel, ServerConfig};
+/// use std::time::Duration;
+///
+/// let config = ServerConfig::new()
+///     .with_bind_addr("0.0.0.0:8000")
+///     .with_workers(8)
+///     .with_read_timeout(Some(Duration::from_secs(10)))
+///     .with_log_level(LogLevel::Off);
+/// assert!(config.validate().is_ok());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfig {
+    pub listeners: Vec<ListenerConfig>, // sockets to accept connections on, TCP or Unix
+    pub workers: usize,                 // number of threads serving connections
+    pub read_timeout: Option<Duration>, // longest wait for the bytes of a request, or None to wait forever
+    pub write_timeout: Option<Duration>, // longest wait while sending a response, or None to wait forever
+    pub read_buffer_size: usize,         // size of each read from a connection, in bytes
+    pub max_header_size: usize, // largest request head (request line and headers), in bytes
+    pub max_headers: usize,     // largest number of header fields in a request
+    pub keep_alive: bool,       // whether connections are reused for several requests
+    pub keep_alive_timeout: Duration, // how long an idle connection is kept open between requests
+    pub max_requests_per_connection: usize, // requests served on one connection before it is closed
+    pub log_level: LogLevel,
+    pub trusted_proxies: Vec<Cidr>, // peers whose `Forwarded` and `X-Forwarded-*` headers are believed
+    pub max_connections: Option<usize>, // connections open at once, or None for no limit
+    pub max_connections_per_ip: Option<usize>, // connections open at once from one peer address, proxies aside
+    pub overload: Overload,                    // what happens to connections over `max_connections`
+    pub overload_retry_after: Duration, // sent in `Retry-After` when a connection is rejected
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listeners: vec![ListenerConfig::new("127.0.0.1:8080")],
+            workers: thread::available_parallelism().map_or(4, |n| n.get()),
+            read_timeout: Some(Duration::from_secs(30)),
+            write_timeout: Some(Duration::from_secs(30)),
+            read_buffer_size: 8192,
+            max_header_size: 16 * 1024,
+            max_headers: 100,
+            keep_alive: true,
+            keep_alive_timeout: Duration::from_secs(5),
+            max_requests_per_connection: 100,
+            log_level: LogLevel::Access,
+            trusted_proxies: Vec::new(),
+            max_connections: None,
+            max_connections_per_ip: None,
+            overload: Overload::Pause,
+            overload_retry_after: Duration::from_secs(5),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Creates a configuration with the default settings: listening on `127.0.0.1:8080`
+    /// with one worker per CPU, 30 second timeouts, 16 KiB request heads and keep-alive enabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Listens on a single address instead of the configured listeners.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address to listen on, as `host:port` (e.g. `0.0.0.0:8080` or `[::]:8080`)
+    ///   or `unix:` followed by a socket path. The listener is named after the address.
+    pub fn with_bind_addr(mut self, addr: impl AsRef<str>) -> Self {
+        self.listeners = vec![ListenerConfig::new(addr.as_ref())];
+        self
+    }
+
+    /// Listens on several addresses instead of the configured listeners.
+    pub fn with_bind_addrs<S: AsRef<str>>(mut self, addrs: impl IntoIterator<Item = S>) -> Self {
+        self.listeners = addrs
+            .into_iter()
+            .map(|addr| ListenerConfig::new(addr.as_ref()))
+            .collect();
+        self
+    }
+
+    /// Listens with the given listeners instead of the configured ones.
+    pub fn with_listeners(mut self, listeners: impl IntoIterator<Item = ListenerConfig>) -> Self {
+        self.listeners = listeners.into_iter().collect();
+        self
+    }
+
+    /// Sets the number of threads serving connections.
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Sets how long the server waits for the bytes of a request (`None` waits forever).
+    pub fn with_read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Sets how long the server waits while sending a response (`None` waits forever).
+    pub fn with_write_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// Sets the size of each read from a connection, in bytes.
+    pub fn with_read_buffer_size(mut self, size: usize) -> Self {
+        self.read_buffer_size = size;
+        self
+    }
+
+    /// Limits the size of request heads, in bytes. Larger requests get 431 Request Header Fields Too Large.
+    pub fn with_max_header_size(mut self, size: usize) -> Self {
+        self.max_header_size = size;
+        self
+    }
+
+    /// Limits the number of header fields in a request. Requests with more get 431 Request Header Fields Too Large.
+    pub fn with_max_headers(mut self, count: usize) -> Self {
+        self.max_headers = count;
+        self
+    }
+
+    /// Enables or disables persistent connections.
+    pub fn with_keep_alive(mut self, keep_alive: bool) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Sets how long an idle connection is kept open, waiting for the next request.
+    pub fn with_keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.keep_alive_timeout = timeout;
+        self
+    }
+
+    /// Sets how many requests are served on one connection before it is closed.
+    pub fn with_max_requests_per_connection(mut self, count: usize) -> Self {
+        self.max_requests_per_connection = count;
+        self
+    }
+
+    /// Sets how much is logged about the requests served.
+    pub fn with_log_level(mut self, log_level: LogLevel) -> Self {
+        self.log_level = log_level;
+        self
+    }
+
+    /// Sets the proxies allowed to report the client of a request, by address range.
+    ///
+    /// # Note
+    ///
+    /// Requests from these addresses have their client address, scheme and host taken from
+    /// the `Forwarded` or `X-Forwarded-*` headers (see `Request::client_ip`). Connections over
+    /// Unix domain sockets come from the same host, and are trusted if loopback addresses are.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_server::config::ServerConfig;
+    ///
+    /// let ranges = ["10.0.0.0/8", "::1"].map(|range| range.parse().unwrap());
+    /// let config = ServerConfig::new().with_trusted_proxies(ranges);
+    /// assert!(config.is_trusted_proxy("10.20.30.40".parse().unwrap()));
+    /// ```
+    pub fn with_trusted_proxies(mut self, ranges: impl IntoIterator<Item = Cidr>) -> Self {
+        self.trusted_proxies = ranges.into_iter().collect();
+        self
+    }
+
+    /// Limits the number of connections open at once, and sets what happens to the others.
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - The number of connections, counting those waiting for a worker, or `None` for no limit.
+    /// * `overload` - `Overload::Pause` leaves new connections in the listen backlog of the system until
+    ///   a connection closes, slowing clients down without failing them (until the backlog is full).
+    ///   `Overload::Reject` answers them at once with 503 Service Unavailable, so clients (or a load
+    ///   balancer) can try elsewhere.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_server::config::{Overload, ServerConfig};
+    /// use std::time::Duration;
+    ///
+    /// let config = ServerConfig::new()
+    ///     .with_max_connections(Some(1000), Overload::Reject)
+    ///     .with_max_connections_per_ip(Some(20))
+    ///     .with_overload_retry_after(Duration::from_secs(10));
+    /// assert!(config.validate().is_ok());
+    /// ```
+    pub fn with_max_connections(mut self, max: Option<usize>, overload: Overload) -> Self {
+        self.max_connections = max;
+        self.overload = overload;
+        self
+    }
+
+    /// Limits the number of connections open at once from one client address. The connections over
+    /// the limit are answered with 503 Service Unavailable, since pausing would hold up every client.
+    ///
+    /// # Note
+    ///
+    /// The address is the one of the socket, as connections are counted before their requests are read.
+    /// Connections from trusted proxies and to listeners with the PROXY protocol are not counted, since
+    /// they carry the requests of many clients: behind a load balancer, the limit does not apply, and
+    /// clients are better limited by the balancer itself or by a `RateLimiter`. Connections over Unix
+    /// domain sockets are not counted either.
+    pub fn with_max_connections_per_ip(mut self, max: Option<usize>) -> Self {
+        self.max_connections_per_ip = max;
+        self
+    }
+
+    /// Sets the delay suggested to the clients of rejected connections, in `Retry-After`.
+    pub fn with_overload_retry_after(mut self, delay: Duration) -> Self {
+        self.overload_retry_after = delay;
+        self
+    }
+
+    /// Returns true if the address belongs to one of the trusted proxies.
+    pub fn is_trusted_proxy(&self, addr: IpAddr) -> bool {
+        self.trusted_proxies
+            .iter()
+            .any(|range| range.contains(addr))
+    }
+
+    /// Checks that the settings make sense together.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())`, or a `ConfigError` describing the first invalid setting.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.listeners.is_empty() {
+            return Err(ConfigError::NoBindAddress);
+        }
+        for (i, listener) in self.listeners.iter().enumerate() {
+            listener.validate()?;
+            if self.listeners[..i]
+                .iter()
+                .any(|other| other.name == listener.name)
+            {
+                return Err(ConfigError::invalid(
+                    "listeners",
+                    format!("two listeners are named `{}`", listener.name),
+                ));
+            }
+        }
+        if self.workers == 0 {
+            return Err(ConfigError::invalid(
+                "workers",
+                "at least one worker is needed",
+            ));
+        }
+        for (name, timeout) in [
+            ("read_timeout", self.read_timeout),
+            ("write_timeout", self.write_timeout),
+            ("keep_alive_timeout", Some(self.keep_alive_timeout)),
+        ] {
+            if timeout == Some(Duration::ZERO) {
+                return Err(ConfigError::invalid(name, "must be longer than zero"));
+            }
+        }
+        if self.read_buffer_size == 0 {
+            return Err(ConfigError::invalid(
+                "read_buffer_size",
+                "must be at least 1 byte",
+            ));
+        }
+        if self.max_header_size < MIN_HEADER_SIZE {
+            return Err(ConfigError::invalid(
+                "max_header_size",
+                format!("must be at least {} bytes", MIN_HEADER_SIZE),
+            ));
+        }
+        if self.max_headers == 0 {
+            return Err(ConfigError::invalid(
+                "max_headers",
+                "must allow at least one header",
+            ));
+        }
+        if self.max_requests_per_connection == 0 {
+            return Err(ConfigError::invalid(
+                "max_requests_per_connection",
+                "must allow at least one request",
+            ));
+        }
+        for (name, max) in [
+            ("max_connections", self.max_connections),
+            ("max_connections_per_ip", self.max_connections_per_ip),
+        ] {
+            if max == Some(0) {
+                return Err(ConfigError::invalid(
+                    name,
+                    "must allow at least one connection",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the settings that differ from `other` and only take effect when the server restarts,
+    /// because they are used when it starts (the listeners and the worker threads).
+    pub fn restart_required(&self, other: &ServerConfig) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if self.listeners != other.listeners {
+            names.push("listeners");
+        }
+        if self.workers != other.workers {
+            names.push("workers");
+        }
+        names
+    }
+}
+
+/// An invalid `ServerConfig` setting.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    NoBindAddress, // there is no address to listen on
+    InvalidBindAddress { addr: String, reason: String },
+    InvalidValue { name: &'static str, reason: String }, // any other setting, by field name
+}
+
+impl ConfigError {
+    fn invalid(name: &'static str, reason: impl Into<String>) -> Self {
+        ConfigError::InvalidValue {
+            name,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            ConfigError::NoBindAddress => write!(f, "no address to listen on"),
+            ConfigError::InvalidBindAddress { addr, reason } => {
+                write!(f, "invalid bind address `{}`: {}", addr, reason)
+            }
+            ConfigError::InvalidValue { name, reason } => write!(f, "invalid {}: {}", name, reason),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config_is_valid() {
+        assert_eq!(ServerConfig::new().validate(), Ok(()));
+        let config = ServerConfig::new().with_bind_addrs(["[::1]:8080", "localhost:0"]);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_bind_addresses() {
+        for addr in [
+            "localhost",
+            ":8080",
+            "127.0.0.1:http",
+            "::1:8080",
+            "127.0.0.1:70000",
+        ] {
+            let config = ServerConfig::new().with_bind_addr(addr);
+            assert!(
+                matches!(
+                    config.validate(),
+                    Err(ConfigError::InvalidBindAddress { .. })
+                ),
+                "{}",
+                addr
+            );
+        }
+        let config = ServerConfig::new().with_bind_addrs(Vec::<String>::new());
+        assert_eq!(config.validate(), Err(ConfigError::NoBindAddress));
+    }
+
+    #[test]
+    fn test_listeners() {
+        let config = ServerConfig::new().with_bind_addrs(["[::]:8080", "unix:/run/app.sock"]);
+        assert_eq!(
+            config.listeners[0].address,
+            ListenAddress::Tcp("[::]:8080".to_string())
+        );
+        assert_eq!(config.listeners[1].name, "unix:/run/app.sock");
+        assert_eq!(
+            config.listeners[1].address,
+            ListenAddress::Unix("/run/app.sock".into())
+        );
+        assert_eq!(config.validate(), Ok(()));
+
+        let config = ServerConfig::new().with_listeners([
+            ListenerConfig::new("127.0.0.1:80").with_name("web"),
+            ListenerConfig::new("[::1]:80").with_name("web"),
+        ]);
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "invalid listeners: two listeners are named `web`"
+        );
+        let config = ServerConfig::new()
+            .with_listeners([ListenerConfig::new("127.0.0.1:80").with_name("a\nb")]);
+        assert!(config.validate().is_err());
+        let config = ServerConfig::new()
+            .with_listeners([ListenerConfig::new("127.0.0.1:80").with_mode(0o600)]);
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "invalid bind address `127.0.0.1:80`: only Unix domain sockets have permissions"
+        );
+        let config = ServerConfig::new().with_bind_addr("unix:");
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InvalidBindAddress { .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_values_are_described() {
+        let error = ServerConfig::new().with_workers(0).validate().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid workers: at least one worker is needed"
+        );
+        let error = ServerConfig::new()
+            .with_read_timeout(Some(Duration::ZERO))
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid read_timeout: must be longer than zero"
+        );
+        let error = ServerConfig::new()
+            .with_max_header_size(100)
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid max_header_size: must be at least 1024 bytes"
+        );
+    }
+}
@@ -0,0 +1,798 @@
+// A subset of TOML (https://toml.io) for configuration files: comments, [tables], [[arrays of tables]],
+// key/value pairs, basic and literal strings, integers, booleans, arrays and inline tables.
+// Floats, dates, dotted keys and multi-line strings are not supported.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// A location in a source file. Lines and columns start at 1, and columns count characters.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// An error in a configuration file, at the position where it was detected.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SourceError {
+    pub position: Position,
+    pub message: String,
+}
+
+impl SourceError {
+    pub fn new(position: Position, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for SourceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Item>),
+    Table(Table),
+}
+
+impl Value {
+    /// Returns the name of the type of the value, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+            Value::Table(_) => "a table",
+        }
+    }
+}
+
+/// A value along with the position where it starts (for tables defined by a header, the header).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Item {
+    pub value: Value,
+    pub position: Position,
+}
+
+/// A key/value pair of a table.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Entry {
+    pub key: String,
+    pub position: Position, // where the key starts
+    pub item: Item,
+}
+
+/// A table, whose entries keep the order they were written in.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Table {
+    entries: Vec<Entry>,
+}
+
+impl Table {
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.key == key)
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut Entry> {
+        self.entries.iter_mut().find(|entry| entry.key == key)
+    }
+
+    /// Adds an entry, refusing to overwrite an existing key.
+    fn insert(&mut self, entry: Entry) -> Result<(), SourceError> {
+        if let Some(existing) = self.get(&entry.key) {
+            return Err(SourceError::new(
+                entry.position,
+                format!(
+                    "`{}` is already defined at line {}",
+                    entry.key, existing.position.line
+                ),
+            ));
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+}
+
+/// Parses a configuration file.
+///
+/// # Arguments
+///
+/// * `source` - The contents of the file.
+/// * `env` - Looks up environment variables, which basic strings refer to as `${NAME}`
+///   or `${NAME:-default}`. A `$` is written `\$` in basic strings; literal strings are never interpolated.
+///
+/// # Returns
+///
+/// The root table, or the first error with its position.
+pub fn parse(source: &str, env: impl Fn(&str) -> Option<String>) -> Result<Table, SourceError> {
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        index: 0,
+        line: 1,
+        column: 1,
+        env: &env,
+    };
+    parser.document()
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    index: usize,
+    line: usize,
+    column: usize,
+    env: &'a dyn Fn(&str) -> Option<String>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.index + offset).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.index += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, SourceError> {
+        Err(SourceError::new(self.position(), message))
+    }
+
+    /// Returns a description of the next character, for error messages.
+    fn found(&self) -> String {
+        match self.peek() {
+            None => "the end of the file".to_string(),
+            Some('\n') | Some('\r') => "the end of the line".to_string(),
+            Some(c) => format!("`{}`", c),
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SourceError> {
+        if self.peek() == Some(expected) {
+            self.advance();
+            Ok(())
+        } else {
+            self.error(format!("expected `{}`, found {}", expected, self.found()))
+        }
+    }
+
+    /// Skips spaces and tabs.
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.advance();
+        }
+    }
+
+    /// Skips whitespace, comments and line breaks, e.g. between the elements of an array.
+    fn skip_blank(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ') | Some('\t') | Some('\r') | Some('\n') => {
+                    self.advance();
+                }
+                Some('#') => self.skip_comment(),
+                _ => return,
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        while !matches!(self.peek(), None | Some('\n')) {
+            self.advance();
+        }
+    }
+
+    /// Checks that nothing but whitespace and a comment follows on the current line, and moves to the next one.
+    fn end_line(&mut self) -> Result<(), SourceError> {
+        self.skip_whitespace();
+        if self.peek() == Some('#') {
+            self.skip_comment();
+        }
+        if self.peek() == Some('\r') && self.peek_at(1) == Some('\n') {
+            self.advance();
+        }
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.advance();
+                Ok(())
+            }
+            _ => self.error(format!(
+                "expected the end of the line, found {}",
+                self.found()
+            )),
+        }
+    }
+
+    fn document(&mut self) -> Result<Table, SourceError> {
+        let mut root = Table::default();
+        let mut current = Vec::new(); // path of the table receiving key/value pairs, from the last header
+        let mut defined: Vec<Vec<String>> = Vec::new(); // tables defined by a [header], which cannot be defined again
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                None => return Ok(root),
+                Some('[') => current = self.header(&mut root, &mut defined)?,
+                Some(_) => {
+                    let entry = self.key_value()?;
+                    table_at(&mut root, &current).insert(entry)?;
+                    self.end_line()?;
+                }
+            }
+        }
+    }
+
+    /// Parses a `[table]` or `[[array of tables]]` header and creates the table it names.
+    ///
+    /// # Returns
+    ///
+    /// The path of the table, from the root.
+    fn header(
+        &mut self,
+        root: &mut Table,
+        defined: &mut Vec<Vec<String>>,
+    ) -> Result<Vec<String>, SourceError> {
+        let position = self.position();
+        self.expect('[')?;
+        let is_array = self.peek() == Some('[');
+        if is_array {
+            self.advance();
+        }
+        self.skip_whitespace();
+        let mut path = vec![self.key()?];
+        self.skip_whitespace();
+        while self.peek() == Some('.') {
+            self.advance();
+            self.skip_whitespace();
+            path.push(self.key()?);
+            self.skip_whitespace();
+        }
+        self.expect(']')?;
+        if is_array {
+            self.expect(']')?;
+        }
+        self.end_line()?;
+
+        let (name, parents) = path
+            .split_last()
+            .expect("a header names at least one table");
+        let parent = create_tables(root, parents, position)?;
+        let table = Item {
+            value: Value::Table(Table::default()),
+            position,
+        };
+        match parent.get_mut(name) {
+            None if is_array => parent.entries.push(Entry {
+                key: name.clone(),
+                position,
+                item: Item {
+                    value: Value::Array(vec![table]),
+                    position,
+                },
+            }),
+            None => parent.entries.push(Entry {
+                key: name.clone(),
+                position,
+                item: table,
+            }),
+            Some(Entry {
+                item:
+                    Item {
+                        value: Value::Array(tables),
+                        ..
+                    },
+                ..
+            }) if is_array
+                && tables
+                    .iter()
+                    .all(|item| matches!(item.value, Value::Table(_))) =>
+            {
+                tables.push(table)
+            }
+            Some(Entry {
+                item:
+                    Item {
+                        value: Value::Table(_),
+                        ..
+                    },
+                ..
+            }) if !is_array && !defined.contains(&path) => {}
+            Some(existing) => {
+                return Err(SourceError::new(
+                    position,
+                    format!(
+                        "`{}` is already defined at line {}",
+                        path.join("."),
+                        existing.position.line
+                    ),
+                ));
+            }
+        }
+        if !is_array {
+            defined.push(path.clone());
+        }
+        Ok(path)
+    }
+
+    /// Parses a bare (`name`, `max-size`) or quoted (`"X-Frame-Options"`) key.
+    fn key(&mut self) -> Result<String, SourceError> {
+        match self.peek() {
+            Some('"') => self.basic_string(false),
+            Some('\'') => self.literal_string(),
+            Some(c) if is_bare_key_char(c) => {
+                let mut key = String::new();
+                while let Some(c) = self.peek().filter(|c| is_bare_key_char(*c)) {
+                    key.push(c);
+                    self.advance();
+                }
+                Ok(key)
+            }
+            _ => self.error(format!("expected a key, found {}", self.found())),
+        }
+    }
+
+    fn key_value(&mut self) -> Result<Entry, SourceError> {
+        let position = self.position();
+        let key = self.key()?;
+        self.skip_whitespace();
+        if self.peek() == Some('.') {
+            return self.error("dotted keys are not supported, use a [table] instead");
+        }
+        self.expect('=')?;
+        self.skip_whitespace();
+        let item = self.value()?;
+        Ok(Entry {
+            key,
+            position,
+            item,
+        })
+    }
+
+    fn value(&mut self) -> Result<Item, SourceError> {
+        let position = self.position();
+        let value = match self.peek() {
+            Some('"') => Value::String(self.basic_string(true)?),
+            Some('\'') => Value::String(self.literal_string()?),
+            Some('[') => Value::Array(self.array()?),
+            Some('{') => Value::Table(self.inline_table()?),
+            Some(c) if c.is_ascii_digit() || c == '+' || c == '-' => {
+                Value::Integer(self.integer()?)
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let mut word = String::new();
+                while let Some(c) = self.peek().filter(char::is_ascii_alphanumeric) {
+                    word.push(c);
+                    self.advance();
+                }
+                match word.as_str() {
+                    "true" => Value::Boolean(true),
+                    "false" => Value::Boolean(false),
+                    _ => {
+                        return Err(SourceError::new(
+                            position,
+                            format!(
+                                "expected a value, found `{}` (strings must be quoted)",
+                                word
+                            ),
+                        ));
+                    }
+                }
+            }
+            _ => return self.error(format!("expected a value, found {}", self.found())),
+        };
+        Ok(Item { value, position })
+    }
+
+    fn integer(&mut self) -> Result<i64, SourceError> {
+        let position = self.position();
+        let mut digits = String::new();
+        if let Some(sign) = self.peek().filter(|c| *c == '+' || *c == '-') {
+            digits.push(sign);
+            self.advance();
+        }
+        let mut previous = None;
+        while let Some(c) = self.peek() {
+            match c {
+                '0'..='9' => digits.push(c),
+                // underscores may separate digits, as in 1_000_000
+                '_' if previous.is_some_and(|p: char| p.is_ascii_digit()) => {}
+                '.' | 'e' | 'E' => return self.error("floating point numbers are not supported"),
+                _ => break,
+            }
+            previous = Some(c);
+            self.advance();
+        }
+        if previous == Some('_') {
+            return self.error("an underscore must be followed by a digit");
+        }
+        digits
+            .parse()
+            .map_err(|_| SourceError::new(position, "invalid integer"))
+    }
+
+    /// Parses a string between double quotes, with escapes and, for values, environment variables.
+    fn basic_string(&mut self, interpolate: bool) -> Result<String, SourceError> {
+        if self.peek_at(1) == Some('"') && self.peek_at(2) == Some('"') {
+            return self.error("multi-line strings are not supported");
+        }
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            let position = self.position();
+            match self.advance() {
+                None | Some('\n') => {
+                    return Err(SourceError::new(position, "unterminated string"));
+                }
+                Some('"') => return Ok(value),
+                Some('\\') => value.push(self.escape(position)?),
+                Some('$') if interpolate && self.peek() == Some('{') => {
+                    value.push_str(&self.variable(position)?)
+                }
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    /// Parses the rest of an escape sequence, after the backslash at `position`.
+    fn escape(&mut self, position: Position) -> Result<char, SourceError> {
+        let c = match self.advance() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('$') => '$',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some(kind @ ('u' | 'U')) => {
+                let len = if kind == 'u' { 4 } else { 8 };
+                let mut hex = String::new();
+                for _ in 0..len {
+                    hex.extend(self.advance());
+                }
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| SourceError::new(position, "invalid unicode escape"))?
+            }
+            _ => return Err(SourceError::new(position, "invalid escape sequence")),
+        };
+        Ok(c)
+    }
+
+    /// Parses a `${NAME}` or `${NAME:-default}` reference, after the `$` at `position`,
+    /// and returns the value of the variable.
+    fn variable(&mut self, position: Position) -> Result<String, SourceError> {
+        self.expect('{')?;
+        let mut name = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        {
+            name.push(c);
+            self.advance();
+        }
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(SourceError::new(
+                position,
+                "invalid environment variable name",
+            ));
+        }
+        let mut default = None;
+        if self.peek() == Some(':') && self.peek_at(1) == Some('-') {
+            self.advance();
+            self.advance();
+            let mut text = String::new();
+            while let Some(c) = self.peek().filter(|c| !matches!(c, '}' | '"' | '\n')) {
+                text.push(c);
+                self.advance();
+            }
+            default = Some(text);
+        }
+        self.expect('}')?;
+        // like the shell's ${NAME:-default}, an empty variable counts as unset when there is a default
+        match ((self.env)(&name), default) {
+            (Some(value), Some(default)) if value.is_empty() => Ok(default),
+            (Some(value), _) => Ok(value),
+            (None, Some(default)) => Ok(default),
+            (None, None) => Err(SourceError::new(
+                position,
+                format!("environment variable `{}` is not set", name),
+            )),
+        }
+    }
+
+    /// Parses a string between single quotes, taken as is.
+    fn literal_string(&mut self) -> Result<String, SourceError> {
+        if self.peek_at(1) == Some('\'') && self.peek_at(2) == Some('\'') {
+            return self.error("multi-line strings are not supported");
+        }
+        self.expect('\'')?;
+        let mut value = String::new();
+        loop {
+            let position = self.position();
+            match self.advance() {
+                None | Some('\n') => {
+                    return Err(SourceError::new(position, "unterminated string"));
+                }
+                Some('\'') => return Ok(value),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    /// Parses an array, which may span several lines and end with a trailing comma.
+    fn array(&mut self) -> Result<Vec<Item>, SourceError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.peek() == Some(']') {
+                self.advance();
+                return Ok(items);
+            }
+            items.push(self.value()?);
+            self.skip_blank();
+            match self.peek() {
+                Some(',') => {
+                    self.advance();
+                }
+                Some(']') => {}
+                _ => return self.error(format!("expected `,` or `]`, found {}", self.found())),
+            }
+        }
+    }
+
+    /// Parses an inline table, e.g. `{ "Cache-Control" = "no-cache" }`, which must fit on one line.
+    fn inline_table(&mut self) -> Result<Table, SourceError> {
+        self.expect('{')?;
+        let mut table = Table::default();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.advance();
+            return Ok(table);
+        }
+        loop {
+            self.skip_whitespace();
+            table.insert(self.key_value()?)?;
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.advance();
+                }
+                Some('}') => {
+                    self.advance();
+                    return Ok(table);
+                }
+                _ => return self.error(format!("expected `,` or `}}`, found {}", self.found())),
+            }
+        }
+    }
+}
+
+fn is_bare_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Returns the table with the given path, following arrays of tables to their last element.
+fn table_at<'t>(root: &'t mut Table, path: &[String]) -> &'t mut Table {
+    let mut table = root;
+    for name in path {
+        let entry = table.get_mut(name).expect("headers create their tables");
+        table = match &mut entry.item.value {
+            Value::Table(table) => table,
+            Value::Array(items) => match items.last_mut().map(|item| &mut item.value) {
+                Some(Value::Table(table)) => table,
+                _ => unreachable!("headers only descend into arrays of tables"),
+            },
+            _ => unreachable!("headers only descend into tables"),
+        };
+    }
+    table
+}
+
+/// Returns the table with the given path, creating missing tables along the way.
+fn create_tables<'t>(
+    root: &'t mut Table,
+    path: &[String],
+    position: Position,
+) -> Result<&'t mut Table, SourceError> {
+    let mut table = root;
+    for (depth, name) in path.iter().enumerate() {
+        if table.get(name).is_none() {
+            table.entries.push(Entry {
+                key: name.clone(),
+                position,
+                item: Item {
+                    value: Value::Table(Table::default()),
+                    position,
+                },
+            });
+        }
+        let entry = table.get_mut(name).expect("just inserted");
+        let line = entry.position.line;
+        table = match &mut entry.item.value {
+            Value::Table(table) => table,
+            Value::Array(items) => match items.last_mut().map(|item| &mut item.value) {
+                Some(Value::Table(table)) => table,
+                _ => return Err(not_a_table(&path[..=depth], line, position)),
+            },
+            _ => return Err(not_a_table(&path[..=depth], line, position)),
+        };
+    }
+    Ok(table)
+}
+
+fn not_a_table(path: &[String], line: usize, position: Position) -> SourceError {
+    SourceError::new(
+        position,
+        format!(
+            "`{}` is already defined at line {} and is not a table",
+            path.join("."),
+            line
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(source: &str) -> Result<Table, SourceError> {
+        parse(source, |name| {
+            (name == "HOME").then(|| "/home/web".to_string())
+        })
+    }
+
+    fn value<'t>(table: &'t Table, key: &str) -> &'t Value {
+        &table.get(key).unwrap().item.value
+    }
+
+    fn error_at(source: &str) -> (usize, usize, String) {
+        let e = parse_str(source).unwrap_err();
+        (e.position.line, e.position.column, e.message)
+    }
+
+    #[test]
+    fn test_parse_values() {
+        let source = r#"
+# comment
+name = "site" # trailing comment
+path = 'C:\www'
+count = -1_000
+enabled = true
+list = [
+    "a", # first
+    'b',
+]
+headers = { "X-Frame-Options" = "DENY", x-test = 1 }
+"#;
+        let table = parse_str(source).unwrap();
+        assert_eq!(value(&table, "name"), &Value::String("site".to_string()));
+        assert_eq!(value(&table, "path"), &Value::String("C:\\www".to_string()));
+        assert_eq!(value(&table, "count"), &Value::Integer(-1000));
+        assert_eq!(value(&table, "enabled"), &Value::Boolean(true));
+        let Value::Array(items) = value(&table, "list") else {
+            panic!("not an array");
+        };
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].position, Position { line: 9, column: 5 });
+        let Value::Table(headers) = value(&table, "headers") else {
+            panic!("not a table");
+        };
+        assert_eq!(
+            value(headers, "X-Frame-Options"),
+            &Value::String("DENY".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_tables() {
+        let source = "[server]\nworkers = 2\n[[route]]\nprefix = \"/a\"\n[[route]]\nprefix = \"/b\"\n[route.headers]\nx = \"1\"\n";
+        let table = parse_str(source).unwrap();
+        let Value::Table(server) = value(&table, "server") else {
+            panic!("not a table");
+        };
+        assert_eq!(value(server, "workers"), &Value::Integer(2));
+        let Value::Array(routes) = value(&table, "route") else {
+            panic!("not an array");
+        };
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[1].position, Position { line: 5, column: 1 });
+        let Value::Table(second) = &routes[1].value else {
+            panic!("not a table");
+        };
+        assert!(second.get("headers").is_some());
+    }
+
+    #[test]
+    fn test_interpolates_environment_variables() {
+        let table =
+            parse_str("root = \"${HOME}/public\"\nport = \"${PORT:-8080}\"\nliteral = '${HOME}'\nprice = \"\\$5\"")
+                .unwrap();
+        assert_eq!(
+            value(&table, "root"),
+            &Value::String("/home/web/public".to_string())
+        );
+        assert_eq!(value(&table, "port"), &Value::String("8080".to_string()));
+        assert_eq!(
+            value(&table, "literal"),
+            &Value::String("${HOME}".to_string())
+        );
+        assert_eq!(value(&table, "price"), &Value::String("$5".to_string()));
+        assert_eq!(
+            error_at("a = 1\nroot = \"x${MISSING}\""),
+            (
+                2,
+                10,
+                "environment variable `MISSING` is not set".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_errors_have_positions() {
+        assert_eq!(
+            error_at("a = 1\nb = \"open"),
+            (2, 10, "unterminated string".to_string())
+        );
+        assert_eq!(
+            error_at("a = 1\na = 2"),
+            (2, 1, "`a` is already defined at line 1".to_string())
+        );
+        assert_eq!(
+            error_at("workers = four"),
+            (
+                1,
+                11,
+                "expected a value, found `four` (strings must be quoted)".to_string()
+            )
+        );
+        assert_eq!(
+            error_at("[server]\nx = 1 2"),
+            (2, 7, "expected the end of the line, found `2`".to_string())
+        );
+        assert_eq!(
+            error_at("[a]\n[a]"),
+            (2, 1, "`a` is already defined at line 1".to_string())
+        );
+        assert_eq!(error_at("list = [1 2]").2, "expected `,` or `]`, found `2`");
+        assert_eq!(
+            error_at("x = 1.5").2,
+            "floating point numbers are not supported"
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use super::server::Handler;
+use crate::compression::ContentEncoding;
+use crate::error_page::error_response;
+use crate::handler_error::HandlerError;
+use crate::http::{Method, Request, Response, StatusCode, format_http_date};
+use crate::representation::{Contents, Representation, is_spa_route};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// A file embedded in the binary, along with everything needed to serve it,
+/// computed by the build script.
+#[derive(Debug)]
+pub struct EmbeddedFile {
+    pub path: &'static str, // path relative to the public directory, e.g. "css/style.css"
+    pub content_type: &'static str,
+    pub etag: &'static str,
+    pub modified: Option<u64>, // modification time of the file at build time, in seconds since the Unix epoch
+    pub bytes: &'static [u8],
+    pub variants: &'static [EmbeddedVariant], // encoded versions, most preferred first
+}
+
+/// An encoded version of an embedded file.
+#[derive(Debug)]
+pub struct EmbeddedVariant {
+    pub encoding: ContentEncoding,
+    pub bytes: &'static [u8],
+    pub etag: &'static str, // entity tag of this representation
+}
+
+/// The public directory as it was when the binary was built.
+#[cfg(feature = "embed")]
+pub static ASSETS: &[EmbeddedFile] = include!(concat!(env!("OUT_DIR"), "/embedded_assets.rs"));
+
+/// Serves files embedded in the binary, with the same routing, compression, range and
+/// caching headers as `WebsiteHandler`, but without touching the filesystem.
+pub struct EmbeddedHandler {
+    files: &'static [EmbeddedFile],
+    spa_fallback: Option<String>, // document served for client-side routes, if enabled
+    error_pages: Vec<(StatusCode, String)>, // documents sent as the body of error responses
+}
+
+impl EmbeddedHandler {
+    /// Creates a new `EmbeddedHandler` serving the given files.
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - The files to serve, usually `ASSETS`.
+    ///
+    /// # Returns
+    ///
+    /// An `EmbeddedHandler` object.
+    pub fn new(files: &'static [EmbeddedFile]) -> Self {
+        Self {
+            files,
+            spa_fallback: None,
+            error_pages: Vec::new(),
+        }
+    }
+
+    /// Enables single-page application mode, like `WebsiteHandler::with_spa_fallback`.
+    ///
+    /// # Arguments
+    ///
+    /// * `fallback` - The path of the fallback document relative to the public directory, usually `index.html`.
+    pub fn with_spa_fallback(mut self, fallback: &str) -> Self {
+        self.spa_fallback = Some(fallback.to_string());
+        self
+    }
+
+    /// Creates a new `EmbeddedHandler` serving the public directory bundled at build time.
+    #[cfg(feature = "embed")]
+    pub fn bundled() -> Self {
+        Self::new(ASSETS)
+    }
+
+    /// Sends an embedded document as the body of error responses with the given status,
+    /// like `WebsiteHandler::with_error_page`.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The error status, e.g. `StatusCode::NotFound`.
+    /// * `file_path` - The path of the HTML document relative to the public directory, e.g. `404.html`.
+    pub fn with_error_page(mut self, status: StatusCode, file_path: &str) -> Self {
+        self.error_pages
+            .retain(|(page_status, _)| *page_status != status);
+        self.error_pages.push((status, file_path.to_string()));
+        self
+    }
+
+    /// Returns the embedded files.
+    pub fn files(&self) -> &'static [EmbeddedFile] {
+        self.files
+    }
+
+    /// Looks up a file, resolving `.` and `..` segments like the filesystem would.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - The path of the file relative to the public directory.
+    ///
+    /// # Returns
+    ///
+    /// The file, or `None` if there is no such file or the path points outside of the public directory.
+    fn find(&self, file_path: &str) -> Option<&'static EmbeddedFile> {
+        let mut segments = Vec::new();
+        for segment in file_path.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    if segments.pop().is_none() {
+                        println!("Directory Traversal Attack Attempted: {}", file_path);
+                        return None;
+                    }
+                }
+                segment => segments.push(segment),
+            }
+        }
+        let path = segments.join("/");
+        self.files.iter().find(|file| file.path == path)
+    }
+
+    /// Serves an embedded file, compressed if the client accepts it.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request being answered.
+    /// * `file_path` - The path of the file relative to the public directory.
+    ///
+    /// # Returns
+    ///
+    /// A `Response` object containing the file (or part of it), or 404 Not Found if there is no such file.
+    fn serve_file(&self, request: &Request, file_path: &str) -> Response {
+        let Some(file) = self.find(file_path) else {
+            return Response::new(StatusCode::NotFound, None);
+        };
+
+        let available: Vec<ContentEncoding> = file
+            .variants
+            .iter()
+            .map(|variant| variant.encoding)
+            .collect();
+        let encoding = ContentEncoding::negotiate(request.header("Accept-Encoding"), &available);
+        let variant = encoding.and_then(|encoding| {
+            file.variants
+                .iter()
+                .find(|variant| variant.encoding == encoding)
+        });
+        let (bytes, etag) = match variant {
+            Some(variant) => (variant.bytes, variant.etag),
+            None => (file.bytes, file.etag),
+        };
+        let representation = Representation {
+            contents: Contents::Static(bytes),
+            len: bytes.len() as u64,
+            content_type: file.content_type,
+            encoding,
+            etag: etag.to_string(),
+            last_modified: file
+                .modified
+                .map(|secs| format_http_date(UNIX_EPOCH + Duration::from_secs(secs))),
+            varies: !available.is_empty(),
+        };
+        representation.respond(request, file_path)
+    }
+}
+
+impl Handler for EmbeddedHandler {
+    /// Handles HTTP requests for the embedded website, routing them like `WebsiteHandler` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - A reference to the HTTP request.
+    ///
+    /// # Returns
+    ///
+    /// A `Response` object containing the HTTP response, or a `HandlerError` for methods other than GET.
+    fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+        let response = match request.method() {
+            Method::GET => match request.path() {
+                "/" => self.serve_file(request, "index.html"),
+                "/hello" => self.serve_file(request, "hello.html"),
+                path => match &self.spa_fallback {
+                    Some(fallback) if self.find(path).is_none() && is_spa_route(request, path) => {
+                        self.serve_file(request, fallback)
+                    }
+                    _ => self.serve_file(request, path),
+                },
+            },
+            _ => return Err(HandlerError::Status(StatusCode::NotFound)),
+        };
+        Ok(response)
+    }
+
+    /// Renders error pages from the documents configured with `with_error_page`.
+    fn render_error(&self, status: StatusCode, accept: Option<&str>) -> Response {
+        let document = self
+            .error_pages
+            .iter()
+            .find(|(page_status, _)| *page_status == status)
+            .and_then(|(_, file_path)| self.find(file_path))
+            .map(|file| file.bytes.to_vec());
+        error_response(status, accept, document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static FILES: &[EmbeddedFile] = &[
+        EmbeddedFile {
+            path: "index.html",
+            content_type: "text/html; charset=utf-8",
+            etag: "\"0-5\"",
+            modified: Some(0),
+            bytes: b"hello",
+            variants: &[],
+        },
+        EmbeddedFile {
+            path: "css/style.css",
+            content_type: "text/css; charset=utf-8",
+            etag: "\"1-a\"",
+            modified: None,
+            bytes: b"body {}   ",
+            variants: &[EmbeddedVariant {
+                encoding: ContentEncoding::Gzip,
+                bytes: b"gzipped",
+                etag: "\"1-a-gzip\"",
+            }],
+        },
+    ];
+
+    fn get(raw: &str) -> Response {
+        let request = Request::try_from(raw.as_bytes()).unwrap();
+        EmbeddedHandler::new(FILES)
+            .handle_request(&request)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_serves_embedded_files() {
+        let response = get("GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status_code(), StatusCode::Ok);
+        assert_eq!(response.body(), Some(&b"hello"[..]));
+        assert_eq!(response.header("ETag"), Some("\"0-5\""));
+        assert_eq!(
+            response.header("Last-Modified"),
+            Some("Thu, 01 Jan 1970 00:00:00 GMT")
+        );
+        assert_eq!(response.header("Vary"), None);
+
+        let response = get("GET /css/./../css//style.css HTTP/1.1\r\n\r\n");
+        assert_eq!(response.body(), Some(&b"body {}   "[..]));
+        assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+    }
+
+    #[test]
+    fn test_missing_files_are_not_found() {
+        for path in [
+            "/nope.html",
+            "/css",
+            "/../index.html",
+            "/css/../../index.html",
+        ] {
+            let response = get(&format!("GET {} HTTP/1.1\r\n\r\n", path));
+            assert_eq!(response.status_code(), StatusCode::NotFound, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_spa_fallback_serves_client_side_routes() {
+        let handler = EmbeddedHandler::new(FILES).with_spa_fallback("index.html");
+        let get = |raw: &str| {
+            let request = Request::try_from(raw.as_bytes()).unwrap();
+            handler.handle_request(&request).unwrap()
+        };
+
+        let response = get("GET /reports/42 HTTP/1.1\r\nAccept: text/html,*/*;q=0.8\r\n\r\n");
+        assert_eq!(response.status_code(), StatusCode::Ok);
+        assert_eq!(response.body(), Some(&b"hello"[..]));
+        let response = get("GET /reports/42 HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status_code(), StatusCode::Ok);
+
+        // assets and non-HTML requests still get real 404s
+        let response = get("GET /app.js HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status_code(), StatusCode::NotFound);
+        let response = get("GET /api/reports HTTP/1.1\r\nAccept: application/json\r\n\r\n");
+        assert_eq!(response.status_code(), StatusCode::NotFound);
+        let response = get("GET /css/style.css HTTP/1.1\r\n\r\n");
+        assert_eq!(response.body(), Some(&b"body {}   "[..]));
+    }
+
+    #[test]
+    fn test_serves_encoded_variants_and_ranges() {
+        let response = get("GET /css/style.css HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n");
+        assert_eq!(response.body(), Some(&b"gzipped"[..]));
+        assert_eq!(response.header("Content-Encoding"), Some("gzip"));
+        assert_eq!(response.header("ETag"), Some("\"1-a-gzip\""));
+
+        let response = get("GET /index.html HTTP/1.1\r\nRange: bytes=1-2\r\n\r\n");
+        assert_eq!(response.status_code(), StatusCode::PartialContent);
+        assert_eq!(response.body(), Some(&b"el"[..]));
+        assert_eq!(response.header("Content-Range"), Some("bytes 1-2/5"));
+    }
+}
@@ -0,0 +1,108 @@
+use crate::http::{Response, StatusCode, media_type_quality};
+use crate::json;
+
+/// Media type of problem details documents (RFC 9457).
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Returns true if the client prefers JSON over HTML, according to its `Accept` header.
+/// Without an `Accept` header, or when both are equally acceptable (e.g. `*/*`), HTML wins.
+pub fn prefers_json(accept: Option<&str>) -> bool {
+    let json = media_type_quality(accept, PROBLEM_JSON)
+        .max(media_type_quality(accept, "application/json"));
+    json > media_type_quality(accept, "text/html")
+}
+
+/// Builds the response for an error status, with a body meant for people or programs.
+///
+/// # Arguments
+///
+/// * `status` - The error status.
+/// * `accept` - The value of the request's `Accept` header, if there is one.
+/// * `document` - A custom HTML document for this status (e.g. the contents of `404.html`), if configured.
+///
+/// # Returns
+///
+/// A `Response` carrying problem details as JSON if the client prefers JSON, otherwise
+/// the custom document, or a built-in HTML page showing the status and reason phrase.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::StatusCode;
+/// use rust_server::error_page::error_response;
+///
+/// let response = error_response(StatusCode::NotFound, Some("application/json"), None);
+/// assert_eq!(response.header("Content-Type"), Some("application/problem+json"));
+/// ```
+pub fn error_response(
+    status: StatusCode,
+    accept: Option<&str>,
+    document: Option<Vec<u8>>,
+) -> Response {
+    if prefers_json(accept) {
+        return Response::new(status, Some(problem_details(status)))
+            .with_header("Content-Type", PROBLEM_JSON);
+    }
+    let body = document.unwrap_or_else(|| default_page(status).into_bytes());
+    Response::from_bytes(status, body).with_header("Content-Type", "text/html; charset=utf-8")
+}
+
+/// Renders a problem details object (RFC 9457) describing the status alone.
+fn problem_details(status: StatusCode) -> String {
+    format!(
+        "{{\"type\":\"about:blank\",\"title\":{},\"status\":{}}}",
+        json::quote(status.reason_phrase()),
+        status
+    )
+}
+
+/// Renders the built-in HTML page for a status.
+fn default_page(status: StatusCode) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{0} {1}</title>\n</head>\n<body>\n<h1>{0} {1}</h1>\n<hr>\n<p>{2}</p>\n</body>\n</html>\n",
+        status,
+        status.reason_phrase(),
+        env!("CARGO_PKG_NAME")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefers_json() {
+        assert!(!prefers_json(None));
+        assert!(!prefers_json(Some("*/*")));
+        assert!(!prefers_json(Some(
+            "text/html,application/xhtml+xml,*/*;q=0.8"
+        )));
+        assert!(prefers_json(Some("application/json")));
+        assert!(prefers_json(Some(
+            "application/problem+json, text/html;q=0.5"
+        )));
+    }
+
+    #[test]
+    fn test_error_response_negotiates_format() {
+        let response = error_response(StatusCode::NotFound, Some("application/json"), None);
+        assert_eq!(response.header("Content-Type"), Some(PROBLEM_JSON));
+        assert_eq!(
+            response.body(),
+            Some(&br#"{"type":"about:blank","title":"Not Found","status":404}"#[..])
+        );
+
+        let response = error_response(StatusCode::MethodNotAllowed, None, None);
+        assert_eq!(response.status_code(), StatusCode::MethodNotAllowed);
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+        let body = String::from_utf8(response.body().unwrap().to_vec()).unwrap();
+        assert!(body.contains("<title>405 Method Not Allowed</title>"));
+
+        let document = b"<h1>Oops</h1>".to_vec();
+        let response = error_response(StatusCode::NotFound, Some("text/html"), Some(document));
+        assert_eq!(response.body(), Some(&b"<h1>Oops</h1>"[..]));
+    }
+}
@@ -0,0 +1,350 @@
+use crate::compression::ContentEncoding;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+/// Files larger than this are not cached by default, so a single large file cannot
+/// push everything else out of the cache.
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// The size and modification time of a file, compared on every lookup to detect changes on disk.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Stamp {
+    /// Stamps a file from its metadata.
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+
+    /// Stamps the file at `path`, or returns `None` if it does not exist.
+    pub fn of(path: &Path) -> Option<Self> {
+        fs::metadata(path)
+            .ok()
+            .map(|metadata| Self::from_metadata(&metadata))
+    }
+}
+
+/// An encoded version of a cached file.
+#[derive(Debug, Clone)]
+pub struct CachedVariant {
+    pub encoding: ContentEncoding,
+    pub bytes: Arc<[u8]>,
+    pub etag: String, // entity tag of this representation
+}
+
+/// A file held in memory, along with everything needed to answer a request for it.
+#[derive(Debug, Clone)]
+pub struct CachedFile {
+    pub bytes: Arc<[u8]>,
+    pub content_type: &'static str,
+    pub etag: String,
+    pub last_modified: Option<String>, // modification date, as an HTTP-date
+    pub variants: Vec<CachedVariant>,  // encoded versions, most preferred first
+    // files the entry was built from, with their stamps at the time (None if the file was missing),
+    // so adding, removing or changing any of them (e.g. a precompressed sibling) invalidates the entry
+    pub sources: Vec<(PathBuf, Option<Stamp>)>,
+}
+
+impl CachedFile {
+    /// Returns the number of bytes the entry counts against the cache budget.
+    fn size(&self) -> u64 {
+        let variants: usize = self
+            .variants
+            .iter()
+            .map(|variant| variant.bytes.len())
+            .sum();
+        (self.bytes.len() + variants) as u64
+    }
+
+    /// Returns true if none of the source files changed since the entry was built.
+    fn is_fresh(&self) -> bool {
+        self.sources
+            .iter()
+            .all(|(path, stamp)| Stamp::of(path) == *stamp)
+    }
+}
+
+/// Counters describing how well the cache performs.
+///
+/// # Note
+///
+/// The counters are shared through an `Arc`, so they can still be read after the cache
+/// (and the handler owning it) has been moved into the server.
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64, // entries dropped because the file changed on disk
+    evictions: AtomicU64,     // entries dropped to stay within the byte budget
+}
+
+impl CacheStats {
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    pub fn invalidations(&self) -> u64 {
+        self.invalidations.load(Ordering::Relaxed)
+    }
+
+    pub fn evictions(&self) -> u64 {
+        self.evictions.load(Ordering::Relaxed)
+    }
+}
+
+/// A bounded, least-recently-used cache of files, keyed by their resolved path.
+///
+/// # Note
+///
+/// Every lookup stats the source files of the entry, so a file modified on disk is reloaded
+/// on the next request. When the total size of the entries would exceed the budget, the least
+/// recently used entries are evicted.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::{FileCache, WebsiteHandler};
+///
+/// let cache = FileCache::new(64 * 1024 * 1024).with_max_file_size(4 * 1024 * 1024);
+/// let stats = cache.stats();
+/// let handler = WebsiteHandler::new("public".to_string()).with_cache(cache);
+/// // ... later
+/// println!("{} hits, {} misses", stats.hits(), stats.misses());
+/// ```
+#[derive(Debug)]
+pub struct FileCache {
+    entries: HashMap<PathBuf, (CachedFile, u64)>, // each entry along with the tick it was last used at
+    recency: BTreeMap<u64, PathBuf>,              // paths ordered from least to most recently used
+    tick: u64,
+    size: u64,
+    max_size: u64,
+    max_file_size: u64,
+    stats: Arc<CacheStats>,
+}
+
+impl FileCache {
+    /// Creates an empty cache holding at most `max_size` bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - The byte budget, counting each file along with its encoded variants.
+    pub fn new(max_size: u64) -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            size: 0,
+            max_size,
+            max_file_size: DEFAULT_MAX_FILE_SIZE.min(max_size),
+            stats: Arc::default(),
+        }
+    }
+
+    /// Sets the size of the largest file that gets cached (1 MiB by default).
+    /// Larger files are read from disk on every request.
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = max_file_size.min(self.max_size);
+        self
+    }
+
+    pub fn max_file_size(&self) -> u64 {
+        self.max_file_size
+    }
+
+    /// Returns a handle on the hit, miss, invalidation and eviction counters.
+    pub fn stats(&self) -> Arc<CacheStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Returns the number of cached files.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the number of bytes currently cached.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Looks up a file, making it the most recently used one.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The resolved path of the file.
+    ///
+    /// # Returns
+    ///
+    /// The cached file, or `None` if it is not cached or changed on disk since it was cached
+    /// (in which case the stale entry is dropped).
+    pub fn get(&mut self, path: &Path) -> Option<&CachedFile> {
+        let Some((file, _)) = self.entries.get(path) else {
+            self.stats.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        if !file.is_fresh() {
+            self.remove(path);
+            self.stats.invalidations.fetch_add(1, Ordering::Relaxed);
+            self.stats.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        self.stats.hits.fetch_add(1, Ordering::Relaxed);
+
+        self.tick += 1;
+        let (file, last_used) = self.entries.get_mut(path)?;
+        self.recency.remove(last_used);
+        *last_used = self.tick;
+        self.recency.insert(self.tick, path.to_path_buf());
+        Some(file)
+    }
+
+    /// Adds a file to the cache, evicting the least recently used files if needed.
+    /// Files larger than the maximum file size are not cached.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The resolved path of the file.
+    /// * `file` - The file contents and metadata.
+    pub fn insert(&mut self, path: PathBuf, file: CachedFile) {
+        let size = file.size();
+        if file.bytes.len() as u64 > self.max_file_size || size > self.max_size {
+            return;
+        }
+        self.remove(&path);
+        while self.size + size > self.max_size {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.remove(&oldest);
+            self.stats.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        self.tick += 1;
+        self.size += size;
+        self.recency.insert(self.tick, path.clone());
+        self.entries.insert(path, (file, self.tick));
+    }
+
+    /// Drops the entry for `path`, if there is one.
+    fn remove(&mut self, path: &Path) {
+        if let Some((file, last_used)) = self.entries.remove(path) {
+            self.recency.remove(&last_used);
+            self.size -= file.size();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Creates an empty directory for a test under the system temporary directory.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rust_server_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn cached(path: &Path) -> CachedFile {
+        let bytes = fs::read(path).unwrap();
+        CachedFile {
+            bytes: bytes.into(),
+            content_type: "text/plain",
+            etag: "\"etag\"".to_string(),
+            last_modified: None,
+            variants: Vec::new(),
+            sources: vec![(path.to_path_buf(), Stamp::of(path))],
+        }
+    }
+
+    #[test]
+    fn test_hits_and_misses() {
+        let dir = temp_dir("cache_hits");
+        let path = dir.join("a.txt");
+        fs::write(&path, "hello").unwrap();
+
+        let mut cache = FileCache::new(1024);
+        let stats = cache.stats();
+        assert!(cache.get(&path).is_none());
+        cache.insert(path.clone(), cached(&path));
+        assert_eq!(&*cache.get(&path).unwrap().bytes, b"hello");
+        assert_eq!(&*cache.get(&path).unwrap().bytes, b"hello");
+        assert_eq!((stats.hits(), stats.misses()), (2, 1));
+        assert_eq!((cache.len(), cache.size()), (1, 5));
+    }
+
+    #[test]
+    fn test_modified_file_is_invalidated() {
+        let dir = temp_dir("cache_invalidation");
+        let path = dir.join("a.txt");
+        fs::write(&path, "old").unwrap();
+
+        let mut cache = FileCache::new(1024);
+        cache.insert(path.clone(), cached(&path));
+        thread::sleep(Duration::from_millis(10)); // make sure the modification time moves on
+        fs::write(&path, "new contents").unwrap();
+        assert!(cache.get(&path).is_none());
+        assert_eq!(cache.stats().invalidations(), 1);
+        assert!(cache.is_empty());
+
+        // a source that did not exist when the entry was built invalidates it by appearing
+        let sibling = dir.join("a.txt.gz");
+        let mut file = cached(&path);
+        file.sources.push((sibling.clone(), None));
+        cache.insert(path.clone(), file);
+        assert!(cache.get(&path).is_some());
+        fs::write(&sibling, "gz").unwrap();
+        assert!(cache.get(&path).is_none());
+    }
+
+    #[test]
+    fn test_least_recently_used_files_are_evicted() {
+        let dir = temp_dir("cache_eviction");
+        let paths: Vec<PathBuf> = (0..3).map(|i| dir.join(format!("{}.txt", i))).collect();
+        for path in &paths {
+            fs::write(path, "0123456789").unwrap();
+        }
+
+        let mut cache = FileCache::new(25);
+        cache.insert(paths[0].clone(), cached(&paths[0]));
+        cache.insert(paths[1].clone(), cached(&paths[1]));
+        assert!(cache.get(&paths[0]).is_some()); // paths[1] is now the least recently used
+        cache.insert(paths[2].clone(), cached(&paths[2]));
+
+        assert!(cache.get(&paths[1]).is_none());
+        assert!(cache.get(&paths[0]).is_some());
+        assert!(cache.get(&paths[2]).is_some());
+        assert_eq!(cache.stats().evictions(), 1);
+        assert_eq!(cache.size(), 20);
+    }
+
+    #[test]
+    fn test_large_files_are_not_cached() {
+        let dir = temp_dir("cache_large");
+        let path = dir.join("big.txt");
+        fs::write(&path, "0123456789").unwrap();
+
+        let mut cache = FileCache::new(1024).with_max_file_size(5);
+        cache.insert(path.clone(), cached(&path));
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+use crate::http::StatusCode;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Error as IoError, ErrorKind};
+
+/// An error returned by `Handler::handle_request` instead of a response.
+/// The server turns it into a response with the matching status code (and an error page),
+/// so handlers can use the `?` operator on fallible operations.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::{Handler, HandlerError, Request, Response, StatusCode};
+/// use std::fs;
+///
+/// struct ReportHandler;
+///
+/// impl Handler for ReportHandler {
+///     fn handle_request(&self, _request: &Request) -> Result<Response, HandlerError> {
+///         let bytes = fs::read("public/report.csv")?; // a missing file becomes 404 Not Found
+///         Ok(Response::from_bytes(StatusCode::Ok, bytes))
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub enum HandlerError {
+    Status(StatusCode), // an error status with nothing more to say, e.g. 404 Not Found
+    Io(IoError),        // an I/O error, mapped to a status according to its kind
+    Internal(String),   // a bug or an unexpected condition, sent as 500 Internal Server Error
+}
+
+impl HandlerError {
+    /// Returns the status code of the response sent for this error.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            HandlerError::Status(status) => *status,
+            HandlerError::Io(e) => match e.kind() {
+                // a file outside of the public directory is reported as missing, so its existence is not revealed
+                ErrorKind::NotFound | ErrorKind::PermissionDenied => StatusCode::NotFound,
+                ErrorKind::InvalidInput | ErrorKind::InvalidData => StatusCode::BadRequest,
+                _ => StatusCode::InternalServerError,
+            },
+            HandlerError::Internal(_) => StatusCode::InternalServerError,
+        }
+    }
+}
+
+impl From<IoError> for HandlerError {
+    fn from(e: IoError) -> Self {
+        HandlerError::Io(e)
+    }
+}
+
+impl From<StatusCode> for HandlerError {
+    fn from(status: StatusCode) -> Self {
+        HandlerError::Status(status)
+    }
+}
+
+impl Display for HandlerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            HandlerError::Status(status) => write!(f, "{} {}", status, status.reason_phrase()),
+            HandlerError::Io(e) => write!(f, "I/O error: {}", e),
+            HandlerError::Internal(message) => write!(f, "Internal error: {}", message),
+        }
+    }
+}
+
+impl Error for HandlerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HandlerError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_code() {
+        assert_eq!(
+            HandlerError::from(StatusCode::BadRequest).status_code(),
+            StatusCode::BadRequest
+        );
+        let missing = IoError::new(ErrorKind::NotFound, "missing");
+        assert_eq!(
+            HandlerError::from(missing).status_code(),
+            StatusCode::NotFound
+        );
+        let broken = IoError::other("disk on fire");
+        assert_eq!(
+            HandlerError::from(broken).status_code(),
+            StatusCode::InternalServerError
+        );
+        assert_eq!(
+            HandlerError::Internal("oops".to_string()).status_code(),
+            StatusCode::InternalServerError
+        );
+    }
+}
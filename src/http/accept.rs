@@ -0,0 +1,135 @@
+/// Quality value given to items without a `q` parameter, in thousandths (i.e. `q=1`).
+pub const MAX_QUALITY: u16 = 1000;
+
+/// Parses a header made of a comma-separated list of items with optional quality values,
+/// such as `Accept-Encoding: gzip;q=1.0, deflate;q=0.5, *;q=0` (RFC 9110, section 12.4.2).
+///
+/// # Arguments
+///
+/// * `value` - The header value.
+///
+/// # Returns
+///
+/// The (item, quality) pairs in the order they appear, with the quality in thousandths
+/// (so `q=0.5` becomes 500). Items with a malformed quality value are left out.
+pub fn parse_quality_list(value: &str) -> Vec<(&str, u16)> {
+    value
+        .split(',')
+        .filter_map(|element| {
+            let mut params = element.split(';');
+            let item = params.next()?.trim();
+            if item.is_empty() {
+                return None;
+            }
+            let mut quality = MAX_QUALITY;
+            for param in params {
+                if let Some((name, value)) = param.split_once('=')
+                    && name.trim().eq_ignore_ascii_case("q")
+                {
+                    quality = parse_quality(value.trim())?;
+                }
+            }
+            Some((item, quality))
+        })
+        .collect()
+}
+
+/// Checks whether an `Accept` header allows a media type (RFC 9110, section 12.5.1).
+///
+/// # Arguments
+///
+/// * `accept` - The value of the `Accept` header, if the request has one.
+/// * `media_type` - The media type to check, without parameters (e.g. `text/html`).
+///
+/// # Returns
+///
+/// `true` if there is no `Accept` header, or if the most specific range matching the media type
+/// (`text/html`, then `text/*`, then `*/*`) has a non-zero quality.
+pub fn accepts_media_type(accept: Option<&str>, media_type: &str) -> bool {
+    media_type_quality(accept, media_type) > 0
+}
+
+/// Returns the quality an `Accept` header gives to a media type, in thousandths.
+///
+/// # Arguments
+///
+/// * `accept` - The value of the `Accept` header, if the request has one.
+/// * `media_type` - The media type to check, without parameters (e.g. `application/json`).
+///
+/// # Returns
+///
+/// The quality of the most specific range matching the media type (`text/html`, then `text/*`,
+/// then `*/*`), `MAX_QUALITY` without an `Accept` header, or 0 if no range matches.
+pub fn media_type_quality(accept: Option<&str>, media_type: &str) -> u16 {
+    let Some(accept) = accept else {
+        return MAX_QUALITY;
+    };
+    let ranges = parse_quality_list(accept);
+    let quality_of = |range: &str| {
+        ranges
+            .iter()
+            .find(|(item, _)| item.eq_ignore_ascii_case(range))
+            .map(|(_, quality)| *quality)
+    };
+    let main_type = media_type.split('/').next().unwrap_or("");
+    quality_of(media_type)
+        .or_else(|| quality_of(&format!("{}/*", main_type)))
+        .or_else(|| quality_of("*/*"))
+        .unwrap_or(0)
+}
+
+/// Parses a quality value ("0", "0.5", "1.000", ...) into thousandths.
+fn parse_quality(value: &str) -> Option<u16> {
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let thousandths = format!("{:0<3}", fraction).parse::<u16>().ok()?;
+    match whole {
+        "0" => Some(thousandths),
+        "1" if thousandths == 0 => Some(MAX_QUALITY),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_quality_list() {
+        assert_eq!(
+            parse_quality_list("gzip;q=1.0, deflate; q=0.5 ,br;q=0.25, identity"),
+            vec![
+                ("gzip", 1000),
+                ("deflate", 500),
+                ("br", 250),
+                ("identity", 1000)
+            ]
+        );
+        assert_eq!(parse_quality_list("*;q=0, ,"), vec![("*", 0)]);
+    }
+
+    #[test]
+    fn test_accepts_media_type() {
+        let browser = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+        assert!(accepts_media_type(Some(browser), "text/html"));
+        assert!(accepts_media_type(None, "text/html"));
+        assert!(accepts_media_type(Some("text/*"), "text/html"));
+        assert!(!accepts_media_type(Some("application/json"), "text/html"));
+        assert!(!accepts_media_type(Some("text/html;q=0, */*"), "text/html"));
+        assert!(accepts_media_type(Some("text/html;q=0, */*"), "image/png"));
+        assert_eq!(
+            media_type_quality(Some("application/*;q=0.5, */*;q=0.1"), "application/json"),
+            500
+        );
+    }
+
+    #[test]
+    fn test_parse_quality_list_drops_invalid_qualities() {
+        assert_eq!(
+            parse_quality_list("gzip;q=2, deflate;q=0.1234, br;q=abc, zstd;q=0.9"),
+            vec![("zstd", 900)]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// How a connection reached the server.
+///
+/// # Note
+///
+/// The server speaks plain HTTP: TLS is terminated in front of it, e.g. by a reverse proxy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Tcp,  // over IPv4 or IPv6
+    Unix, // over a Unix domain socket, usually from a local proxy
+}
+
+impl Display for Transport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Transport::Tcp => write!(f, "tcp"),
+            Transport::Unix => write!(f, "unix"),
+        }
+    }
+}
+
+/// What the server knows about the connection a request came on, shared by all its requests.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::http::{ConnectionInfo, Transport};
+///
+/// let connection = ConnectionInfo::new(7, "public", Transport::Tcp)
+///     .with_peer_addr("203.0.113.9:52100")
+///     .with_local_addr("192.0.2.1:443");
+/// assert_eq!(connection.peer_ip(), Some("203.0.113.9".parse().unwrap()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    pub id: u64, // unique within the process, in the order the connections were accepted
+    pub listener: Arc<str>, // name of the listener that accepted the connection
+    pub transport: Transport,
+    pub peer_addr: String, // the client, "ip:port" over TCP and "unix:" (or "unix:path") over Unix sockets
+    pub local_addr: String, // the end of the connection on the server, in the same forms
+    pub proxy: Option<ProxyInfo>, // set when a load balancer relayed the connection with the PROXY protocol
+}
+
+impl ConnectionInfo {
+    /// Creates the description of a connection whose addresses are not known yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The number of the connection.
+    /// * `listener` - The name of the listener that accepted it.
+    /// * `transport` - The kind of socket it came on.
+    pub fn new(id: u64, listener: &str, transport: Transport) -> Self {
+        Self {
+            id,
+            listener: Arc::from(listener),
+            transport,
+            peer_addr: String::new(),
+            local_addr: String::new(),
+            proxy: None,
+        }
+    }
+
+    /// Sets the address of the client.
+    pub fn with_peer_addr(mut self, addr: impl Into<String>) -> Self {
+        self.peer_addr = addr.into();
+        self
+    }
+
+    /// Sets the address of the server end of the connection.
+    pub fn with_local_addr(mut self, addr: impl Into<String>) -> Self {
+        self.local_addr = addr.into();
+        self
+    }
+
+    /// Records that the connection was relayed by a load balancer speaking the PROXY protocol.
+    pub fn with_proxy(mut self, proxy: ProxyInfo) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Returns the IP address of the client, or `None` for connections over Unix domain sockets.
+    /// IPv4 clients of a listener on an IPv6 address (e.g. `[::]:8080`) get their IPv4 address,
+    /// not the mapped `::ffff:a.b.c.d` form.
+    pub fn peer_ip(&self) -> Option<IpAddr> {
+        self.peer_addr
+            .parse::<SocketAddr>()
+            .ok()
+            .map(|addr| addr.ip().to_canonical())
+    }
+}
+
+/// What a load balancer speaking the PROXY protocol (see `ListenerConfig::with_proxy_protocol`)
+/// said about a connection it relayed. The addresses it reported replace the ones of the socket
+/// in `ConnectionInfo`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyInfo {
+    pub proxy_addr: String, // the load balancer, which the socket is actually connected to
+    pub tlvs: Vec<(u8, Vec<u8>)>, // type-length-value extensions of a version 2 header, in order
+}
+
+impl ProxyInfo {
+    /// Returns the value of the first extension of the given type, e.g. `0x01` for the ALPN protocol.
+    pub fn tlv(&self, kind: u8) -> Option<&[u8]> {
+        self.tlvs
+            .iter()
+            .find(|(tlv, _)| *tlv == kind)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// Returns the host name the client asked for when it connected over TLS (its SNI), if reported.
+    pub fn authority(&self) -> Option<&str> {
+        std::str::from_utf8(self.tlv(0x02)?).ok()
+    }
+
+    /// Returns true if the client connected to the load balancer over TLS, which it terminated.
+    pub fn is_tls(&self) -> bool {
+        // PP2_TYPE_SSL starts with flags, of which PP2_CLIENT_SSL is the lowest bit
+        self.tlv(0x20)
+            .and_then(|ssl| ssl.first())
+            .is_some_and(|flags| flags & 0x01 != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_ip() {
+        let connection = ConnectionInfo::new(1, "web", Transport::Tcp);
+        assert_eq!(
+            connection.clone().with_peer_addr("[::1]:80").peer_ip(),
+            Some("::1".parse().unwrap())
+        );
+        // IPv4 clients of a dual-stack listener
+        let mapped = connection.clone().with_peer_addr("[::ffff:192.0.2.7]:80");
+        assert_eq!(mapped.peer_ip(), Some("192.0.2.7".parse().unwrap()));
+        let unix = ConnectionInfo::new(2, "proxy", Transport::Unix).with_peer_addr("unix:");
+        assert_eq!(unix.peer_ip(), None);
+    }
+
+    #[test]
+    fn test_proxy_tlvs() {
+        let proxy = ProxyInfo {
+            proxy_addr: "10.0.0.1:40000".to_string(),
+            tlvs: vec![
+                (0x02, b"example.com".to_vec()),
+                (0x20, vec![0x07, 0, 0, 0, 0]),
+            ],
+        };
+        assert_eq!(proxy.authority(), Some("example.com"));
+        assert!(proxy.is_tls());
+        assert_eq!(proxy.tlv(0x01), None);
+    }
+}
@@ -0,0 +1,180 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAY_NAMES: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"]; // 1970-01-01 was a Thursday
+const MONTH_NAMES: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Converts a number of days since 1970-01-01 into a (year, month, day) civil date.
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097); // day of era [0, 146096]
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365; // year of era [0, 399]
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100); // day of year [0, 365]
+    let mp = (5 * doy + 2) / 153; // month starting from March [0, 11]
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Inverse of `civil_from_days`: converts a civil date into days since 1970-01-01.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Formats a timestamp as an HTTP-date in the preferred IMF-fixdate format,
+/// e.g. `Sun, 06 Nov 1994 08:49:37 GMT` (RFC 9110, section 5.6.7).
+/// Times before the Unix epoch are clamped to the epoch.
+///
+/// # Arguments
+///
+/// * `time` - The timestamp to format.
+pub fn format_http_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let days = (secs / 86_400) as i64;
+    let secs_of_day = secs % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        DAY_NAMES[(days % 7) as usize],
+        day,
+        MONTH_NAMES[(month - 1) as usize],
+        year,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Parses an HTTP-date in the IMF-fixdate format produced by `format_http_date`.
+///
+/// # Arguments
+///
+/// * `value` - The header value to parse, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+///
+/// # Returns
+///
+/// The parsed timestamp, or `None` if the value is not a valid IMF-fixdate.
+/// The obsolete RFC 850 and asctime formats are not accepted.
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let (_, rest) = value.trim().split_once(", ")?;
+    let mut parts = rest.split(' ');
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month_name = parts.next()?;
+    let month = MONTH_NAMES.iter().position(|m| *m == month_name)? as u32 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut clock = parts.next()?.split(':').map(|p| p.parse::<u64>().ok());
+    let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next()??);
+    if parts.next()? != "GMT" || parts.next().is_some() || clock.next().is_some() {
+        return None;
+    }
+    if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 || year < 1970 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day) as u64;
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Parses a date, or a date and time in UTC, in the format of RFC 3339: `2026-12-31` (the start of the day)
+/// or `2026-12-31T23:59:59Z`. Fractional seconds and other time zones are not accepted.
+///
+/// # Returns
+///
+/// The parsed timestamp, or `None` if the value has another format or is before 1970.
+pub fn parse_rfc3339(value: &str) -> Option<SystemTime> {
+    let (date, time) = match value.trim().split_once(['T', 't']) {
+        Some((date, time)) => (date, Some(time.strip_suffix(['Z', 'z'])?)),
+        None => (value.trim(), None),
+    };
+    let number = |field: &str, len: usize| -> Option<u64> {
+        (field.len() == len && field.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| field.parse().ok())?
+    };
+    let mut fields = date.split('-');
+    let year = number(fields.next()?, 4)?;
+    let month = number(fields.next()?, 2)?;
+    let day = number(fields.next()?, 2)?;
+    if fields.next().is_some()
+        || year < 1970
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+    {
+        return None;
+    }
+    let mut secs = 0;
+    if let Some(time) = time {
+        let mut fields = time.split(':');
+        let hour = number(fields.next()?, 2)?;
+        let minute = number(fields.next()?, 2)?;
+        let second = number(fields.next()?, 2)?;
+        if fields.next().is_some() || hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        secs = hour * 3600 + minute * 60 + second;
+    }
+    let days = days_from_civil(year as i64, month as u32, day as u32) as u64;
+    Some(UNIX_EPOCH + Duration::from_secs(days * 86_400 + secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_http_date() {
+        let time = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        assert_eq!(format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(
+            format_http_date(UNIX_EPOCH),
+            "Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+    }
+
+    #[test]
+    fn test_parse_http_date_round_trip() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_251_199); // 2024-02-29 23:59:59, a leap day
+        assert_eq!(parse_http_date(&format_http_date(time)), Some(time));
+    }
+
+    #[test]
+    fn test_parse_http_date_rejects_other_formats() {
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 PST"), None);
+        assert_eq!(parse_http_date("\"abc\""), None);
+    }
+
+    #[test]
+    fn test_parse_rfc3339() {
+        let leap_day = UNIX_EPOCH + Duration::from_secs(1_709_164_800); // 2024-02-29
+        assert_eq!(parse_rfc3339("2024-02-29"), Some(leap_day));
+        assert_eq!(
+            parse_rfc3339("2024-02-29T23:59:59Z"),
+            Some(leap_day + Duration::from_secs(86_399))
+        );
+        for invalid in [
+            "2024-2-29",
+            "2024-02-29T23:59:59",
+            "2024-02-29T23:59:59+01:00",
+            "2024-13-01",
+            "1969-12-31",
+            "2024-02-29T24:00:00Z",
+        ] {
+            assert_eq!(parse_rfc3339(invalid), None, "{}", invalid);
+        }
+    }
+}
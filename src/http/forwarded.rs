@@ -0,0 +1,245 @@
+use super::Request;
+use std::net::{IpAddr, SocketAddr};
+
+/// The client of a request that went through proxies, as they reported it in the `Forwarded`
+/// (RFC 7239) or `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host` headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Forwarded<'buf> {
+    pub client: IpAddr, // the address of the client, or of the last proxy that knew it
+    pub proto: Option<&'buf str>, // "http" or "https", the scheme the client used
+    pub host: Option<&'buf str>, // the `Host` the client sent, e.g. "example.com"
+}
+
+/// One proxy hop: the address it received the request from, and what it said about the request.
+struct Hop<'buf> {
+    addr: Option<IpAddr>, // None for "unknown" and obfuscated identifiers such as "_hidden"
+    proto: Option<&'buf str>,
+    host: Option<&'buf str>,
+}
+
+/// Works out the client of a request received from a proxy, trusting the forwarding headers only
+/// as far as the chain of proxies is trusted.
+///
+/// # Arguments
+///
+/// * `request` - The request.
+/// * `peer` - The address the request came from, i.e. the last proxy.
+/// * `is_trusted` - Tells whether an address belongs to a trusted proxy.
+///
+/// # Returns
+///
+/// `None` if the peer is not a trusted proxy or sent no forwarding header. Otherwise the hops are
+/// walked back from the peer while they are trusted proxies: the first address that is not one
+/// is the client, along with the scheme and host reported for it. `Forwarded` takes precedence
+/// over the `X-Forwarded-*` headers when both are present.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::Request;
+/// use rust_server::http::forwarded_client;
+/// use std::net::IpAddr;
+///
+/// let head = b"GET / HTTP/1.1\r\nForwarded: for=203.0.113.9;proto=https, for=10.0.0.2\r\n\r\n";
+/// let request = Request::try_from(&head[..]).unwrap();
+/// let is_trusted = |ip: IpAddr| ip.to_string().starts_with("10.");
+/// let forwarded = forwarded_client(&request, "10.0.0.1".parse().unwrap(), is_trusted).unwrap();
+/// assert_eq!(forwarded.client, "203.0.113.9".parse::<IpAddr>().unwrap());
+/// assert_eq!(forwarded.proto, Some("https"));
+/// ```
+pub fn forwarded_client<'buf>(
+    request: &Request<'buf>,
+    peer: IpAddr,
+    is_trusted: impl Fn(IpAddr) -> bool,
+) -> Option<Forwarded<'buf>> {
+    if !is_trusted(peer) {
+        return None; // anyone can send the headers; only proxies we run get to set them
+    }
+    let hops = forwarded_hops(request).or_else(|| x_forwarded_hops(request))?;
+    let mut forwarded = Forwarded {
+        client: peer,
+        proto: None,
+        host: None,
+    };
+    for hop in hops.iter().rev() {
+        forwarded.proto = hop.proto;
+        forwarded.host = hop.host;
+        match hop.addr {
+            Some(addr) => forwarded.client = addr.to_canonical(),
+            // the client is hidden; the proxy that received the request from it is as far as we know
+            None => break,
+        }
+        if !is_trusted(forwarded.client) {
+            break;
+        }
+    }
+    Some(forwarded)
+}
+
+/// Returns the values of every header with the given name, split on commas, in order.
+fn list_values<'buf>(request: &Request<'buf>, name: &str) -> Vec<&'buf str> {
+    request
+        .headers()
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+        .flat_map(|(_, value)| value.split(','))
+        .map(str::trim)
+        .collect()
+}
+
+/// Reads the hops of the `Forwarded` headers, e.g. `for=192.0.2.60;proto=https, for="[2001:db8::17]:4711"`.
+fn forwarded_hops<'buf>(request: &Request<'buf>) -> Option<Vec<Hop<'buf>>> {
+    let elements = list_values(request, "Forwarded");
+    if elements.is_empty() {
+        return None;
+    }
+    let hops = elements
+        .into_iter()
+        .map(|element| {
+            let mut hop = Hop {
+                addr: None,
+                proto: None,
+                host: None,
+            };
+            for pair in element.split(';') {
+                let Some((name, value)) = pair.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim().trim_matches('"');
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "for" => hop.addr = parse_node(value),
+                    "proto" => hop.proto = checked_proto(value),
+                    "host" => hop.host = checked_host(value),
+                    _ => {}
+                }
+            }
+            hop
+        })
+        .collect();
+    Some(hops)
+}
+
+/// Reads the hops of the `X-Forwarded-For` header, each proxy having appended the address it received
+/// the request from. `X-Forwarded-Proto` and `X-Forwarded-Host`, when they list several values,
+/// are matched with the addresses from the right.
+fn x_forwarded_hops<'buf>(request: &Request<'buf>) -> Option<Vec<Hop<'buf>>> {
+    let addrs = list_values(request, "X-Forwarded-For");
+    if addrs.is_empty() {
+        return None;
+    }
+    let protos = list_values(request, "X-Forwarded-Proto");
+    let hosts = list_values(request, "X-Forwarded-Host");
+    let count = addrs.len();
+    // the hop `i` counts from the left, `count - 1 - i` from the right; short lists were set
+    // by the first proxy only, so the hops without a value of their own use the leftmost one
+    let value_at = |values: &[&'buf str], i: usize| {
+        let from_right = count - 1 - i;
+        values
+            .len()
+            .checked_sub(1 + from_right)
+            .and_then(|j| values.get(j))
+            .or(values.first())
+            .copied()
+    };
+    let hops = addrs
+        .iter()
+        .enumerate()
+        .map(|(i, addr)| Hop {
+            addr: parse_node(addr),
+            proto: value_at(&protos, i).and_then(checked_proto),
+            host: value_at(&hosts, i).and_then(checked_host),
+        })
+        .collect();
+    Some(hops)
+}
+
+/// Parses the address of a node: `192.0.2.60`, `192.0.2.60:4711`, `[2001:db8::17]:4711`,
+/// or a bare IPv6 address as `X-Forwarded-For` writes them.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(addr) = node.parse::<IpAddr>() {
+        return Some(addr);
+    }
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+    // an IPv6 address in brackets without a port
+    node.strip_prefix('[')?.strip_suffix(']')?.parse().ok()
+}
+
+/// Accepts the schemes a client can use to reach the server.
+fn checked_proto(proto: &str) -> Option<&str> {
+    ["http", "https"]
+        .into_iter()
+        .find(|scheme| proto.eq_ignore_ascii_case(scheme))
+}
+
+/// Accepts a host name or address, with an optional port, as sent in the `Host` header.
+fn checked_host(host: &str) -> Option<&str> {
+    let valid = !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".-_:[]".contains(c));
+    valid.then_some(host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(head: &str) -> Request<'_> {
+        Request::try_from(head.as_bytes()).unwrap()
+    }
+
+    fn is_trusted(ip: IpAddr) -> bool {
+        ip.is_loopback() || matches!(ip, IpAddr::V4(v4) if v4.is_private())
+    }
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn test_forwarded_header() {
+        let head = "GET / HTTP/1.1\r\nForwarded: for=198.51.100.4;proto=http, for=\"[2001:db8::17]:4711\";proto=HTTPS;host=example.com\r\nForwarded: for=10.0.0.5\r\n\r\n";
+        let forwarded = forwarded_client(&request(head), ip("127.0.0.1"), is_trusted).unwrap();
+        // 2001:db8::17 is the first untrusted hop: the client is not 198.51.100.4, which it could have made up
+        assert_eq!(forwarded.client, ip("2001:db8::17"));
+        assert_eq!(forwarded.proto, Some("https"));
+        assert_eq!(forwarded.host, Some("example.com"));
+
+        // a hidden client stops the walk at the proxy that hid it
+        let head = "GET / HTTP/1.1\r\nForwarded: for=unknown, for=10.0.0.5\r\n\r\n";
+        let forwarded = forwarded_client(&request(head), ip("127.0.0.1"), is_trusted).unwrap();
+        assert_eq!(forwarded.client, ip("10.0.0.5"));
+    }
+
+    #[test]
+    fn test_x_forwarded_headers() {
+        let head = "GET / HTTP/1.1\r\nX-Forwarded-For: 203.0.113.9, 2001:db8::1, 10.1.1.1\r\nX-Forwarded-Proto: https\r\nX-Forwarded-Host: shop.example\r\n\r\n";
+        let forwarded = forwarded_client(&request(head), ip("10.0.0.1"), is_trusted).unwrap();
+        assert_eq!(forwarded.client, ip("2001:db8::1"));
+        assert_eq!(forwarded.proto, Some("https"));
+        assert_eq!(forwarded.host, Some("shop.example"));
+
+        // every hop is trusted: the leftmost address is the client
+        let head = "GET / HTTP/1.1\r\nX-Forwarded-For: 192.168.0.7, 10.1.1.1\r\nX-Forwarded-Proto: ftp\r\n\r\n";
+        let forwarded = forwarded_client(&request(head), ip("10.0.0.1"), is_trusted).unwrap();
+        assert_eq!(forwarded.client, ip("192.168.0.7"));
+        assert_eq!(forwarded.proto, None);
+    }
+
+    #[test]
+    fn test_untrusted_peers_are_not_believed() {
+        let head = "GET / HTTP/1.1\r\nX-Forwarded-For: 10.0.0.1\r\nForwarded: for=10.0.0.1\r\n\r\n";
+        assert_eq!(
+            forwarded_client(&request(head), ip("203.0.113.9"), is_trusted),
+            None
+        );
+        // a trusted peer that forwards nothing is the client itself
+        let head = "GET / HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            forwarded_client(&request(head), ip("10.0.0.1"), is_trusted),
+            None
+        );
+    }
+}
@@ -0,0 +1,90 @@
+/// Media type used when the extension of a file is unknown.
+pub const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// Returns the media type to send in `Content-Type` for a file, based on its extension.
+///
+/// # Arguments
+///
+/// * `path` - The path (or just the name) of the file, e.g. `videos/intro.mp4`.
+///
+/// # Returns
+///
+/// The media type, or `application/octet-stream` if the extension is unknown.
+/// Text types carry a `charset=utf-8` parameter.
+pub fn mime_type(path: &str) -> &'static str {
+    let extension = match path.rsplit_once('.') {
+        // a dot inside a directory name (e.g. "v1.2/readme") is not an extension
+        Some((_, ext)) if !ext.contains('/') => ext.to_ascii_lowercase(),
+        _ => return DEFAULT_MIME_TYPE,
+    };
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "ogv" => "video/ogg",
+        "mov" => "video/quicktime",
+        "mp3" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "ogg" | "oga" => "audio/ogg",
+        "wav" => "audio/wav",
+        "vtt" => "text/vtt; charset=utf-8",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        _ => DEFAULT_MIME_TYPE,
+    }
+}
+
+/// Returns true if responses of the given media type are worth compressing.
+/// Text formats shrink a lot, while images, audio, video and archives are already compressed.
+///
+/// # Arguments
+///
+/// * `content_type` - The media type, possibly with parameters (e.g. `text/html; charset=utf-8`).
+pub fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+    essence.starts_with("text/")
+        || essence.ends_with("+xml")
+        || essence.ends_with("+json")
+        || matches!(
+            essence,
+            "application/json" | "application/xml" | "application/javascript" | "application/wasm"
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mime_type() {
+        assert_eq!(mime_type("index.html"), "text/html; charset=utf-8");
+        assert_eq!(mime_type("/videos/Intro.MP4"), "video/mp4");
+        assert_eq!(mime_type("/v1.2/README"), DEFAULT_MIME_TYPE);
+        assert_eq!(mime_type("Makefile"), DEFAULT_MIME_TYPE);
+    }
+
+    #[test]
+    fn test_is_compressible() {
+        assert!(is_compressible(mime_type("style.css")));
+        assert!(is_compressible(mime_type("logo.svg")));
+        assert!(is_compressible("application/json"));
+        assert!(!is_compressible(mime_type("intro.mp4")));
+        assert!(!is_compressible(DEFAULT_MIME_TYPE));
+    }
+}
@@ -1,12 +1,36 @@
+pub use accept::{accepts_media_type, media_type_quality, parse_quality_list};
+pub use connection::{ConnectionInfo, ProxyInfo, Transport};
+pub use date::format_http_date;
+pub use forwarded::{Forwarded, forwarded_client};
 pub use method::Method;
-pub use query_string::{QueryString, Value};
+pub use mime::{is_compressible, mime_type};
+pub use principal::Principal;
+pub use query_string::QueryString;
+pub use range::{
+    ByteRange, coalesce_ranges, generate_boundary, multipart_byteranges, multipart_byteranges_len,
+    parse_range_header,
+};
 pub use request::ParseError;
 pub use request::Request;
 pub use response::Response;
 pub use status_code::StatusCode;
+pub use {
+    accept::MAX_QUALITY,
+    date::{parse_http_date, parse_rfc3339},
+    mime::DEFAULT_MIME_TYPE,
+    query_string::Value,
+    range::{MAX_RANGES, RangeError},
+};
 
+mod accept;
+mod connection;
+mod date;
+mod forwarded;
 mod method;
+mod mime;
+mod principal;
 mod query_string;
+mod range;
 mod request;
 mod response;
 mod status_code;
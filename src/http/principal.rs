@@ -0,0 +1,52 @@
+use crate::json;
+use std::time::SystemTime;
+
+/// The client a request was authenticated as, which handlers find with `Request::principal`.
+/// Authentication handlers such as `auth::BasicAuth` make them, and `auth` re-exports the type.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::{Handler, HandlerError, Request, Response, StatusCode};
+///
+/// struct WhoAmI;
+///
+/// impl Handler for WhoAmI {
+///     fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+///         let name = request.principal().map_or("anonymous", |principal| &principal.name);
+///         Ok(Response::new(StatusCode::Ok, Some(name.to_string())))
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Principal {
+    pub name: String, // the user of Basic credentials, the name of a token in its store, or the subject of a JWT
+    pub scopes: Vec<String>, // what the client is allowed to do, e.g. "read" or "deploy"
+    pub expires: Option<SystemTime>, // when the credentials stop being valid, if ever
+    pub claims: Vec<(String, json::Value)>, // every claim of a JWT, empty for other credentials
+}
+
+impl Principal {
+    /// Creates a principal without scopes that does not expire.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            scopes: Vec::new(),
+            expires: None,
+            claims: Vec::new(),
+        }
+    }
+
+    /// Returns true if the principal was granted a scope.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
+
+    /// Returns a claim of the JWT the principal was authenticated with, such as `email` or `groups`.
+    pub fn claim(&self, name: &str) -> Option<&json::Value> {
+        self.claims
+            .iter()
+            .find(|(claim, _)| claim == name)
+            .map(|(_, value)| value)
+    }
+}
@@ -27,7 +27,7 @@ impl<'buf> QueryString<'buf> {
     /// # Returns
     ///
     /// An `Option` containing a reference to the `Value` if the key exists, or `None` otherwise.
-    pub fn get(&self, key: &str) -> Option<&Value> {
+    pub fn get(&self, key: &str) -> Option<&Value<'buf>> {
         self.data.get(key)
     }
 }
@@ -52,7 +52,10 @@ impl<'buf> From<&'buf str> for QueryString<'buf> {
     /// # Examples
     ///
     /// ```
+    /// use rust_server::http::{QueryString, Value};
+    ///
     /// let query = QueryString::from("name=John&age=30");
+    /// assert_eq!(query.get("name"), Some(&Value::Single("John")));
     /// ```
     fn from(s: &'buf str) -> Self {
         // Initialize an empty HashMap to store the key-value pairs
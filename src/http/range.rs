@@ -0,0 +1,368 @@
+use std::collections::hash_map::RandomState;
+use std::fmt::{self, Display};
+use std::hash::{BuildHasher, Hasher};
+
+/// The maximum number of ranges accepted in a single `Range` header.
+/// Clients asking for more are served the full representation instead, which bounds the work
+/// of parsing the header. It says nothing of the bytes the ranges cover: see `coalesce_ranges`.
+pub const MAX_RANGES: usize = 32;
+
+/// An inclusive byte range within a representation, e.g. `0-499` for the first 500 bytes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ByteRange {
+    pub start: u64, // offset of the first byte
+    pub end: u64,   // offset of the last byte (inclusive)
+}
+
+impl ByteRange {
+    /// Returns the number of bytes covered by the range.
+    // an inclusive range always covers at least one byte, so there is no `is_empty`
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// Returns the value of the `Content-Range` header for this range,
+    /// e.g. `bytes 0-499/1234`.
+    ///
+    /// # Arguments
+    ///
+    /// * `total` - The full length of the representation.
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+/// Reasons a `Range` header could not be turned into a list of byte ranges.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RangeError {
+    UnsupportedUnit, // the range unit is not `bytes`, so the header should be ignored
+    TooManyRanges,   // more than `MAX_RANGES` ranges, so the header should be ignored
+    Invalid,         // the header is syntactically invalid
+    Unsatisfiable,   // none of the ranges overlap the representation
+}
+
+impl RangeError {
+    /// Returns true if the request should be answered as if it had no `Range` header,
+    /// rather than with `416 Range Not Satisfiable`.
+    pub fn is_ignorable(&self) -> bool {
+        matches!(
+            self,
+            RangeError::UnsupportedUnit | RangeError::TooManyRanges
+        )
+    }
+}
+
+impl Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            RangeError::UnsupportedUnit => "The range unit is not supported",
+            RangeError::TooManyRanges => "Too many ranges were requested",
+            RangeError::Invalid => "The range is invalid",
+            RangeError::Unsatisfiable => "The range is not satisfiable",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+/// Parses a `Range` header value (RFC 9110, section 14.2) against a representation of `len` bytes.
+///
+/// # Arguments
+///
+/// * `value` - The header value, e.g. `bytes=0-499, -500`.
+/// * `len` - The full length of the representation.
+///
+/// # Returns
+///
+/// The satisfiable ranges in the order they were requested, clamped to the representation.
+/// Ranges that start past the end are dropped; if none are left the result is `RangeError::Unsatisfiable`.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::http::{ByteRange, parse_range_header};
+///
+/// let ranges = parse_range_header("bytes=0-99,-100", 1000).unwrap();
+/// assert_eq!(ranges, vec![ByteRange { start: 0, end: 99 }, ByteRange { start: 900, end: 999 }]);
+/// ```
+pub fn parse_range_header(value: &str, len: u64) -> Result<Vec<ByteRange>, RangeError> {
+    let (unit, specs) = value.trim().split_once('=').ok_or(RangeError::Invalid)?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return Err(RangeError::UnsupportedUnit);
+    }
+
+    let mut ranges = Vec::new();
+    let mut count = 0;
+    // empty list elements (e.g. "bytes=0-1,,5-6") are allowed by the list syntax, so skip them
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        count += 1;
+        if count > MAX_RANGES {
+            return Err(RangeError::TooManyRanges);
+        }
+        let (first, last) = spec.split_once('-').ok_or(RangeError::Invalid)?;
+        let range = if first.is_empty() {
+            // suffix range: "-500" means the last 500 bytes
+            let suffix = parse_position(last)?;
+            if suffix == 0 || len == 0 {
+                continue;
+            }
+            ByteRange {
+                start: len.saturating_sub(suffix),
+                end: len - 1,
+            }
+        } else {
+            let start = parse_position(first)?;
+            let end = if last.is_empty() {
+                u64::MAX // open-ended range: "500-" means from byte 500 to the end
+            } else {
+                parse_position(last)?
+            };
+            if end < start {
+                return Err(RangeError::Invalid);
+            }
+            if start >= len {
+                continue;
+            }
+            ByteRange {
+                start,
+                end: end.min(len - 1),
+            }
+        };
+        ranges.push(range);
+    }
+
+    if count == 0 {
+        return Err(RangeError::Invalid);
+    }
+    if ranges.is_empty() {
+        return Err(RangeError::Unsatisfiable);
+    }
+    Ok(ranges)
+}
+
+/// Merges the ranges that overlap or touch, e.g. `0-99,50-149,150-199` into `0-199`, so that no byte
+/// is sent twice (RFC 9110, section 14.2 lets servers coalesce ranges).
+///
+/// # Returns
+///
+/// The remaining ranges, in ascending order.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::http::{ByteRange, coalesce_ranges};
+///
+/// let ranges = vec![ByteRange { start: 50, end: 149 }, ByteRange { start: 0, end: 99 }];
+/// assert_eq!(coalesce_ranges(ranges), vec![ByteRange { start: 0, end: 149 }]);
+/// ```
+pub fn coalesce_ranges(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Parses a byte position, which must consist of ASCII digits only (no sign, no whitespace).
+fn parse_position(s: &str) -> Result<u64, RangeError> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(RangeError::Invalid);
+    }
+    s.parse().map_err(|_| RangeError::Invalid)
+}
+
+/// Generates a boundary string for a `multipart/byteranges` body.
+/// The boundary is random so it is very unlikely to appear inside the parts themselves.
+pub fn generate_boundary() -> String {
+    // RandomState is seeded randomly for every instance, which makes it a cheap source of randomness
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    let high = hasher.finish();
+    hasher.write_u64(1);
+    format!("{:016x}{:016x}", high, hasher.finish())
+}
+
+/// Builds a `multipart/byteranges` body (RFC 9110, section 14.6) out of the given parts.
+/// The matching `Content-Type` header is `multipart/byteranges; boundary=<boundary>`.
+///
+/// # Arguments
+///
+/// * `boundary` - The boundary separating the parts, usually from `generate_boundary`.
+/// * `content_type` - The media type of the full representation, repeated in every part.
+/// * `total` - The full length of the representation.
+/// * `parts` - The ranges along with the bytes they cover.
+pub fn multipart_byteranges(
+    boundary: &str,
+    content_type: &str,
+    total: u64,
+    parts: &[(ByteRange, &[u8])],
+) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (range, bytes) in parts {
+        body.extend_from_slice(part_header(boundary, content_type, total, *range).as_bytes());
+        body.extend_from_slice(bytes);
+    }
+    body.extend_from_slice(closing_delimiter(boundary).as_bytes());
+    body
+}
+
+/// Returns the length of the body `multipart_byteranges` builds out of the given ranges,
+/// without reading them.
+pub fn multipart_byteranges_len(
+    boundary: &str,
+    content_type: &str,
+    total: u64,
+    ranges: &[ByteRange],
+) -> u64 {
+    ranges
+        .iter()
+        .map(|range| part_header(boundary, content_type, total, *range).len() as u64 + range.len())
+        .sum::<u64>()
+        + closing_delimiter(boundary).len() as u64
+}
+
+/// Returns the delimiter and headers preceding a part of a `multipart/byteranges` body.
+fn part_header(boundary: &str, content_type: &str, total: u64, range: ByteRange) -> String {
+    format!(
+        "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+        boundary,
+        content_type,
+        range.content_range(total)
+    )
+}
+
+/// Returns the delimiter ending a `multipart/byteranges` body.
+fn closing_delimiter(boundary: &str) -> String {
+    format!("\r\n--{}--\r\n", boundary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn test_parse_single_ranges() {
+        assert_eq!(
+            parse_range_header("bytes=0-499", 1000),
+            Ok(vec![range(0, 499)])
+        );
+        assert_eq!(
+            parse_range_header("bytes=500-", 1000),
+            Ok(vec![range(500, 999)])
+        );
+        assert_eq!(
+            parse_range_header("bytes=-200", 1000),
+            Ok(vec![range(800, 999)])
+        );
+        assert_eq!(
+            parse_range_header("bytes=900-5000", 1000),
+            Ok(vec![range(900, 999)])
+        );
+        assert_eq!(
+            parse_range_header("bytes=-5000", 1000),
+            Ok(vec![range(0, 999)])
+        );
+    }
+
+    #[test]
+    fn test_parse_multiple_ranges() {
+        assert_eq!(
+            parse_range_header("bytes=0-0, 10-19,,-1", 100),
+            Ok(vec![range(0, 0), range(10, 19), range(99, 99)])
+        );
+        // unsatisfiable ranges are dropped as long as one range remains
+        assert_eq!(
+            parse_range_header("bytes=0-9,200-300", 100),
+            Ok(vec![range(0, 9)])
+        );
+    }
+
+    #[test]
+    fn test_parse_range_errors() {
+        assert_eq!(
+            parse_range_header("items=0-1", 100),
+            Err(RangeError::UnsupportedUnit)
+        );
+        assert_eq!(
+            parse_range_header("bytes=5-1", 100),
+            Err(RangeError::Invalid)
+        );
+        assert_eq!(
+            parse_range_header("bytes=abc", 100),
+            Err(RangeError::Invalid)
+        );
+        assert_eq!(
+            parse_range_header("bytes=+1-2", 100),
+            Err(RangeError::Invalid)
+        );
+        assert_eq!(parse_range_header("bytes=", 100), Err(RangeError::Invalid));
+        assert_eq!(
+            parse_range_header("bytes=100-", 100),
+            Err(RangeError::Unsatisfiable)
+        );
+        assert_eq!(
+            parse_range_header("bytes=-0", 100),
+            Err(RangeError::Unsatisfiable)
+        );
+        assert_eq!(
+            parse_range_header("bytes=0-", 0),
+            Err(RangeError::Unsatisfiable)
+        );
+
+        let many = format!("bytes={}", vec!["0-0"; MAX_RANGES + 1].join(","));
+        assert_eq!(
+            parse_range_header(&many, 100),
+            Err(RangeError::TooManyRanges)
+        );
+    }
+
+    #[test]
+    fn test_multipart_byteranges() {
+        let body = multipart_byteranges(
+            "XYZ",
+            "text/plain",
+            10,
+            &[(range(0, 1), b"ab"), (range(8, 9), b"ij")],
+        );
+        let expected = "\r\n--XYZ\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\nab\
+                        \r\n--XYZ\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\nij\
+                        \r\n--XYZ--\r\n";
+        assert_eq!(String::from_utf8(body).unwrap(), expected);
+        assert_eq!(
+            multipart_byteranges_len("XYZ", "text/plain", 10, &[range(0, 1), range(8, 9)]),
+            expected.len() as u64
+        );
+    }
+
+    #[test]
+    fn test_coalesce_ranges() {
+        // overlapping, duplicate and adjacent ranges are merged, in any order
+        assert_eq!(
+            coalesce_ranges(vec![
+                range(50, 149),
+                range(0, 99),
+                range(150, 199),
+                range(0, 99)
+            ]),
+            vec![range(0, 199)]
+        );
+        assert_eq!(
+            coalesce_ranges(vec![range(90, 99), range(0, 0), range(10, 19)]),
+            vec![range(0, 0), range(10, 19), range(90, 99)]
+        );
+        assert_eq!(
+            coalesce_ranges(vec![range(0, 9), range(2, 3), range(11, 12)]),
+            vec![range(0, 9), range(11, 12)]
+        );
+    }
+}
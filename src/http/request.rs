@@ -1,8 +1,9 @@
 use super::method::{Method, MethodError}; // Import Method and MethodError from the method module
-use super::{QueryString, Value};
+use super::{ConnectionInfo, Forwarded, Principal, QueryString};
 use std::convert::TryFrom; // convert::From doesn't handle errors, convert::TryFrom handles errors
 use std::error::Error; // Error trait is used for error handling in Rust
-use std::fmt::{self, Debug, Display, Result as FmtResult};
+use std::fmt::{Debug, Display, Result as FmtResult};
+use std::net::IpAddr;
 use std::str;
 use std::str::Utf8Error; // Utf8Error is used to handle errors when converting bytes to a string
 
@@ -22,14 +23,10 @@ use std::str::Utf8Error; // Utf8Error is used to handle errors when converting b
 ///
 /// # Examples
 ///
-/// ```
-/// let text = "GET /path HTTP/1.1";
-/// let (word, rest) = get_next_word(text).unwrap();
-/// assert_eq!(word, "GET");
-/// assert_eq!(rest, "/path HTTP/1.1");
-/// ```
-fn get_next_word<'buf>(request: &'buf str) -> Option<(&'buf str, &'buf str)> {
-    for (i, c) in request.chars().enumerate() {
+/// `"GET /path HTTP/1.1"` is split into `"GET"` and `"/path HTTP/1.1"` (see the tests below).
+fn get_next_word(request: &str) -> Option<(&str, &str)> {
+    // char_indices yields byte offsets, so slicing stays on character boundaries
+    for (i, c) in request.char_indices() {
         if c == ' ' || c == '\r' {
             return Some((&request[..i], &request[i + 1..])); // Adding 1 to skip the space character, however in non-utf-8 encoded strings this could cause issues
             // + 1 means adding one byte, not just adding one character
@@ -78,13 +75,47 @@ mod tests {
         let result = get_next_word(input);
         assert_eq!(result, Some(("GET", "   /path HTTP/1.1")));
     }
+
+    #[test]
+    fn test_request_parses_headers() {
+        let input = b"GET /video.mp4 HTTP/1.1\r\nHost: localhost\r\nRange:  bytes=0-99 \r\n\r\n";
+        let request = Request::try_from(&input[..]).unwrap();
+        assert_eq!(request.path(), "/video.mp4");
+        assert_eq!(request.header("range"), Some("bytes=0-99"));
+        assert_eq!(request.header("HOST"), Some("localhost"));
+        assert_eq!(request.header("Accept"), None);
+        assert_eq!(request.headers().len(), 2);
+    }
+
+    #[test]
+    fn test_request_rejects_malformed_header() {
+        let input = b"GET / HTTP/1.1\r\nNot a header\r\n\r\n";
+        assert!(matches!(
+            Request::try_from(&input[..]),
+            Err(ParseError::InvalidRequest)
+        ));
+    }
+
+    #[test]
+    fn test_request_splits_query_string() {
+        let input = b"GET /search?q=rust HTTP/1.1\r\n\r\n";
+        let request = Request::try_from(&input[..]).unwrap();
+        assert_eq!(request.path(), "/search");
+        let query = request.query_string().unwrap();
+        assert_eq!(query.get("q"), Some(&crate::http::Value::Single("rust")));
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Request<'buf> {
     method: Method,
     query_string: Option<QueryString<'buf>>, // query string may or may not exist on URL
     path: &'buf str,
+    headers: Vec<(&'buf str, &'buf str)>, // header (name, value) pairs in the order they were received
+    connection: Option<&'buf ConnectionInfo>, // the connection the request came on, if any
+    sequence: u64,                        // position of the request on its connection, from 1
+    forwarded: Option<Forwarded<'buf>>,   // the client as reported by trusted proxies, if any
+    principal: Option<&'buf Principal>,   // who the client authenticated as, if anyone
 }
 impl<'buf> Request<'buf> {
     pub fn method(&self) -> &Method {
@@ -92,22 +123,162 @@ impl<'buf> Request<'buf> {
     }
 
     pub fn path(&self) -> &str {
-        &self.path
+        self.path
     }
 
     /// Returns a reference to the query string if it exists, otherwise returns None
-    /// NOTE: instead of returning &Option<QueryString>, we return Option<&QueryString>
+    /// NOTE: instead of returning `&Option<QueryString>`, we return `Option<&QueryString>`
     /// which is more flexible and easier to use in the caller.
-    pub fn query_string(&self) -> Option<&QueryString> {
+    pub fn query_string(&self) -> Option<&QueryString<'buf>> {
         self.query_string.as_ref() // as_ref() converts &Option<QueryString> to Option<&QueryString>
     }
+
+    /// Returns the value of the first header with the given name, if present.
+    /// Header names are compared case-insensitively, as required by the HTTP spec.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The header name to look up (e.g. "Range").
+    pub fn header(&self, name: &str) -> Option<&'buf str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+    }
+
+    /// Returns the same request with a different method.
+    /// The server uses this to answer HEAD requests by running the GET path of a handler.
+    pub fn with_method(self, method: Method) -> Self {
+        Self { method, ..self }
+    }
+
+    /// Returns the same request with a different path.
+    /// Routers use this to hand a request to the handler of a path prefix, without the prefix.
+    pub fn with_path(self, path: &'buf str) -> Self {
+        Self { path, ..self }
+    }
+
+    /// Returns the name of the listener that accepted the connection of the request
+    /// (see `ListenerConfig`), or `None` for requests that did not come from a `Server`.
+    pub fn listener(&self) -> Option<&'buf str> {
+        self.connection.map(|connection| &*connection.listener)
+    }
+
+    /// Returns the connection the request came on (client and server addresses, listener,
+    /// connection id), or `None` for requests that did not come from a `Server`.
+    pub fn connection(&self) -> Option<&'buf ConnectionInfo> {
+        self.connection
+    }
+
+    /// Returns the position of the request among the requests of its connection: 1 for the first,
+    /// 2 for the next one on a kept-alive connection, and so on (0 without a connection).
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Returns the same request, tagged with the connection it came on.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - The connection.
+    /// * `sequence` - The position of the request on the connection, from 1.
+    pub fn with_connection(self, connection: &'buf ConnectionInfo, sequence: u64) -> Self {
+        Self {
+            connection: Some(connection),
+            sequence,
+            ..self
+        }
+    }
+
+    /// Returns the client as reported by the trusted proxies the request went through,
+    /// or `None` if it came straight from the client (see `ServerConfig::trusted_proxies`).
+    pub fn forwarded(&self) -> Option<&Forwarded<'buf>> {
+        self.forwarded.as_ref()
+    }
+
+    /// Returns the same request, with the client reported by trusted proxies.
+    pub fn with_forwarded(self, forwarded: Forwarded<'buf>) -> Self {
+        Self {
+            forwarded: Some(forwarded),
+            ..self
+        }
+    }
+
+    /// Returns the IP address of the client: the one reported by trusted proxies, or else the address
+    /// of the connection. `None` for requests over Unix domain sockets that no proxy reported on,
+    /// and for requests that did not come from a `Server`.
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        match &self.forwarded {
+            Some(forwarded) => Some(forwarded.client),
+            None => self.connection?.peer_ip(),
+        }
+    }
+
+    /// Returns the scheme the client used: `https` if a trusted proxy says so, `http` otherwise.
+    pub fn scheme(&self) -> &'buf str {
+        self.forwarded
+            .and_then(|forwarded| forwarded.proto)
+            .unwrap_or("http")
+    }
+
+    /// Returns the host the client asked for: the one reported by trusted proxies, or else the `Host` header.
+    pub fn host(&self) -> Option<&'buf str> {
+        self.forwarded
+            .and_then(|forwarded| forwarded.host)
+            .or_else(|| self.header("Host"))
+    }
+
+    /// Returns the client the request was authenticated as by an authentication handler, such as
+    /// `auth::BasicAuth`, or `None` if the request was not authenticated.
+    pub fn principal(&self) -> Option<&'buf Principal> {
+        self.principal
+    }
+
+    /// Returns the same request, authenticated as `principal`.
+    pub fn with_principal(self, principal: &'buf Principal) -> Self {
+        Self {
+            principal: Some(principal),
+            ..self
+        }
+    }
+
+    /// Returns all headers as (name, value) pairs in the order they were received.
+    pub fn headers(&self) -> &[(&'buf str, &'buf str)] {
+        &self.headers
+    }
     // don't need to implement convert method on own own, just use std::convert::TryFrom in idiomatic Rust (see below)
     // NOT NEEDED: fn from_byte_array(byte_array: &[u8]) -> Result<Self, String>
 }
 
+/// Parses the header section that follows the request line.
+///
+/// # Arguments
+///
+/// * `section` - Everything after the request line, starting with the header lines.
+///
+/// # Returns
+///
+/// The header (name, value) pairs, with surrounding whitespace trimmed from the values.
+/// Parsing stops at the first empty line, which marks the end of the headers.
+fn parse_headers(section: &str) -> Result<Vec<(&str, &str)>, ParseError> {
+    let mut headers = Vec::new();
+    for line in section.split("\r\n") {
+        if line.is_empty() {
+            break; // an empty line separates the headers from the body
+        }
+        let (name, value) = line.split_once(':').ok_or(ParseError::InvalidRequest)?;
+        // whitespace between the header name and the colon is forbidden (RFC 9112, section 5.1)
+        if name.is_empty() || name.ends_with(|c: char| c.is_ascii_whitespace()) {
+            return Err(ParseError::InvalidRequest);
+        }
+        headers.push((name, value.trim()));
+    }
+    Ok(headers)
+}
+
 // Convert &[u8] byte array into Request using TryFrom trait
 // **Parsing logic of request headers goes here**
-// Extract URL path, query param, and HTTP method & protocol into Request struct
+// Extract URL path, query param, headers, and HTTP method & protocol into Request struct
 impl<'buf> TryFrom<&'buf [u8]> for Request<'buf> {
     // need to assign `Error` type and implement `try_from` method
     type Error = ParseError;
@@ -121,7 +292,7 @@ impl<'buf> TryFrom<&'buf [u8]> for Request<'buf> {
         let request = str::from_utf8(buffer)?; // '?' propagates the error to caller
         let (method, rest) = get_next_word(request).ok_or(ParseError::InvalidRequest)?; // If get_next_word returns None, return ParseError::InvalidRequest
         let (mut path, rest) = get_next_word(rest).ok_or(ParseError::InvalidRequest)?; // If get_next_word returns None, return ParseError::InvalidRequest, rest is variable shadowing
-        let (protocol, _) = get_next_word(rest).ok_or(ParseError::InvalidRequest)?; // If get_next_word returns None, return ParseError::InvalidRequest
+        let (protocol, rest) = get_next_word(rest).ok_or(ParseError::InvalidRequest)?; // If get_next_word returns None, return ParseError::InvalidRequest
 
         if protocol != "HTTP/1.1" {
             return Err(ParseError::InvalidProtocol); // If the protocol is not HTTP/1.1 or HTTP/2.0, return ParseError::InvalidProtocol
@@ -131,18 +302,28 @@ impl<'buf> TryFrom<&'buf [u8]> for Request<'buf> {
         let mut query_string: Option<QueryString<'buf>> = None;
         if let Some(i) = path.find('?') {
             // `if let` syntax allows you to only match on variants you care about
-            path = &path[..i]; // Update path to exclude the query string
             query_string = Some(QueryString::from(&path[i + 1..])); // If the path contains a query string, extract it
+            path = &path[..i]; // Update path to exclude the query string
         }
 
+        // get_next_word stopped at the '\r' of the request line, so skip the '\n' that follows it
+        let headers = parse_headers(rest.strip_prefix('\n').unwrap_or(rest))?;
+
         Ok(Self {
             method,
             path,
             query_string,
+            headers,
+            connection: None,
+            sequence: 0,
+            forwarded: None,
+            principal: None,
         })
     }
 }
 
+// Every variant describes a different way the request can be invalid
+#[allow(clippy::enum_variant_names)]
 pub enum ParseError {
     InvalidRequest,  // General error for invalid requests
     InvalidMethod,   // Error for unsupported HTTP methods, we only support GET and POST for now
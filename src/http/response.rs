@@ -1,18 +1,102 @@
 use super::StatusCode;
-use std::{
-    fmt::{Display, Formatter, Result as FmtResult},
-    io::{Result as IoResult, Write},
-};
+use std::io::{Result as IoResult, Write};
 
 #[derive(Debug)]
 pub struct Response {
     status_code: StatusCode,
-    body: Option<String>,
+    headers: Vec<(String, String)>, // header (name, value) pairs, sent in insertion order
+    body: Option<Vec<u8>>,          // raw bytes, so binary files (images, videos) can be sent as-is
+    log_notes: Vec<String>,         // appended to the access log line of the response, never sent
 }
 
 impl Response {
     pub fn new(status_code: StatusCode, body: Option<String>) -> Self {
-        Self { status_code, body }
+        Self {
+            status_code,
+            headers: Vec::new(),
+            body: body.map(String::into_bytes),
+            log_notes: Vec::new(),
+        }
+    }
+
+    /// Creates a new response whose body is an arbitrary byte buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `status_code` - The status code of the response.
+    /// * `body` - The raw bytes of the body (e.g. the contents of an image file).
+    pub fn from_bytes(status_code: StatusCode, body: Vec<u8>) -> Self {
+        Self {
+            status_code,
+            headers: Vec::new(),
+            body: Some(body),
+            log_notes: Vec::new(),
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        self.status_code
+    }
+
+    pub fn body(&self) -> Option<&[u8]> {
+        self.body.as_deref()
+    }
+
+    /// Replaces the body of the response.
+    pub fn set_body(&mut self, body: Vec<u8>) {
+        self.body = Some(body);
+    }
+
+    /// Returns the headers in the order they will be sent.
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Returns the value of the first header with the given name, if present.
+    /// Header names are compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets a header, replacing any existing headers with the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The header name (e.g. "Content-Type").
+    /// * `value` - The header value (e.g. "text/html").
+    pub fn set_header(&mut self, name: &str, value: impl Into<String>) {
+        self.headers
+            .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.into()));
+    }
+
+    /// Builder-style variant of `set_header`, handy when constructing a response in one expression.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_server::{Response, StatusCode};
+    ///
+    /// let response = Response::new(StatusCode::Ok, None).with_header("Accept-Ranges", "bytes");
+    /// assert_eq!(response.header("accept-ranges"), Some("bytes"));
+    /// ```
+    pub fn with_header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.set_header(name, value);
+        self
+    }
+
+    /// Adds a note to the access log line of the response, such as a decision taken by a middleware
+    /// (e.g. `access=deny:all`). Notes are logged after the body size, in the order they were added.
+    pub fn add_log_note(&mut self, note: impl Into<String>) {
+        self.log_notes.push(note.into());
+    }
+
+    /// Returns the notes for the access log.
+    pub fn log_notes(&self) -> &[String] {
+        &self.log_notes
     }
 
     /// Sends the response to the client, without creating a new string.
@@ -25,12 +109,17 @@ impl Response {
     /// It uses `write!` macro directly, instead of returning a copy of the response string.
     /// It also uses static dispatch, instead of dynamic dispatch,
     /// which resolves to the correct method of types implementing `Write` trait at compile time.
+    /// A `Content-Length` header is added automatically unless one was set explicitly.
     ///
     /// # Example
     ///
     /// ```
+    /// use rust_server::{Response, StatusCode};
+    ///
     /// let response = Response::new(StatusCode::Ok, Some("Hello".to_string()));
-    /// response.send(&mut stream);
+    /// let mut stream = Vec::new(); // a TcpStream in practice
+    /// response.send(&mut stream).unwrap();
+    /// assert_eq!(stream, b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHello");
     /// ```
     ///
     /// # Arguments
@@ -40,14 +129,32 @@ impl Response {
     /// # Returns
     /// * `IoResult<()>` - The result of the write operation.
     pub fn send(&self, stream: &mut impl Write) -> IoResult<()> {
-        let body = self.body.as_deref().unwrap_or("");
+        self.send_head(stream)?;
+        stream.write_all(self.body.as_deref().unwrap_or(&[]))
+    }
+
+    /// Sends only the status line and headers of the response, as required when answering a HEAD request.
+    /// The `Content-Length` header still describes the body that a GET request would have received.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The stream to write the response head to.
+    pub fn send_head(&self, stream: &mut impl Write) -> IoResult<()> {
         write!(
             stream,
-            "HTTP/1.1 {} {}\r\n\r\n{}",
+            "HTTP/1.1 {} {}\r\n",
             self.status_code,
-            self.status_code.reason_phrase(),
-            body
-        )
+            self.status_code.reason_phrase()
+        )?;
+        for (name, value) in &self.headers {
+            write!(stream, "{}: {}\r\n", name, value)?;
+        }
+        // a 204 No Content response must not carry a Content-Length header (RFC 9110, section 8.6)
+        if self.header("Content-Length").is_none() && self.status_code != StatusCode::NoContent {
+            let len = self.body.as_ref().map_or(0, Vec::len);
+            write!(stream, "Content-Length: {}\r\n", len)?;
+        }
+        write!(stream, "\r\n")
     }
 }
 
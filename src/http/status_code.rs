@@ -3,18 +3,66 @@ use std::fmt::{Display, Formatter, Result as FmtResult};
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum StatusCode {
     Ok = 200,
+    NoContent = 204,
+    PartialContent = 206,
     BadRequest = 400,
+    Unauthorized = 401,
+    Forbidden = 403,
     NotFound = 404,
+    MethodNotAllowed = 405,
+    RequestTimeout = 408,
+    RangeNotSatisfiable = 416,
+    TooManyRequests = 429,
+    RequestHeaderFieldsTooLarge = 431,
+    InternalServerError = 500,
+    ServiceUnavailable = 503,
 }
 
 impl StatusCode {
     pub fn reason_phrase(&self) -> &str {
         match self {
             StatusCode::Ok => "OK",
+            StatusCode::NoContent => "No Content",
+            StatusCode::PartialContent => "Partial Content",
             StatusCode::BadRequest => "Bad Request",
+            StatusCode::Unauthorized => "Unauthorized",
+            StatusCode::Forbidden => "Forbidden",
             StatusCode::NotFound => "Not Found",
+            StatusCode::MethodNotAllowed => "Method Not Allowed",
+            StatusCode::RequestTimeout => "Request Timeout",
+            StatusCode::RangeNotSatisfiable => "Range Not Satisfiable",
+            StatusCode::TooManyRequests => "Too Many Requests",
+            StatusCode::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
+            StatusCode::InternalServerError => "Internal Server Error",
+            StatusCode::ServiceUnavailable => "Service Unavailable",
         }
     }
+
+    /// Returns the status with the given numeric code, if it is one the server knows.
+    pub fn from_u16(code: u16) -> Option<StatusCode> {
+        match code {
+            200 => Some(StatusCode::Ok),
+            204 => Some(StatusCode::NoContent),
+            206 => Some(StatusCode::PartialContent),
+            400 => Some(StatusCode::BadRequest),
+            401 => Some(StatusCode::Unauthorized),
+            403 => Some(StatusCode::Forbidden),
+            404 => Some(StatusCode::NotFound),
+            405 => Some(StatusCode::MethodNotAllowed),
+            408 => Some(StatusCode::RequestTimeout),
+            416 => Some(StatusCode::RangeNotSatisfiable),
+            429 => Some(StatusCode::TooManyRequests),
+            431 => Some(StatusCode::RequestHeaderFieldsTooLarge),
+            500 => Some(StatusCode::InternalServerError),
+            503 => Some(StatusCode::ServiceUnavailable),
+            _ => None,
+        }
+    }
+
+    /// Returns true for client (4xx) and server (5xx) errors.
+    pub fn is_error(&self) -> bool {
+        *self as u16 >= 400
+    }
 }
 
 impl Display for StatusCode {
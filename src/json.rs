@@ -0,0 +1,467 @@
+// Just enough JSON (RFC 8259) for the bodies the server writes itself, such as problem details,
+// and for the small documents it reads, such as the claims of JSON Web Tokens.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// How deeply arrays and objects can be nested, so that hostile input cannot exhaust the stack.
+const MAX_DEPTH: usize = 64;
+
+/// A JSON value. Objects keep their members in order, and numbers are read as `f64`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Returns the member of an object with the given name, or `None` for other values.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+// Written as compact JSON, with integral numbers written without a fraction.
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Number(value) if value.fract() == 0.0 && value.abs() < 1e15 => {
+                write!(f, "{}", *value as i64)
+            }
+            Value::Number(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", quote(value)),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", quote(name), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// A document that is not valid JSON.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonError {
+    pub offset: usize, // in bytes, where the error was detected
+    pub message: &'static str,
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "invalid JSON at byte {}: {}", self.offset, self.message)
+    }
+}
+
+/// Parses a JSON document.
+///
+/// # Returns
+///
+/// The value, or the first error. Objects with the same member twice are rejected, as their meaning
+/// depends on the parser, and so are numbers too large for an `f64`.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::json::{Value, parse};
+///
+/// let claims = parse(r#"{"sub": "alice", "exp": 1700000000, "admin": true}"#).unwrap();
+/// assert_eq!(claims.get("sub").and_then(Value::as_str), Some("alice"));
+/// assert_eq!(claims.get("exp").and_then(Value::as_f64), Some(1700000000.0));
+/// assert!(parse("{\"a\": 1,}").is_err());
+/// ```
+pub fn parse(text: &str) -> Result<Value, JsonError> {
+    let mut parser = Parser {
+        bytes: text.as_bytes(),
+        pos: 0,
+    };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.pos < parser.bytes.len() {
+        return parser.error("unexpected data after the value");
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error<T>(&self, message: &'static str) -> Result<T, JsonError> {
+        Err(JsonError {
+            offset: self.pos,
+            message,
+        })
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8, message: &'static str) -> Result<(), JsonError> {
+        self.skip_whitespace();
+        if self.peek() != Some(byte) {
+            return self.error(message);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(depth + 1),
+            Some(b'[') => self.array(depth + 1),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(_) => self.error("expected a value"),
+            None => self.error("unexpected end of the document"),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, JsonError> {
+        if !self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            return self.error("expected a value");
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, JsonError> {
+        if depth > MAX_DEPTH {
+            return self.error("too deeply nested");
+        }
+        self.pos += 1; // {
+        let mut members: Vec<(String, Value)> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return self.error("expected a member name");
+            }
+            let start = self.pos;
+            let name = self.string()?;
+            if members.iter().any(|(other, _)| *other == name) {
+                self.pos = start;
+                return self.error("duplicate member name");
+            }
+            self.expect(b':', "expected `:`")?;
+            let value = self.value(depth)?;
+            members.push((name, value));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                _ => return self.error("expected `,` or `}`"),
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, JsonError> {
+        if depth > MAX_DEPTH {
+            return self.error("too deeply nested");
+        }
+        self.pos += 1; // [
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value(depth)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(values));
+                }
+                _ => return self.error("expected `,` or `]`"),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.pos += 1; // "
+        let mut out = Vec::new();
+        loop {
+            match self.peek() {
+                None => return self.error("unterminated string"),
+                Some(b'"') => {
+                    self.pos += 1;
+                    // the input is a str, and escapes produce whole characters
+                    return Ok(String::from_utf8(out).expect("valid UTF-8"));
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let c = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            self.pos += 1;
+                            let c = self.unicode_escape()?;
+                            out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                            continue;
+                        }
+                        _ => return self.error("invalid escape"),
+                    };
+                    self.pos += 1;
+                    out.push(c as u8);
+                }
+                Some(byte) if byte < b' ' => return self.error("control character in a string"),
+                Some(byte) => {
+                    self.pos += 1;
+                    out.push(byte);
+                }
+            }
+        }
+    }
+
+    /// Decodes the hex digits of a `\u` escape, and of the low surrogate that follows a high one.
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.bytes[self.pos..].starts_with(b"\\u") {
+                return self.error("unpaired surrogate");
+            }
+            self.pos += 2;
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return self.error("unpaired surrogate");
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        match char::from_u32(code) {
+            Some(c) => Ok(c),
+            None => self.error("unpaired surrogate"),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self.bytes.get(self.pos..self.pos + 4);
+        let code = digits
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok());
+        match code {
+            Some(code) => {
+                self.pos += 4;
+                Ok(code)
+            }
+            None => self.error("expected 4 hex digits"),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, JsonError> {
+        let start = self.pos;
+        let digits = |parser: &mut Self| {
+            let from = parser.pos;
+            while parser.peek().is_some_and(|b| b.is_ascii_digit()) {
+                parser.pos += 1;
+            }
+            parser.pos > from
+        };
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        // no leading zeros
+        if self.peek() == Some(b'0') {
+            self.pos += 1;
+        } else if !digits(self) {
+            return self.error("invalid number");
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            if !digits(self) {
+                return self.error("invalid number");
+            }
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return self.error("invalid number");
+            }
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).expect("ASCII digits");
+        match text.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(Value::Number(value)),
+            _ => {
+                self.pos = start;
+                self.error("the number is too large")
+            }
+        }
+    }
+}
+
+/// Encodes a string as a JSON string literal, including the surrounding quotes.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::json::quote;
+///
+/// assert_eq!(quote("say \"hi\"\n"), r#""say \"hi\"\n""#);
+/// ```
+pub fn quote(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            // the remaining control characters have no short escape
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("Not Found"), "\"Not Found\"");
+        assert_eq!(quote("a\"b\\c"), "\"a\\\"b\\\\c\"");
+        assert_eq!(
+            quote("line\nbreak\ttab\u{1}"),
+            "\"line\\nbreak\\ttab\\u0001\""
+        );
+        assert_eq!(quote("héllo"), "\"héllo\"");
+    }
+
+    #[test]
+    fn test_parse() {
+        let value = parse(
+            r#" {"s": "a\"\u00e9\ud83d\ude00\n", "n": [0, -1.5, 2e3, 1E-2], "b": [true, false, null], "o": {}} "#,
+        )
+        .unwrap();
+        assert_eq!(value.get("s").and_then(Value::as_str), Some("a\"é😀\n"));
+        assert_eq!(
+            value.get("n"),
+            Some(&Value::Array(vec![
+                Value::Number(0.0),
+                Value::Number(-1.5),
+                Value::Number(2000.0),
+                Value::Number(0.01)
+            ]))
+        );
+        assert_eq!(value.get("o"), Some(&Value::Object(Vec::new())));
+        assert_eq!(value.get("missing"), None);
+        assert_eq!(
+            value.to_string(),
+            r#"{"s":"a\"é😀\n","n":[0,-1.5,2000,0.01],"b":[true,false,null],"o":{}}"#
+        );
+        assert_eq!(parse(&value.to_string()).unwrap(), value);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (text, offset, message) in [
+            ("", 0, "unexpected end of the document"),
+            ("[1,]", 3, "expected a value"),
+            ("{\"a\":1 \"b\":2}", 7, "expected `,` or `}`"),
+            ("{\"a\":1,\"a\":2}", 7, "duplicate member name"),
+            ("01", 1, "unexpected data after the value"),
+            ("1.", 2, "invalid number"),
+            ("1e999", 0, "the number is too large"),
+            ("\"\\ud83d\"", 7, "unpaired surrogate"),
+            ("\"tab\there\"", 4, "control character in a string"),
+            ("tru", 0, "expected a value"),
+            ("{} x", 3, "unexpected data after the value"),
+        ] {
+            assert_eq!(parse(text), Err(JsonError { offset, message }), "{}", text);
+        }
+        let nested = "[".repeat(MAX_DEPTH + 1);
+        assert_eq!(parse(&nested).unwrap_err().message, "too deeply nested");
+    }
+}
@@ -0,0 +1,74 @@
+//! A small HTTP/1.1 server built on the standard library alone.
+//!
+//! The crate is made of a few layers that can be used on their own:
+//!
+//! * [`http`] - requests, responses, status codes and the header helpers built on them
+//!   (ranges, quality lists, dates, media types).
+//! * [`server`] - the [`Server`] accepting connections and the [`Handler`] trait it calls for
+//!   every request, including HEAD/OPTIONS handling, error pages and panic isolation,
+//!   configured with a [`config::ServerConfig`] (in code, or from a file with [`config::FileConfig`],
+//!   which also builds the handler chain the file describes with `FileConfig::build_handler`).
+//! * [`router`] - a [`Router`] sending requests to handlers by path prefix and adding response headers.
+//! * [`access`] - an [`access::AccessControl`] letting clients in by IP address, per path prefix.
+//! * [`auth`] - a [`auth::BasicAuth`] asking for a user name and password, a [`auth::TokenAuth`]
+//!   asking for a bearer token and a [`auth::JwtAuth`] asking for a signed JWT, per path prefix.
+//! * [`rate_limit`] - a [`rate_limit::RateLimiter`] answering clients that send too many requests with 429.
+//! * [`website_handler`] and [`embedded`] - ready-made handlers serving static files from a
+//!   directory or from files bundled into the binary, with compression, ranges and caching headers.
+//!
+//! # Example
+//!
+//! A handler answering every request with a greeting:
+//!
+//! ```no_run
+//! use rust_server::config::ServerConfig;
+//! use rust_server::{Handler, HandlerError, Request, Response, Server, StatusCode};
+//!
+//! struct HelloHandler;
+//!
+//! impl Handler for HelloHandler {
+//!     fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+//!         let body = format!("Hello from {}", request.path());
+//!         Ok(Response::new(StatusCode::Ok, Some(body)))
+//!     }
+//! }
+//!
+//! let config = ServerConfig::new().with_bind_addr("127.0.0.1:8080");
+//! let server = Server::new(config).expect("invalid configuration");
+//! server.run(HelloHandler).expect("cannot start the server");
+//! ```
+
+pub mod access;
+pub mod auth;
+pub mod compression;
+pub mod config;
+pub mod embedded;
+pub mod error_page;
+pub mod file_cache;
+pub mod handler_error;
+pub mod http;
+pub mod json;
+mod listener;
+pub mod prefix_table;
+mod proxy_protocol;
+pub mod rate_limit;
+mod representation;
+pub mod router;
+pub mod server;
+#[cfg(unix)]
+pub mod signal;
+#[cfg(unix)]
+pub mod systemd;
+#[cfg(test)]
+mod testing;
+#[cfg(unix)]
+mod upgrade;
+pub mod website_handler;
+
+pub use embedded::EmbeddedHandler;
+pub use file_cache::FileCache;
+pub use handler_error::HandlerError;
+pub use http::{Method, Request, Response, StatusCode};
+pub use router::{Route, Router};
+pub use server::{Handler, Server};
+pub use website_handler::WebsiteHandler;
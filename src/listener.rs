@@ -0,0 +1,391 @@
+// The sockets the server accepts connections on: TCP, over IPv4 or IPv6, and Unix domain sockets,
+// which let a local reverse proxy reach the server without a TCP port.
+
+use crate::config::{ListenAddress, ListenerConfig};
+use crate::http::Transport;
+use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(unix)]
+use std::{
+    ffi::{c_int, c_short},
+    fs::{self, Permissions},
+    os::fd::{AsRawFd, OwnedFd, RawFd},
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    sync::{Mutex, PoisonError},
+};
+
+/// An entry of the array given to `poll`, which has the same layout on every Unix.
+#[cfg(unix)]
+#[repr(C)]
+struct PollFd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+#[cfg(unix)]
+const POLLIN: c_short = 1;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+type PollCount = std::ffi::c_ulong;
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+type PollCount = std::ffi::c_uint;
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+type Mode = u16;
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly"
+    ))
+))]
+type Mode = u32;
+
+#[cfg(unix)]
+unsafe extern "C" {
+    fn poll(fds: *mut PollFd, nfds: PollCount, timeout: c_int) -> c_int;
+    fn umask(mask: Mode) -> Mode;
+}
+
+/// Held while the umask is changed, since it belongs to the whole process.
+#[cfg(unix)]
+static UMASK: Mutex<()> = Mutex::new(());
+
+/// A bound socket, along with the name of its configuration.
+pub(crate) struct Listener {
+    pub name: Arc<str>,
+    pub proxy_protocol: bool, // whether connections start with a PROXY protocol header
+    socket: Socket,
+}
+
+enum Socket {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+/// An accepted connection.
+pub(crate) enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Listener {
+    /// Binds the socket of a listener, naming the address in the error if it fails.
+    pub fn bind(config: &ListenerConfig) -> IoResult<Self> {
+        let socket = match &config.address {
+            ListenAddress::Tcp(addr) => TcpListener::bind(addr).map(Socket::Tcp),
+            #[cfg(unix)]
+            ListenAddress::Unix(path) => bind_unix(path, config.mode).map(Socket::Unix),
+            #[cfg(not(unix))]
+            ListenAddress::Unix(_) => Err(IoError::new(
+                ErrorKind::Unsupported,
+                "Unix domain sockets are not supported on this system",
+            )),
+        };
+        let socket = socket.map_err(|e| {
+            IoError::new(
+                e.kind(),
+                format!("cannot listen on {}: {}", config.address, e),
+            )
+        })?;
+        let mut listener = Self::new(&config.name, socket)?;
+        listener.proxy_protocol = config.proxy_protocol;
+        Ok(listener)
+    }
+
+    fn new(name: &str, socket: Socket) -> IoResult<Self> {
+        // accepting never blocks, so the accept loops can notice when the server stops
+        match &socket {
+            Socket::Tcp(listener) => listener.set_nonblocking(true)?,
+            #[cfg(unix)]
+            Socket::Unix(listener) => listener.set_nonblocking(true)?,
+        }
+        Ok(Self {
+            name: Arc::from(name),
+            proxy_protocol: false,
+            socket,
+        })
+    }
+
+    /// Wraps a listening socket bound by another process, e.g. passed by systemd.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the listener.
+    /// * `fd` - The socket, which must be a listening TCP or Unix stream socket.
+    #[cfg(unix)]
+    pub fn inherit(name: &str, fd: OwnedFd) -> IoResult<Self> {
+        // the address family tells the kinds of socket apart: only TCP sockets have an IP address
+        let tcp = TcpListener::from(fd);
+        let socket = if tcp.local_addr().is_ok() {
+            Socket::Tcp(tcp)
+        } else {
+            let unix = UnixListener::from(OwnedFd::from(tcp));
+            unix.local_addr().map_err(|e| {
+                IoError::new(
+                    e.kind(),
+                    format!("the socket {} is neither TCP nor Unix: {}", name, e),
+                )
+            })?;
+            Socket::Unix(unix)
+        };
+        Self::new(name, socket)
+    }
+
+    /// Returns the file descriptor of the socket, to hand it over to another process.
+    #[cfg(unix)]
+    pub fn raw_fd(&self) -> RawFd {
+        match &self.socket {
+            Socket::Tcp(listener) => listener.as_raw_fd(),
+            Socket::Unix(listener) => listener.as_raw_fd(),
+        }
+    }
+
+    /// Returns the address the listener is bound to, e.g. to find the port picked for `127.0.0.1:0`.
+    pub fn local_addr(&self) -> IoResult<String> {
+        match &self.socket {
+            Socket::Tcp(listener) => Ok(listener.local_addr()?.to_string()),
+            #[cfg(unix)]
+            Socket::Unix(listener) => Ok(unix_addr(&listener.local_addr()?)),
+        }
+    }
+
+    /// Waits for the next connection, for at most about `timeout`.
+    ///
+    /// # Returns
+    ///
+    /// The connection and the address of the client (for the logs), or `None` if no connection
+    /// came in time, or another process sharing the socket took it first.
+    pub fn accept_timeout(&self, timeout: Duration) -> IoResult<Option<(Stream, String)>> {
+        if let Some(accepted) = self.try_accept()? {
+            return Ok(Some(accepted));
+        }
+        if !self.wait(timeout)? {
+            return Ok(None);
+        }
+        self.try_accept()
+    }
+
+    fn try_accept(&self) -> IoResult<Option<(Stream, String)>> {
+        let accepted = match &self.socket {
+            Socket::Tcp(listener) => listener
+                .accept()
+                .map(|(stream, peer)| (Stream::Tcp(stream), peer.to_string())),
+            #[cfg(unix)]
+            Socket::Unix(listener) => listener
+                .accept()
+                .map(|(stream, peer)| (Stream::Unix(stream), unix_addr(&peer))),
+        };
+        match accepted {
+            Ok((stream, peer)) => {
+                // some systems pass the non-blocking mode of the listener on to its connections
+                stream.set_nonblocking(false)?;
+                Ok(Some((stream, peer)))
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Waits until a connection may be accepted, or the timeout expires.
+    #[cfg(unix)]
+    fn wait(&self, timeout: Duration) -> IoResult<bool> {
+        let mut fd = PollFd {
+            fd: self.raw_fd(),
+            events: POLLIN,
+            revents: 0,
+        };
+        let millis = c_int::try_from(timeout.as_millis()).unwrap_or(c_int::MAX);
+        // SAFETY: `fd` is one valid entry, and poll only writes its `revents`
+        match unsafe { poll(&mut fd, 1, millis) } {
+            -1 => {
+                let e = IoError::last_os_error();
+                // a signal interrupted the wait, e.g. the one asking the server to stop
+                if e.kind() == ErrorKind::Interrupted {
+                    return Ok(false);
+                }
+                Err(e)
+            }
+            ready => Ok(ready > 0),
+        }
+    }
+
+    /// Waits a little before trying again, on systems without `poll`.
+    #[cfg(not(unix))]
+    fn wait(&self, timeout: Duration) -> IoResult<bool> {
+        std::thread::sleep(timeout.min(Duration::from_millis(10)));
+        Ok(true)
+    }
+}
+
+/// Binds a Unix domain socket, replacing the file left by a previous run, and sets its permissions.
+///
+/// # Note
+///
+/// The socket file is created with no more permissions than `mode`, rather than changed afterwards,
+/// so that no client can connect in between. This takes changing the umask of the process for the
+/// time of the bind, which the files other threads create meanwhile get too; the listeners are bound
+/// before the server starts its workers.
+#[cfg(unix)]
+fn bind_unix(path: &Path, mode: Option<u32>) -> IoResult<UnixListener> {
+    // a socket file outlives its process; it is only stale if nothing accepts connections on it
+    let is_socket = fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket());
+    if is_socket {
+        if UnixStream::connect(path).is_ok() {
+            return Err(IoError::new(
+                ErrorKind::AddrInUse,
+                "another process is listening on the socket",
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+    let Some(mode) = mode else {
+        return UnixListener::bind(path);
+    };
+    let listener = {
+        let _umask = UMASK.lock().unwrap_or_else(PoisonError::into_inner);
+        // SAFETY: umask cannot fail, and the previous mask is restored right after the bind
+        let previous = unsafe { umask((!mode & 0o777) as Mode) };
+        let listener = UnixListener::bind(path);
+        unsafe { umask(previous) };
+        listener?
+    };
+    // the umask of the process may have left out some of the permissions
+    fs::set_permissions(path, Permissions::from_mode(mode))?;
+    Ok(listener)
+}
+
+/// Formats the address of a Unix socket the way it is configured, or `unix:` alone for the
+/// unnamed sockets clients usually connect from.
+#[cfg(unix)]
+fn unix_addr(addr: &std::os::unix::net::SocketAddr) -> String {
+    match addr.as_pathname() {
+        Some(path) => format!("unix:{}", path.display()),
+        None => "unix:".to_string(),
+    }
+}
+
+impl Stream {
+    /// Returns the kind of socket the connection came on.
+    pub fn transport(&self) -> Transport {
+        match self {
+            Stream::Tcp(_) => Transport::Tcp,
+            #[cfg(unix)]
+            Stream::Unix(_) => Transport::Unix,
+        }
+    }
+
+    /// Returns the address of the server end of the connection, which tells which address of
+    /// the host a client connected to when the listener is bound to all of them.
+    pub fn local_addr(&self) -> IoResult<String> {
+        match self {
+            Stream::Tcp(stream) => Ok(stream.local_addr()?.to_string()),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Ok(unix_addr(&stream.local_addr()?)),
+        }
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> IoResult<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> IoResult<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> IoResult<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_write_timeout(timeout),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_write_timeout(timeout),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn test_unix_listener_replaces_stale_socket_and_sets_mode() {
+        let path = env::temp_dir().join(format!("rust_server_test_{}.sock", process::id()));
+        let config = ListenerConfig::new(&format!("unix:{}", path.display())).with_mode(0o600);
+        let listener = Listener::bind(&config).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        let (mut stream, peer) = listener
+            .accept_timeout(Duration::from_secs(30))
+            .unwrap()
+            .unwrap();
+        assert_eq!(peer, "unix:");
+        client.write_all(b"ping").unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // a second server cannot take over a socket in use
+        assert_eq!(
+            Listener::bind(&config).err().map(|e| e.kind()),
+            Some(ErrorKind::AddrInUse)
+        );
+
+        // the file left behind once the listener is gone is replaced
+        drop(listener);
+        assert!(Listener::bind(&config).is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+}
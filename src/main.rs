@@ -1,25 +1,355 @@
-#![allow(dead_code)]
-mod http;
-mod server;
-mod website_handler;
-use server::Server;
+mod cli;
+
+use cli::{Command, Options};
+#[cfg(unix)]
+use rust_server::Handler;
+use rust_server::Server;
+use rust_server::auth::{self, Credentials, TokenStore};
+use rust_server::config::{FileConfig, ListenAddress};
+use rust_server::server::ReloadableHandler;
+#[cfg(unix)]
+use rust_server::signal::{self, Signal};
+#[cfg(unix)]
+use rust_server::systemd;
 use std::env;
-use std::path::{Path, absolute};
-use website_handler::WebsiteHandler;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+#[cfg(unix)]
+use std::{io::Result as IoResult, process::Command as Process, thread, time::Duration};
 
 fn main() {
-    let default_path = format!("{}/public", env!("CARGO_MANIFEST_DIR"));
-    // you can also ues String::from("./public") as default value
-    let public_path = env::var("PUBLIC_PATH").unwrap_or(default_path);
-    let host = env::var("HOST").unwrap_or(String::from("127.0.0.1"));
-    let port = env::var("PORT").unwrap_or(String::from("8080"));
-    let server = Server::new(format!("{}:{}", host, port));
+    let command = match cli::parse_args(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!(
+                "Error: {}\nRun `{} --help` for usage.",
+                e,
+                env!("CARGO_PKG_NAME")
+            );
+            process::exit(2);
+        }
+    };
+    match command {
+        Command::Help => print!("{}", cli::usage()),
+        Command::Version => println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        Command::CheckConfig(options) => check_config(&options),
+        Command::Serve(options) => serve(options),
+        Command::HashPassword { user, iterations } => hash_password(&user, iterations),
+        Command::GenerateToken {
+            name,
+            scopes,
+            expires,
+        } => generate_token(&name, &scopes, expires.as_deref()),
+    }
+}
+
+/// Runs the server until it is stopped by SIGTERM or SIGINT, or upgraded with SIGUSR2.
+fn serve(options: Options) {
+    let config = match load_config(&options) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+    let server = match Server::new(config.server.clone()) {
+        Ok(server) => Arc::new(server),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
     println!("================================================");
-    println!("Server is running on http://{}", server.addr);
+    if socket_activated() {
+        println!("Server is running on the sockets passed by systemd");
+    } else {
+        for listener in &server.config().listeners {
+            match &listener.address {
+                ListenAddress::Tcp(addr) => println!("Server is running on http://{}", addr),
+                ListenAddress::Unix(_) => println!("Server is running on {}", listener.address),
+            }
+        }
+    }
+    let handler = match config.build_handler() {
+        Ok(handler) => Arc::new(ReloadableHandler::new(handler)),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+    println!("================================================");
+    #[cfg(unix)]
+    if let Err(e) = handle_signals(options, Arc::clone(&server), Arc::clone(&handler)) {
+        println!("Error: cannot watch signals\n{}", e);
+    }
+    if let Err(e) = server.run(handler) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+    let stats = server.connection_stats();
     println!(
-        "Serving files from public path: {}",
-        absolute(Path::new(&public_path)).unwrap().display()
+        "Server stopped: {} connections served, {} rejected over max_connections and {} over max_connections_per_ip, accepting paused for {:.1?}",
+        stats.accepted, stats.rejected, stats.rejected_per_ip, stats.paused
     );
-    println!("================================================");
-    server.run(WebsiteHandler::new(public_path));
+}
+
+/// Returns true if systemd passed the listening sockets, which replace the configured listeners.
+fn socket_activated() -> bool {
+    #[cfg(unix)]
+    return systemd::is_socket_activated();
+    #[cfg(not(unix))]
+    false
+}
+
+/// Validates the configuration and prints the effective settings, in the syntax of configuration files.
+/// Exits with status 1 if the configuration is invalid, a document root is missing or a credentials
+/// file, token store or JWT key cannot be loaded.
+fn check_config(options: &Options) {
+    let config = match load_config(options) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+    let roots = std::iter::once(&config.site)
+        .chain(config.routes.iter().map(|route| &route.site))
+        .filter_map(|site| site.root.as_ref());
+    let mut valid = true;
+    for root in roots {
+        if !root.is_dir() {
+            eprintln!(
+                "Error: the document root {} is not a directory",
+                root.display()
+            );
+            valid = false;
+        }
+    }
+    for realm in &config.basic_auth {
+        if let Err(e) = Credentials::load(&realm.credentials) {
+            eprintln!("Error: {}", e);
+            valid = false;
+        }
+    }
+    for policy in &config.token_auth {
+        if let Err(e) = TokenStore::load(&policy.tokens) {
+            eprintln!("Error: {}", e);
+            valid = false;
+        }
+    }
+    for policy in &config.jwt_auth {
+        for (_, path) in &policy.keys {
+            if let Err(e) = auth::load_jwt_key(path) {
+                eprintln!("Error: {}", e);
+                valid = false;
+            }
+        }
+    }
+    print!("{}", config);
+    if !valid {
+        process::exit(1);
+    }
+    eprintln!("The configuration is valid");
+}
+
+/// Returns the path of the configuration file, from `--config` or else `CONFIG_FILE`.
+fn config_path(options: &Options) -> Option<PathBuf> {
+    options
+        .config
+        .clone()
+        .or_else(|| env::var_os("CONFIG_FILE").map(PathBuf::from))
+}
+
+/// Loads the configuration file, if there is one, and applies the environment variables
+/// and then the command-line flags over it.
+fn load_config(options: &Options) -> Result<FileConfig, Box<dyn Error>> {
+    let mut config = match config_path(options) {
+        Some(path) => FileConfig::load(&path)?,
+        None => FileConfig::default(),
+    };
+    // a host or port replaces the configured listeners; given on its own, it keeps the other half
+    // of the first TCP address
+    let host = options.host.clone().or_else(|| env::var("HOST").ok());
+    let port = options
+        .port
+        .map(|port| port.to_string())
+        .or_else(|| env::var("PORT").ok());
+    if host.is_some() || port.is_some() {
+        let first = config
+            .server
+            .listeners
+            .iter()
+            .find_map(|listener| match &listener.address {
+                ListenAddress::Tcp(addr) => addr.rsplit_once(':'),
+                ListenAddress::Unix(_) => None,
+            });
+        let (default_host, default_port) = first.unwrap_or(("127.0.0.1", "8080"));
+        let host = host.unwrap_or(default_host.to_string());
+        let port = port.unwrap_or(default_port.to_string());
+        config.server = config.server.with_bind_addr(format!("{}:{}", host, port));
+    }
+    if let Some(root) = options
+        .root
+        .clone()
+        .or_else(|| env::var_os("PUBLIC_PATH").map(PathBuf::from))
+    {
+        config.site.root = Some(root);
+    }
+    if let Some(workers) = options.workers {
+        config.server.workers = workers;
+    }
+    // the file cache is off unless a budget is given, in MiB
+    if let Some(size) = env::var("CACHE_SIZE_MB")
+        .ok()
+        .and_then(|size| size.parse::<u64>().ok())
+    {
+        config.site.cache_size = Some(size * 1024 * 1024);
+    }
+    // single-page applications set this to the document serving their client-side routes
+    if let Ok(fallback) = env::var("SPA_FALLBACK") {
+        config.site.spa_fallback = Some(fallback);
+    }
+    config.server.validate()?;
+    Ok(config)
+}
+
+/// Handles the signals controlling the server: SIGHUP reloads the configuration file (if there is one),
+/// SIGTERM and SIGINT stop the server gracefully, and SIGUSR2 upgrades it to the binary now installed
+/// at the same path, started with the same arguments.
+///
+/// # Note
+///
+/// The listeners stay open during reloads and upgrades, so no connection is dropped: requests received
+/// after a reload use the new handler and new connections the new limits. Changes to the listen addresses
+/// and the number of workers need a restart, which an upgrade is. An invalid file is reported and ignored,
+/// and so is a new binary that fails to start.
+#[cfg(unix)]
+fn handle_signals(
+    options: Options,
+    server: Arc<Server>,
+    handler: Arc<ReloadableHandler<Box<dyn Handler>>>,
+) -> IoResult<()> {
+    // without a configuration file, there is nothing to reload
+    let reloads = config_path(&options).is_some();
+    if reloads {
+        signal::watch(Signal::Hangup)?;
+    }
+    signal::watch(Signal::Terminate)?;
+    signal::watch(Signal::Interrupt)?;
+    signal::watch(Signal::User2)?;
+    // the binary may be replaced while the server runs, so its path is taken now
+    let exe = env::current_exe()?;
+    let args: Vec<_> = env::args_os().skip(1).collect();
+    thread::spawn(move || {
+        loop {
+            thread::sleep(Duration::from_millis(250));
+            if signal::take(Signal::Terminate) | signal::take(Signal::Interrupt) {
+                println!("Stopping: finishing the requests in progress");
+                server.shutdown();
+                return;
+            }
+            if signal::take(Signal::User2) {
+                println!("Upgrading to {}", exe.display());
+                let mut command = Process::new(&exe);
+                command.args(&args);
+                match server.upgrade(command, Duration::from_secs(30)) {
+                    Ok(pid) => {
+                        println!(
+                            "Upgraded to process {}, finishing the requests in progress",
+                            pid
+                        );
+                        return;
+                    }
+                    Err(e) => println!("Error: cannot upgrade\n{}\nKeeping the current process", e),
+                }
+            }
+            if !reloads || !signal::take(Signal::Hangup) {
+                continue;
+            }
+            println!("Reloading the configuration");
+            // the flags and environment keep overriding the file, as they did at startup
+            let updated = load_config(&options).and_then(|config| {
+                // built first, so that a bad credentials file leaves the server as it was
+                let new_handler = config.build_handler()?;
+                let ignored = server.update_config(config.server.clone())?;
+                handler.replace(new_handler);
+                Ok(ignored)
+            });
+            match updated {
+                Ok(ignored) => {
+                    for name in ignored {
+                        println!("Warning: {} only changes when the server restarts", name);
+                    }
+                    println!("Configuration reloaded");
+                }
+                Err(e) => println!("Error: {}\nKeeping the current configuration", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Reads a password from the standard input and prints the line of a credentials file for it.
+fn hash_password(user: &str, iterations: u32) {
+    // the password is echoed by terminals, so it is better piped in, e.g. from a password manager
+    eprint!("Password for {}: ", user);
+    let mut line = String::new();
+    if let Err(e) = io::stdin().read_line(&mut line) {
+        eprintln!("Error: cannot read the password: {}", e);
+        process::exit(1);
+    }
+    let password = line.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        eprintln!("Error: the password is empty");
+        process::exit(1);
+    }
+    let salt = match random_bytes::<16>() {
+        Ok(salt) => salt,
+        Err(e) => {
+            eprintln!("Error: cannot generate a salt: {}", e);
+            process::exit(1);
+        }
+    };
+    let hash = auth::hash_password(password, &salt, iterations);
+    println!("{}:{}", user, hash);
+}
+
+/// Generates a token, printing it on the standard error and the line of a token store for it
+/// on the standard output, so that the line can be appended to the store.
+fn generate_token(name: &str, scopes: &[String], expires: Option<&str>) {
+    let bytes = match random_bytes::<32>() {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Error: cannot generate a token: {}", e);
+            process::exit(1);
+        }
+    };
+    let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    eprintln!("Token for {} (shown only once): {}", name, token);
+    let mut line = format!("{} {}", name, auth::hash_token(&token));
+    if !scopes.is_empty() {
+        line.push_str(&format!(" scopes={}", scopes.join(",")));
+    }
+    if let Some(expires) = expires {
+        line.push_str(&format!(" expires={}", expires));
+    }
+    println!("{}", line);
+}
+
+/// Returns random bytes for salts and tokens, from the system's generator.
+///
+/// # Returns
+///
+/// The bytes, or an error if the generator cannot be read: salts and tokens that could be guessed
+/// are worse than none.
+fn random_bytes<const N: usize>() -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    fs::File::open("/dev/urandom")
+        .and_then(|mut file| file.read_exact(&mut bytes))
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot read /dev/urandom: {}", e)))?;
+    Ok(bytes)
 }
@@ -0,0 +1,117 @@
+/// Values kept per path prefix, such as access lists or authentication realms,
+/// and found by the path of a request.
+///
+/// # Note
+///
+/// A prefix matches the path itself and every path under it: `/admin` matches `/admin/users`
+/// but not `/administrator`, a trailing slash makes no difference, and `/` matches every path.
+/// Only the value of the longest matching prefix is found, so a value for `/admin` replaces
+/// the one for the whole site under `/admin`. Paths are looked up after resolving `.`, `..` and
+/// empty segments, the way the file handlers do, so that `/public/../admin` cannot get around
+/// the value of `/admin`.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::prefix_table::PrefixTable;
+///
+/// let mut table = PrefixTable::new();
+/// table.insert("/", "site");
+/// table.insert("/admin/", "admin");
+/// assert_eq!(table.lookup("/admin/users"), Some(&"admin"));
+/// assert_eq!(table.lookup("/public/../admin"), Some(&"admin"));
+/// assert_eq!(table.lookup("/administrator"), Some(&"site"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct PrefixTable<T> {
+    entries: Vec<(String, T)>, // prefixes without a trailing slash, longest first
+}
+
+impl<T> PrefixTable<T> {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Stores a value for a prefix, replacing the value the prefix already had.
+    pub fn insert(&mut self, prefix: &str, value: T) {
+        let prefix = prefix.trim_end_matches('/');
+        self.entries.retain(|(existing, _)| existing != prefix);
+        let index = self
+            .entries
+            .partition_point(|(existing, _)| existing.len() >= prefix.len());
+        self.entries.insert(index, (prefix.to_string(), value));
+    }
+
+    /// Returns the value of the longest prefix matching a path, if any.
+    pub fn lookup(&self, path: &str) -> Option<&T> {
+        let path = normalize(path);
+        self.entries
+            .iter()
+            .find(|(prefix, _)| is_under(&path, prefix))
+            .map(|(_, value)| value)
+    }
+}
+
+impl<T> Default for PrefixTable<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns true if a normalized path is `prefix` or under it, `prefix` having no trailing slash.
+fn is_under(path: &str, prefix: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// Resolves the `.`, `..` and empty segments of a path, e.g. `/a//b/../c/.` into `/a/c`.
+/// Segments going above the root are dropped, as the file handlers refuse them anyway.
+fn normalize(path: &str) -> String {
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_longest_prefix_wins() {
+        let mut table = PrefixTable::new();
+        table.insert("/api", 1);
+        table.insert("/", 0);
+        table.insert("/api/admin/", 2);
+        assert_eq!(table.lookup("/api/admin/users"), Some(&2));
+        assert_eq!(table.lookup("/api/admin"), Some(&2));
+        assert_eq!(table.lookup("/api/administrator"), Some(&1));
+        assert_eq!(table.lookup("/apis"), Some(&0));
+        // a prefix given again replaces its value, with or without a trailing slash
+        table.insert("/api/", 3);
+        assert_eq!(table.lookup("/api/x"), Some(&3));
+        assert_eq!(PrefixTable::<u8>::new().lookup("/"), None);
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("/a//b/../c/."), "/a/c");
+        assert_eq!(normalize("/../.."), "/");
+        assert_eq!(normalize(""), "/");
+        assert!(is_under("/admin", "/admin"));
+        assert!(is_under("/admin/x", "/admin"));
+        assert!(!is_under("/administrator", "/admin"));
+        assert!(is_under("/", ""));
+    }
+}
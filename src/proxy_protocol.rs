@@ -0,0 +1,253 @@
+// The PROXY protocol of HAProxy, spoken by TCP load balancers that relay connections without
+// understanding HTTP: before the first request, the balancer sends a header with the addresses
+// of the connection it accepted, in text (version 1) or binary (version 2, with extensions).
+// See https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt
+
+use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// The start of every version 2 header.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The longest version 1 header: "PROXY TCP6 " with two full IPv6 addresses and ports, and CRLF.
+const V1_MAX_LEN: usize = 107;
+
+/// A PROXY protocol header.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ProxyHeader {
+    pub addresses: Option<(String, String)>, // source and destination, None if the balancer did not tell them
+    pub tlvs: Vec<(u8, Vec<u8>)>,            // type-length-value extensions of version 2, in order
+}
+
+/// Reads the PROXY protocol header at the start of a connection, and nothing after it.
+///
+/// # Returns
+///
+/// The header, or an `ErrorKind::InvalidData` error if the connection does not start with a valid one.
+/// The connection must be closed then: it did not come from the load balancer.
+pub(crate) fn read_header(stream: &mut impl Read) -> IoResult<ProxyHeader> {
+    // both versions are at least this long: "PROXY UNKNOWN\r\n" and the fixed part of version 2
+    let mut start = [0; 16];
+    stream.read_exact(&mut start[..15])?;
+    if start.starts_with(b"PROXY ") {
+        let mut line = start[..15].to_vec();
+        while !line.ends_with(b"\r\n") {
+            if line.len() >= V1_MAX_LEN {
+                return Err(invalid("the PROXY header is too long"));
+            }
+            let mut byte = [0];
+            stream.read_exact(&mut byte)?;
+            line.push(byte[0]);
+        }
+        return parse_v1(&line[..line.len() - 2]);
+    }
+    if start.starts_with(&V2_SIGNATURE) {
+        stream.read_exact(&mut start[15..])?;
+        let len = u16::from_be_bytes([start[14], start[15]]);
+        let mut payload = vec![0; usize::from(len)];
+        stream.read_exact(&mut payload)?;
+        return parse_v2(start[12], start[13], &payload);
+    }
+    Err(invalid("the connection does not start with a PROXY header"))
+}
+
+fn invalid(reason: &str) -> IoError {
+    IoError::new(ErrorKind::InvalidData, reason)
+}
+
+/// Parses a version 1 line without its CRLF, e.g. `PROXY TCP4 192.0.2.1 192.0.2.2 56324 443`.
+fn parse_v1(line: &[u8]) -> IoResult<ProxyHeader> {
+    let line = std::str::from_utf8(line).map_err(|_| invalid("the PROXY header is not text"))?;
+    let fields: Vec<&str> = line.split(' ').collect();
+    let addresses = match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => None,
+        [
+            "PROXY",
+            family @ ("TCP4" | "TCP6"),
+            source,
+            destination,
+            source_port,
+            destination_port,
+        ] => {
+            let ipv6 = *family == "TCP6";
+            let source = v1_addr(source, source_port, ipv6)?;
+            let destination = v1_addr(destination, destination_port, ipv6)?;
+            Some((source.to_string(), destination.to_string()))
+        }
+        _ => return Err(invalid("malformed PROXY header")),
+    };
+    Ok(ProxyHeader {
+        addresses,
+        tlvs: Vec::new(),
+    })
+}
+
+fn v1_addr(ip: &str, port: &str, ipv6: bool) -> IoResult<SocketAddr> {
+    let malformed = || invalid("malformed address in the PROXY header");
+    // ports are written without leading zeros or signs
+    if port.starts_with(['0', '+']) && port != "0" {
+        return Err(malformed());
+    }
+    let port: u16 = port.parse().map_err(|_| malformed())?;
+    let addr = if ipv6 {
+        ip.parse::<Ipv6Addr>().map(Into::into)
+    } else {
+        ip.parse::<Ipv4Addr>().map(Into::into)
+    };
+    Ok(SocketAddr::new(addr.map_err(|_| malformed())?, port))
+}
+
+/// Parses the rest of a version 2 header: the version and command, the address family and protocol,
+/// and the addresses followed by the TLVs.
+fn parse_v2(version_command: u8, family_protocol: u8, payload: &[u8]) -> IoResult<ProxyHeader> {
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    let local = match version_command & 0x0F {
+        0x0 => true, // LOCAL: a connection of the balancer itself, e.g. a health check
+        0x1 => false,
+        _ => return Err(invalid("unsupported PROXY command")),
+    };
+    let len = match family_protocol >> 4 {
+        0x0 => 0,   // AF_UNSPEC
+        0x1 => 12,  // AF_INET: two addresses and two ports
+        0x2 => 36,  // AF_INET6
+        0x3 => 216, // AF_UNIX: two paths of 108 bytes
+        _ => return Err(invalid("unsupported address family in the PROXY header")),
+    };
+    if payload.len() < len {
+        return Err(invalid("the PROXY header is too short for its addresses"));
+    }
+    let (addrs, mut tlvs) = payload.split_at(len);
+    let addresses = match family_protocol >> 4 {
+        _ if local => None,
+        0x1 => {
+            let ip = |at: usize| Ipv4Addr::from(<[u8; 4]>::try_from(&addrs[at..at + 4]).unwrap());
+            let port = |at: usize| u16::from_be_bytes([addrs[at], addrs[at + 1]]);
+            let source = SocketAddr::new(ip(0).into(), port(8));
+            let destination = SocketAddr::new(ip(4).into(), port(10));
+            Some((source.to_string(), destination.to_string()))
+        }
+        0x2 => {
+            let ip = |at: usize| Ipv6Addr::from(<[u8; 16]>::try_from(&addrs[at..at + 16]).unwrap());
+            let port = |at: usize| u16::from_be_bytes([addrs[at], addrs[at + 1]]);
+            let source = SocketAddr::new(ip(0).into(), port(32));
+            let destination = SocketAddr::new(ip(16).into(), port(34));
+            Some((source.to_string(), destination.to_string()))
+        }
+        0x3 => {
+            // the paths are padded with NUL bytes
+            let path = |bytes: &[u8]| {
+                let end = bytes
+                    .iter()
+                    .position(|byte| *byte == 0)
+                    .unwrap_or(bytes.len());
+                format!("unix:{}", String::from_utf8_lossy(&bytes[..end]))
+            };
+            Some((path(&addrs[..108]), path(&addrs[108..])))
+        }
+        _ => None,
+    };
+
+    let mut parsed = Vec::new();
+    while !tlvs.is_empty() {
+        let [kind, high, low, rest @ ..] = tlvs else {
+            return Err(invalid("truncated TLV in the PROXY header"));
+        };
+        let len = usize::from(u16::from_be_bytes([*high, *low]));
+        if rest.len() < len {
+            return Err(invalid("truncated TLV in the PROXY header"));
+        }
+        parsed.push((*kind, rest[..len].to_vec()));
+        tlvs = &rest[len..];
+    }
+    Ok(ProxyHeader {
+        addresses,
+        tlvs: parsed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Reads a header, and returns it along with what follows it on the connection.
+    fn read(bytes: &[u8]) -> IoResult<(ProxyHeader, Vec<u8>)> {
+        let mut stream = Cursor::new(bytes.to_vec());
+        let header = read_header(&mut stream)?;
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest)?;
+        Ok((header, rest))
+    }
+
+    fn addresses(source: &str, destination: &str) -> Option<(String, String)> {
+        Some((source.to_string(), destination.to_string()))
+    }
+
+    #[test]
+    fn test_v1_headers() {
+        let (header, rest) =
+            read(b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\nGET / HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(
+            header.addresses,
+            addresses("192.0.2.1:56324", "198.51.100.2:443")
+        );
+        assert_eq!(rest, b"GET / HTTP/1.1\r\n\r\n");
+
+        let (header, _) = read(b"PROXY TCP6 2001:db8::1 ::1 4711 80\r\n").unwrap();
+        assert_eq!(
+            header.addresses,
+            addresses("[2001:db8::1]:4711", "[::1]:80")
+        );
+        let (header, rest) = read(b"PROXY UNKNOWN ffff::1 ffff::2 1 2\r\nGET").unwrap();
+        assert_eq!(header.addresses, None);
+        assert_eq!(rest, b"GET");
+
+        for bad in [
+            &b"GET / HTTP/1.1\r\n\r\n"[..],
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 056324 443\r\n",
+            b"PROXY TCP4 2001:db8::1 ::1 4711 80\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 56324\r\n",
+        ] {
+            assert_eq!(read(bad).unwrap_err().kind(), ErrorKind::InvalidData);
+        }
+        let endless = [&b"PROXY UNKNOWN "[..], &[b'x'; 200]].concat();
+        assert_eq!(read(&endless).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_v2_headers() {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend([0x21, 0x11, 0, 12 + 9]); // PROXY over TCP/IPv4, 12 bytes of addresses and 9 of TLVs
+        header.extend([192, 0, 2, 1, 198, 51, 100, 2, 0xDC, 0x04, 0x01, 0xBB]);
+        header.extend([0x02, 0, 3, b'a', b'.', b'b']); // PP2_TYPE_AUTHORITY
+        header.extend([0x04, 0, 0]); // PP2_TYPE_NOOP
+        let (parsed, rest) = read(&[&header[..], b"GET"].concat()).unwrap();
+        assert_eq!(
+            parsed.addresses,
+            addresses("192.0.2.1:56324", "198.51.100.2:443")
+        );
+        assert_eq!(parsed.tlvs, [(0x02, b"a.b".to_vec()), (0x04, Vec::new())]);
+        assert_eq!(rest, b"GET");
+
+        // a health check of the balancer itself
+        let mut local = V2_SIGNATURE.to_vec();
+        local.extend([0x20, 0x00, 0, 0]);
+        let (parsed, _) = read(&local).unwrap();
+        assert_eq!(parsed.addresses, None);
+
+        let mut ipv6 = V2_SIGNATURE.to_vec();
+        ipv6.extend([0x21, 0x21, 0, 36]);
+        ipv6.extend(Ipv6Addr::LOCALHOST.octets());
+        ipv6.extend(Ipv6Addr::UNSPECIFIED.octets());
+        ipv6.extend([0, 80, 0, 81]);
+        let (parsed, _) = read(&ipv6).unwrap();
+        assert_eq!(parsed.addresses, addresses("[::1]:80", "[::]:81"));
+
+        // a TLV longer than the header
+        let mut truncated = V2_SIGNATURE.to_vec();
+        truncated.extend([0x21, 0x00, 0, 4, 0x02, 0, 9, b'a']);
+        assert_eq!(read(&truncated).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,524 @@
+use crate::handler_error::HandlerError;
+use crate::http::{Request, Response, StatusCode};
+use crate::server::{Handler, delegate_handler};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// The number of clients (or other keys) tracked by default, see `RateLimit::with_max_keys`.
+pub const DEFAULT_MAX_KEYS: usize = 10_000;
+
+/// How the requests of a key are counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// A bucket holding up to `burst` tokens, refilled at `limit` tokens per `window`: each request
+    /// takes a token, so clients can send a burst at once and then keep up with the refill rate.
+    TokenBucket,
+    /// At most `limit` requests in any `window`, estimated from the counts of the current and the
+    /// previous fixed windows, the previous one weighted by how much of it the sliding window covers.
+    SlidingWindow,
+}
+
+/// What the requests are counted by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RateLimitKey {
+    /// The client IP address, as reported by trusted proxies (see `Request::client_ip`).
+    /// IPv6 clients are counted by /64 network, the block a single host usually gets.
+    ClientIp,
+    /// The value of a header, e.g. an API key, for requests accepted by an authentication handler in
+    /// front of the limiter (see `Request::principal`). The value is chosen by the client, who could send
+    /// a new one with every request to get a new quota, so other requests are counted by client IP.
+    Header(String),
+    /// One count for every request reaching the limiter: wrapping the handler of a route caps
+    /// the route as a whole.
+    Route,
+}
+
+/// The settings of a `RateLimiter`.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::rate_limit::{Algorithm, RateLimit, RateLimitKey};
+/// use std::time::Duration;
+///
+/// // 10 requests per second per API key, in bursts of up to 50
+/// let limit = RateLimit::new(10, Duration::from_secs(1))
+///     .with_burst(50)
+///     .with_key(RateLimitKey::Header("X-API-Key".to_string()));
+/// assert_eq!(limit.algorithm, Algorithm::TokenBucket);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimit {
+    pub algorithm: Algorithm,
+    pub limit: u32,       // requests allowed per window, at least 1
+    pub window: Duration, // not zero
+    pub burst: u32, // capacity of the token bucket, the limit by default; unused by sliding windows
+    pub key: RateLimitKey,
+    pub max_keys: usize, // keys tracked at once, see `with_max_keys`
+}
+
+impl RateLimit {
+    /// Allows `limit` requests per `window` and key, with a token bucket as large as `limit`,
+    /// counted by client IP.
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            algorithm: Algorithm::TokenBucket,
+            limit,
+            window,
+            burst: limit,
+            key: RateLimitKey::ClientIp,
+            max_keys: DEFAULT_MAX_KEYS,
+        }
+    }
+
+    pub fn with_algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Sets how many requests a client that has been quiet can send at once (token bucket only).
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst;
+        self
+    }
+
+    pub fn with_key(mut self, key: RateLimitKey) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Sets how many keys are tracked at once, which bounds the memory of the limiter.
+    ///
+    /// # Note
+    ///
+    /// When a new key comes and the limit is reached, the least recently used key is forgotten if it is
+    /// back to a full quota, since a new entry for it would be the same. Keys that are still limited
+    /// are never forgotten, so that sending requests under new keys does not reset their quota: until
+    /// there is room again, new keys share a single quota (see `RateLimiter` for what this means).
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = max_keys;
+        self
+    }
+
+    /// Returns the number of requests a key can send at once.
+    fn capacity(&self) -> u32 {
+        match self.algorithm {
+            Algorithm::TokenBucket => self.burst.max(1),
+            Algorithm::SlidingWindow => self.limit.max(1),
+        }
+    }
+}
+
+/// What a limiter knows about the requests of a key.
+#[derive(Debug, Clone, Copy)]
+enum Usage {
+    Bucket {
+        tokens: f64,      // requests that can be sent right now, up to the burst
+        updated: Instant, // when `tokens` was computed
+    },
+    Window {
+        start: Instant, // start of the current fixed window
+        current: u32,   // requests in the current window
+        previous: u32,  // requests in the window before it
+    },
+}
+
+/// The answer of a limiter to a request.
+#[derive(Debug, Clone, Copy)]
+struct Decision {
+    allowed: bool,
+    remaining: u32,        // requests left right now
+    reset: Duration,       // until the quota is full again (token bucket) or the window ends
+    retry_after: Duration, // until a request would be allowed, zero if this one is
+}
+
+impl Usage {
+    fn new(limit: &RateLimit, now: Instant) -> Self {
+        match limit.algorithm {
+            Algorithm::TokenBucket => Usage::Bucket {
+                tokens: f64::from(limit.capacity()),
+                updated: now,
+            },
+            Algorithm::SlidingWindow => Usage::Window {
+                start: now,
+                current: 0,
+                previous: 0,
+            },
+        }
+    }
+
+    /// Returns the usage at `now`: the bucket refilled, or the windows moved forward.
+    fn at(self, limit: &RateLimit, now: Instant) -> Self {
+        let window = limit.window.max(Duration::from_millis(1));
+        match self {
+            Usage::Bucket { tokens, updated } => {
+                let rate = f64::from(limit.limit.max(1)) / window.as_secs_f64();
+                let refill = now.saturating_duration_since(updated).as_secs_f64() * rate;
+                Usage::Bucket {
+                    tokens: (tokens + refill).min(f64::from(limit.capacity())),
+                    updated: now,
+                }
+            }
+            Usage::Window { start, current, .. } => {
+                let elapsed = now.saturating_duration_since(start).as_nanos();
+                match elapsed / window.as_nanos() {
+                    0 => self,
+                    // the window ended: it becomes the previous one
+                    1 => Usage::Window {
+                        start: start + window,
+                        current: 0,
+                        previous: current,
+                    },
+                    // both windows ended; the current one started a fraction of a window ago
+                    _ => Usage::Window {
+                        start: now - Duration::from_nanos((elapsed % window.as_nanos()) as u64),
+                        current: 0,
+                        previous: 0,
+                    },
+                }
+            }
+        }
+    }
+
+    /// Returns true if the key has its full quota back, so forgetting it changes nothing.
+    fn is_idle(self, limit: &RateLimit, now: Instant) -> bool {
+        match self.at(limit, now) {
+            Usage::Bucket { tokens, .. } => tokens >= f64::from(limit.capacity()),
+            Usage::Window {
+                current, previous, ..
+            } => current == 0 && previous == 0,
+        }
+    }
+
+    /// Counts a request made at `now`, if it is allowed.
+    fn take(&mut self, limit: &RateLimit, now: Instant) -> Decision {
+        *self = self.at(limit, now);
+        let window = limit.window.max(Duration::from_millis(1)).as_secs_f64();
+        let capacity = f64::from(limit.capacity());
+        match self {
+            Usage::Bucket { tokens, .. } => {
+                let rate = f64::from(limit.limit.max(1)) / window;
+                let allowed = *tokens >= 1.0;
+                if allowed {
+                    *tokens -= 1.0;
+                }
+                Decision {
+                    allowed,
+                    remaining: tokens.floor() as u32,
+                    reset: Duration::from_secs_f64((capacity - *tokens) / rate),
+                    retry_after: Duration::from_secs_f64(if allowed {
+                        0.0
+                    } else {
+                        (1.0 - *tokens) / rate
+                    }),
+                }
+            }
+            Usage::Window {
+                start,
+                current,
+                previous,
+            } => {
+                // how far into the current window we are, from 0 to 1
+                let position = now.saturating_duration_since(*start).as_secs_f64() / window;
+                let estimate = f64::from(*previous) * (1.0 - position) + f64::from(*current);
+                let allowed = estimate + 1.0 <= capacity;
+                if allowed {
+                    *current += 1;
+                }
+                let retry_after = if allowed {
+                    0.0
+                } else if f64::from(*current) + 1.0 <= capacity {
+                    // the previous window has to slide out enough to make room
+                    let weight = (capacity - 1.0 - f64::from(*current)) / f64::from(*previous);
+                    (1.0 - weight - position) * window
+                } else {
+                    // the current window is full: wait for it to end, and for enough of it to slide out
+                    let weight = (capacity - 1.0) / f64::from(*current);
+                    (1.0 - position + 1.0 - weight) * window
+                };
+                let used = estimate + f64::from(u8::from(allowed));
+                Decision {
+                    allowed,
+                    remaining: (capacity - used).max(0.0).floor() as u32,
+                    reset: Duration::from_secs_f64((1.0 - position) * window),
+                    retry_after: Duration::from_secs_f64(retry_after.max(0.0)),
+                }
+            }
+        }
+    }
+}
+
+/// The keys being tracked, with their usage.
+#[derive(Default)]
+struct Entries {
+    keys: HashMap<String, (Usage, u64)>, // each key along with the tick it was last used at
+    recency: BTreeMap<u64, String>,      // keys ordered from least to most recently used
+    tick: u64,
+    overflow: Option<Usage>, // shared by the new keys that come when no tracked key can be forgotten
+}
+
+/// A handler limiting how many requests each client (or API key, or route) can send, and answering
+/// the others with 429 Too Many Requests.
+///
+/// # Note
+///
+/// The responses of the wrapped handler and the 429 responses get `RateLimit-Limit`, `RateLimit-Remaining`
+/// and `RateLimit-Reset` headers (the quota, the requests left, and the seconds until the quota is full
+/// again), and 429 responses a `Retry-After` header with the seconds until a request would be allowed.
+/// The body of 429 responses is the error page of the wrapped handler. Errors the wrapped handler
+/// returns as a `HandlerError` are passed on as they are, for the server to log and answer, without
+/// these headers. The counts are kept in memory, for as many keys as `RateLimit::max_keys`, and are
+/// lost when the limiter is dropped (e.g. when the server reloads its configuration).
+///
+/// Once `max_keys` keys are tracked and the least recently used one is still limited, new keys share
+/// a single quota. A client sending requests under enough keys at once (e.g. from many addresses)
+/// can use that quota up and keep new clients out, while the clients already tracked keep theirs:
+/// set `max_keys` above the number of clients expected within a window.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::WebsiteHandler;
+/// use rust_server::rate_limit::{Algorithm, RateLimit, RateLimiter};
+/// use rust_server::router::{Route, Router};
+/// use std::time::Duration;
+///
+/// // 100 requests per minute per client, and 5 per minute on the login pages
+/// let login = RateLimiter::new(
+///     WebsiteHandler::new("login".to_string()),
+///     RateLimit::new(5, Duration::from_secs(60)).with_algorithm(Algorithm::SlidingWindow),
+/// );
+/// let router = Router::new(WebsiteHandler::new("public".to_string()))
+///     .with_route(Route::new("/login", login));
+/// let handler = RateLimiter::new(router, RateLimit::new(100, Duration::from_secs(60)));
+/// ```
+pub struct RateLimiter<H> {
+    handler: H,
+    limit: RateLimit,
+    entries: Mutex<Entries>,
+}
+
+impl<H: Handler> RateLimiter<H> {
+    pub fn new(handler: H, limit: RateLimit) -> Self {
+        Self {
+            handler,
+            limit,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Returns the number of keys being tracked.
+    pub fn tracked_keys(&self) -> usize {
+        self.entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys
+            .len()
+    }
+
+    /// Returns the key a request is counted by.
+    fn key_of(&self, request: &Request) -> String {
+        let client_ip = || match request.client_ip() {
+            // a host usually gets a whole /64, and could otherwise use a new address for every request
+            Some(IpAddr::V6(ip)) => format!("{}/64", Ipv6Addr::from(u128::from(ip) >> 64 << 64)),
+            Some(ip) => ip.to_string(),
+            None => "-".to_string(), // a Unix domain socket without a proxy reporting the client
+        };
+        match &self.limit.key {
+            RateLimitKey::ClientIp => client_ip(),
+            RateLimitKey::Header(name) => match request.header(name) {
+                Some(value) if request.principal().is_some() => format!("{}: {}", name, value),
+                _ => client_ip(),
+            },
+            RateLimitKey::Route => String::new(),
+        }
+    }
+
+    /// Counts a request of `key` made at `now`.
+    fn check(&self, key: String, now: Instant) -> Decision {
+        let limit = &self.limit;
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let entries = &mut *entries;
+        if !entries.keys.contains_key(&key) && entries.keys.len() >= limit.max_keys.max(1) {
+            // the least recently used key makes room if it has its full quota back
+            let oldest = entries
+                .recency
+                .first_entry()
+                .filter(|oldest| entries.keys[oldest.get()].0.is_idle(limit, now));
+            match oldest {
+                Some(oldest) => {
+                    entries.keys.remove(&oldest.remove());
+                }
+                None => {
+                    let usage = entries
+                        .overflow
+                        .get_or_insert_with(|| Usage::new(limit, now));
+                    return usage.take(limit, now);
+                }
+            }
+        }
+        entries.tick += 1;
+        let tick = entries.tick;
+        let (usage, last_used) = match entries.keys.get_mut(&key) {
+            Some(entry) => entry,
+            None => entries
+                .keys
+                .entry(key.clone())
+                .or_insert((Usage::new(limit, now), tick)),
+        };
+        entries.recency.remove(last_used);
+        *last_used = tick;
+        entries.recency.insert(tick, key);
+        usage.take(limit, now)
+    }
+}
+
+/// Formats a duration as a number of seconds, rounded up so that clients do not come back too early.
+fn seconds(duration: Duration) -> String {
+    duration.as_secs_f64().ceil().to_string()
+}
+
+impl<H: Handler> Handler for RateLimiter<H> {
+    fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+        let decision = self.check(self.key_of(request), Instant::now());
+        let mut response = if decision.allowed {
+            self.handler.handle_request(request)?
+        } else {
+            Response::new(StatusCode::TooManyRequests, None).with_header(
+                "Retry-After",
+                seconds(decision.retry_after.max(Duration::from_secs(1))),
+            )
+        };
+        response.set_header("RateLimit-Limit", self.limit.capacity().to_string());
+        response.set_header("RateLimit-Remaining", decision.remaining.to_string());
+        response.set_header("RateLimit-Reset", seconds(decision.reset));
+        Ok(response)
+    }
+
+    delegate_handler!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Principal;
+    use crate::testing::OkHandler;
+
+    fn limiter(limit: RateLimit) -> RateLimiter<OkHandler> {
+        RateLimiter::new(OkHandler, limit)
+    }
+
+    /// Sends requests of `key` at `at`, returning how many were allowed.
+    fn allowed(limiter: &RateLimiter<OkHandler>, key: &str, at: Instant, count: usize) -> usize {
+        (0..count)
+            .filter(|_| limiter.check(key.to_string(), at).allowed)
+            .count()
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        // 2 requests per second, in bursts of 5
+        let limiter = limiter(RateLimit::new(2, Duration::from_secs(1)).with_burst(5));
+        let start = Instant::now();
+        assert_eq!(allowed(&limiter, "a", start, 10), 5);
+        let denied = limiter.check("a".to_string(), start);
+        assert_eq!(denied.remaining, 0);
+        assert_eq!(denied.retry_after, Duration::from_millis(500));
+        assert_eq!(denied.reset, Duration::from_millis(2500));
+        // other keys have their own bucket
+        assert_eq!(allowed(&limiter, "b", start, 1), 1);
+        // one token every half second
+        let later = start + Duration::from_millis(1100);
+        assert_eq!(allowed(&limiter, "a", later, 10), 2);
+        let later = start + Duration::from_secs(60);
+        assert_eq!(allowed(&limiter, "a", later, 10), 5);
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let limiter = limiter(
+            RateLimit::new(10, Duration::from_secs(10)).with_algorithm(Algorithm::SlidingWindow),
+        );
+        let start = Instant::now();
+        assert_eq!(allowed(&limiter, "a", start, 4), 4);
+        let decision = limiter.check("a".to_string(), start + Duration::from_secs(9));
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 5);
+        assert_eq!(decision.reset, Duration::from_secs(1));
+        assert_eq!(
+            allowed(&limiter, "a", start + Duration::from_secs(9), 10),
+            5
+        );
+        let denied = limiter.check("a".to_string(), start + Duration::from_secs(9));
+        // the window is full: it has to end, and half of it to slide out
+        assert_eq!(denied.retry_after.as_millis(), 1000 + 1000);
+        // halfway through the next window, the 10 requests of the previous one count for 5
+        let later = start + Duration::from_secs(15);
+        assert_eq!(allowed(&limiter, "a", later, 10), 5);
+        let later = start + Duration::from_secs(35);
+        assert_eq!(allowed(&limiter, "a", later, 20), 10);
+    }
+
+    #[test]
+    fn test_idle_keys_are_evicted() {
+        let limiter = limiter(RateLimit::new(1, Duration::from_secs(1)).with_max_keys(2));
+        let start = Instant::now();
+        allowed(&limiter, "a", start, 1);
+        allowed(&limiter, "b", start + Duration::from_millis(10), 1);
+        // every key is limited: they are kept, and new keys share a quota
+        let at = start + Duration::from_millis(20);
+        assert_eq!(allowed(&limiter, "c", at, 1), 1);
+        assert_eq!(allowed(&limiter, "d", at, 1), 0);
+        assert_eq!(limiter.tracked_keys(), 2);
+        assert_eq!(
+            allowed(&limiter, "a", start + Duration::from_millis(30), 1),
+            0
+        );
+        // once its bucket is full again, the least recently used key makes room for a new one
+        assert_eq!(allowed(&limiter, "e", start + Duration::from_secs(5), 1), 1);
+        assert_eq!(limiter.tracked_keys(), 2);
+        let entries = limiter.entries.lock().unwrap();
+        assert!(entries.keys.contains_key("a") && !entries.keys.contains_key("b"));
+    }
+
+    #[test]
+    fn test_rate_limit_headers() {
+        let limiter = limiter(
+            RateLimit::new(1, Duration::from_secs(60))
+                .with_key(RateLimitKey::Header("X-API-Key".to_string())),
+        );
+        // as if an authentication handler had accepted the keys
+        let principal = Principal::new("client");
+        let head = b"GET / HTTP/1.1\r\nX-API-Key: k1\r\n\r\n";
+        let request = Request::try_from(&head[..])
+            .unwrap()
+            .with_principal(&principal);
+        let response = limiter.handle_request(&request).unwrap();
+        assert_eq!(response.status_code(), StatusCode::Ok);
+        assert_eq!(response.header("RateLimit-Limit"), Some("1"));
+        assert_eq!(response.header("RateLimit-Remaining"), Some("0"));
+        assert_eq!(response.header("RateLimit-Reset"), Some("60"));
+        let response = limiter.handle_request(&request).unwrap();
+        assert_eq!(response.status_code(), StatusCode::TooManyRequests);
+        assert_eq!(response.header("Retry-After"), Some("60"));
+        // another key
+        let head = b"GET / HTTP/1.1\r\nX-API-Key: k2\r\n\r\n";
+        let request = Request::try_from(&head[..])
+            .unwrap()
+            .with_principal(&principal);
+        let response = limiter.handle_request(&request).unwrap();
+        assert_eq!(response.status_code(), StatusCode::Ok);
+        // keys that were not accepted are not trusted: the client is counted by address
+        for key in ["k3", "k4"] {
+            let head = format!("GET / HTTP/1.1\r\nX-API-Key: {}\r\n\r\n", key);
+            let request = Request::try_from(head.as_bytes()).unwrap();
+            limiter.handle_request(&request).unwrap();
+        }
+        let head = b"GET / HTTP/1.1\r\nX-API-Key: k5\r\n\r\n";
+        let request = Request::try_from(&head[..]).unwrap();
+        let response = limiter.handle_request(&request).unwrap();
+        assert_eq!(response.status_code(), StatusCode::TooManyRequests);
+    }
+}
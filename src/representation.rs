@@ -0,0 +1,210 @@
+use crate::compression::ContentEncoding;
+use crate::http::{
+    ByteRange, Request, Response, StatusCode, accepts_media_type, coalesce_ranges,
+    generate_boundary, multipart_byteranges, multipart_byteranges_len, parse_range_header,
+};
+use std::fs::File;
+use std::io::{Read, Result as IoResult, Seek, SeekFrom};
+use std::sync::Arc;
+
+/// Derives the entity tag of an encoded representation from the tag of the file, since the
+/// gzip and identity versions of a file are different representations (RFC 9110, section 8.8.3.3).
+pub fn encoded_etag(etag: &str, encoding: ContentEncoding) -> String {
+    let opaque = etag.trim_end_matches('"');
+    format!("{}-{}\"", opaque, encoding.as_str())
+}
+
+/// The bytes of the representation being served.
+pub enum Contents {
+    File(File),       // the file itself, read on demand so a range never loads the whole file
+    Bytes(Arc<[u8]>), // the file or an encoded copy of it, held in memory
+    Static(&'static [u8]), // the file or an encoded copy of it, embedded in the binary
+}
+
+impl Contents {
+    /// Reads the bytes covered by `range`.
+    fn read_range(&mut self, range: ByteRange) -> IoResult<Vec<u8>> {
+        match self {
+            Contents::File(file) => {
+                let mut bytes = Vec::with_capacity(range.len() as usize);
+                file.seek(SeekFrom::Start(range.start))?;
+                file.take(range.len()).read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }
+            Contents::Bytes(bytes) => Ok(bytes[range.start as usize..=range.end as usize].to_vec()),
+            Contents::Static(bytes) => {
+                Ok(bytes[range.start as usize..=range.end as usize].to_vec())
+            }
+        }
+    }
+
+    /// Reads all the bytes.
+    fn read_all(self) -> IoResult<Vec<u8>> {
+        match self {
+            Contents::File(mut file) => {
+                let mut bytes = Vec::new();
+                file.read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }
+            Contents::Bytes(bytes) => Ok(bytes.to_vec()),
+            Contents::Static(bytes) => Ok(bytes.to_vec()),
+        }
+    }
+}
+
+/// A representation of a file selected for a response, along with its metadata.
+pub struct Representation {
+    pub contents: Contents,
+    pub len: u64,                          // length of `contents`
+    pub content_type: &'static str,        // media type of the file
+    pub encoding: Option<ContentEncoding>, // content coding applied to `contents`, if any
+    pub etag: String,                      // strong entity tag of this representation
+    pub last_modified: Option<String>,     // modification date of the file, as an HTTP-date
+    pub varies: bool,                      // whether another representation could have been chosen
+}
+
+impl Representation {
+    /// Builds the response for this representation, honouring the `Range` and `If-Range` headers.
+    ///
+    /// # Note
+    ///
+    /// Without a `Range` header (or when `If-Range` no longer matches) the whole representation is sent with 200 OK.
+    /// A single range is sent with 206 Partial Content and a `Content-Range` header, while several ranges
+    /// are sent as a `multipart/byteranges` body. Ranges that cannot be satisfied get 416 Range Not Satisfiable.
+    /// Ranges that overlap or touch are merged first, so that no byte is sent twice. Several ranges
+    /// of an encoded representation are answered with the whole representation instead, since a multipart
+    /// body has no way to say that only its parts are encoded, and so are ranges whose multipart body
+    /// would be at least as large as the whole representation.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request being answered.
+    /// * `file_path` - The path of the file, used in log messages.
+    pub fn respond(self, request: &Request, file_path: &str) -> Response {
+        let varies = self.varies;
+        let response = self.respond_to_ranges(request, file_path);
+        // the response depends on Accept-Encoding whenever an encoded version could have been chosen
+        if varies {
+            response.with_header("Vary", "Accept-Encoding")
+        } else {
+            response
+        }
+    }
+
+    /// Builds the response for this representation, apart from the `Vary` header.
+    fn respond_to_ranges(self, request: &Request, file_path: &str) -> Response {
+        let Representation {
+            mut contents,
+            len,
+            content_type,
+            encoding,
+            etag,
+            last_modified,
+            ..
+        } = self;
+
+        let boundary = generate_boundary();
+        let ranges = match request.header("Range") {
+            Some(value) if if_range_matches(request, &etag, last_modified.as_deref()) => {
+                match parse_range_header(value, len).map(coalesce_ranges) {
+                    Ok(ranges) if ranges.len() > 1 && encoding.is_some() => None,
+                    Ok(ranges)
+                        if ranges.len() > 1
+                            && multipart_byteranges_len(&boundary, content_type, len, &ranges)
+                                >= len =>
+                    {
+                        None
+                    }
+                    Ok(ranges) => Some(ranges),
+                    // unknown range units and abusive range lists are ignored, as if there was no Range header
+                    Err(e) if e.is_ignorable() => None,
+                    Err(e) => {
+                        println!("Error: {} ({}) for {}", e, value, file_path);
+                        return Response::new(StatusCode::RangeNotSatisfiable, None)
+                            .with_header("Content-Range", format!("bytes */{}", len))
+                            .with_header("Accept-Ranges", "bytes");
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        let result = match ranges.as_deref() {
+            None => contents.read_all().map(|bytes| {
+                Response::from_bytes(StatusCode::Ok, bytes)
+                    .with_header("Content-Type", content_type)
+            }),
+            Some([range]) => contents.read_range(*range).map(|bytes| {
+                Response::from_bytes(StatusCode::PartialContent, bytes)
+                    .with_header("Content-Type", content_type)
+                    .with_header("Content-Range", range.content_range(len))
+            }),
+            Some(ranges) => ranges
+                .iter()
+                .map(|range| contents.read_range(*range).map(|bytes| (*range, bytes)))
+                .collect::<IoResult<Vec<_>>>()
+                .map(|parts| {
+                    let parts: Vec<(ByteRange, &[u8])> = parts
+                        .iter()
+                        .map(|(range, bytes)| (*range, bytes.as_slice()))
+                        .collect();
+                    let body = multipart_byteranges(&boundary, content_type, len, &parts);
+                    Response::from_bytes(StatusCode::PartialContent, body).with_header(
+                        "Content-Type",
+                        format!("multipart/byteranges; boundary={}", boundary),
+                    )
+                }),
+        };
+
+        match result {
+            Ok(mut response) => {
+                if let Some(encoding) = encoding {
+                    response.set_header("Content-Encoding", encoding.as_str());
+                }
+                response.set_header("Accept-Ranges", "bytes");
+                response.set_header("ETag", etag);
+                if let Some(last_modified) = last_modified {
+                    response.set_header("Last-Modified", last_modified);
+                }
+                response
+            }
+            Err(e) => {
+                println!("Error reading file: {}, error: {}", file_path, e);
+                Response::new(StatusCode::NotFound, None)
+            }
+        }
+    }
+}
+
+/// Decides whether a request for a missing file should get the single-page application
+/// fallback document, because it is most likely a client-side route such as `/reports/42`.
+///
+/// # Arguments
+///
+/// * `request` - The request being answered.
+/// * `path` - The path that was not found.
+///
+/// # Returns
+///
+/// `true` if the last segment of the path has no extension (so it does not look like an asset)
+/// and the client accepts HTML.
+pub fn is_spa_route(request: &Request, path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    !name.contains('.') && accepts_media_type(request.header("Accept"), "text/html")
+}
+
+/// Evaluates the `If-Range` precondition (RFC 9110, section 13.1.5).
+///
+/// # Returns
+///
+/// `true` if the `Range` header should be honoured: either there is no `If-Range` header,
+/// or it names the current strong entity tag or the exact `Last-Modified` date of the file.
+fn if_range_matches(request: &Request, etag: &str, last_modified: Option<&str>) -> bool {
+    match request.header("If-Range") {
+        None => true,
+        // weak entity tags ("W/...") never match, because If-Range requires a strong comparison
+        Some(value) if value.starts_with('"') => value == etag,
+        Some(value) if value.starts_with("W/") => false,
+        Some(value) => last_modified == Some(value),
+    }
+}
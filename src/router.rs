@@ -0,0 +1,185 @@
+use crate::handler_error::HandlerError;
+use crate::http::{Method, ParseError, Request, Response, StatusCode};
+use crate::server::Handler;
+
+/// A handler serving the requests under a path prefix, e.g. `/docs`.
+pub struct Route {
+    prefix: String,                 // without a trailing slash
+    handler: Box<dyn Handler>,      // receives paths relative to the prefix
+    headers: Vec<(String, String)>, // added to the responses of the route
+}
+
+impl Route {
+    /// Creates a route.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The path prefix, e.g. `/docs`, which matches `/docs` and every path under `/docs/`.
+    ///   A trailing slash is ignored.
+    /// * `handler` - The handler serving the route. It receives paths without the prefix,
+    ///   so `/docs/guide.html` reaches it as `/guide.html` (and `/docs` as `/`).
+    pub fn new(prefix: &str, handler: impl Handler + 'static) -> Self {
+        Self {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            handler: Box::new(handler),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Adds a header to the responses of the route, replacing a header of the same name set by the router.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Returns the path relative to the route, if the route serves it.
+    fn strip<'p>(&self, path: &'p str) -> Option<&'p str> {
+        match path.strip_prefix(&self.prefix)? {
+            "" => Some("/"),
+            rest if rest.starts_with('/') => Some(rest),
+            _ => None, // "/docsearch" is not under "/docs"
+        }
+    }
+}
+
+/// A handler dispatching requests to routes by path prefix, with a fallback handler for the rest,
+/// and adding configured headers (e.g. security headers) to the responses.
+///
+/// # Note
+///
+/// The router answers the methods of the fallback handler, which also renders the error pages.
+/// Headers are added to the responses returned by the handlers, including their error responses,
+/// but not to the responses the server sends for a `HandlerError`.
+///
+/// # Example
+///
+/// ```
+/// use rust_server::WebsiteHandler;
+/// use rust_server::router::{Route, Router};
+///
+/// let router = Router::new(WebsiteHandler::new("public".to_string()))
+///     .with_header("X-Content-Type-Options", "nosniff")
+///     .with_route(
+///         Route::new("/docs", WebsiteHandler::new("docs".to_string()))
+///             .with_header("Cache-Control", "max-age=3600"),
+///     );
+/// ```
+pub struct Router {
+    fallback: Box<dyn Handler>,
+    routes: Vec<Route>, // longest prefix first, so nested routes take precedence
+    headers: Vec<(String, String)>, // added to every response
+}
+
+impl Router {
+    /// Creates a router without routes, sending every request to `fallback`.
+    pub fn new(fallback: impl Handler + 'static) -> Self {
+        Self {
+            fallback: Box::new(fallback),
+            routes: Vec::new(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Adds a route. When prefixes overlap (e.g. `/docs` and `/docs/api`), the longest one wins.
+    pub fn with_route(mut self, route: Route) -> Self {
+        let index = self
+            .routes
+            .partition_point(|existing| existing.prefix.len() >= route.prefix.len());
+        self.routes.insert(index, route);
+        self
+    }
+
+    /// Adds a header to every response.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+impl Handler for Router {
+    fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+        let route = self
+            .routes
+            .iter()
+            .find_map(|route| Some((route, route.strip(request.path())?)));
+        let (mut response, route_headers) = match route {
+            Some((route, path)) => (
+                route
+                    .handler
+                    .handle_request(&request.clone().with_path(path))?,
+                route.headers.as_slice(),
+            ),
+            None => (self.fallback.handle_request(request)?, &[][..]),
+        };
+        for (name, value) in self.headers.iter().chain(route_headers) {
+            response.set_header(name, value.as_str());
+        }
+        Ok(response)
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.fallback.handle_bad_request(e)
+    }
+
+    fn allowed_methods(&self) -> &[Method] {
+        self.fallback.allowed_methods()
+    }
+
+    fn render_error(&self, status: StatusCode, accept: Option<&str>) -> Response {
+        self.fallback.render_error(status, accept)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A handler answering with its name and the path it received.
+    struct EchoHandler(&'static str);
+
+    impl Handler for EchoHandler {
+        fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+            let body = format!("{} {}", self.0, request.path());
+            Ok(Response::new(StatusCode::Ok, Some(body)))
+        }
+    }
+
+    fn get(router: &Router, path: &str) -> Response {
+        let raw = format!("GET {} HTTP/1.1\r\n\r\n", path);
+        let request = Request::try_from(raw.as_bytes()).unwrap();
+        router.handle_request(&request).unwrap()
+    }
+
+    fn body(response: &Response) -> &str {
+        std::str::from_utf8(response.body().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_routes_by_longest_prefix() {
+        let router = Router::new(EchoHandler("site"))
+            .with_route(Route::new("/docs/", EchoHandler("docs")))
+            .with_route(Route::new("/docs/api", EchoHandler("api")));
+        assert_eq!(body(&get(&router, "/docs/guide.html")), "docs /guide.html");
+        assert_eq!(body(&get(&router, "/docs")), "docs /");
+        assert_eq!(body(&get(&router, "/docs/api/v1")), "api /v1");
+        assert_eq!(body(&get(&router, "/docsearch")), "site /docsearch");
+        assert_eq!(body(&get(&router, "/")), "site /");
+    }
+
+    #[test]
+    fn test_adds_headers() {
+        let router = Router::new(EchoHandler("site"))
+            .with_header("X-Frame-Options", "DENY")
+            .with_header("Cache-Control", "no-cache")
+            .with_route(
+                Route::new("/assets", EchoHandler("assets"))
+                    .with_header("Cache-Control", "max-age=3600"),
+            );
+        let response = get(&router, "/");
+        assert_eq!(response.header("X-Frame-Options"), Some("DENY"));
+        assert_eq!(response.header("Cache-Control"), Some("no-cache"));
+        let response = get(&router, "/assets/app.js");
+        assert_eq!(response.header("X-Frame-Options"), Some("DENY"));
+        assert_eq!(response.header("Cache-Control"), Some("max-age=3600"));
+    }
+}
@@ -1,92 +1,1601 @@
-/// Server module for handling incoming TCP connections and parsing HTTP requests.
+//! Server module for handling incoming TCP connections and parsing HTTP requests.
+//!
+//! This module defines the `Server` struct, which binds the listeners of its `ServerConfig`
+//! (TCP or Unix domain sockets), accepts connections on them, reads HTTP requests from clients,
+//! and hands them to a pool of worker threads sharing one `Handler`. Connections are kept alive
+//! between requests (including pipelined ones) within the limits of the configuration.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rust_server::config::ServerConfig;
+//! use rust_server::{Server, WebsiteHandler};
+//!
+//! let config = ServerConfig::new().with_bind_addr("127.0.0.1:8080");
+//! let server = Server::new(config).expect("invalid configuration");
+//! server.run(WebsiteHandler::new("public".to_string())).expect("server failed");
+//! ```
+
+use crate::config::{ConfigError, LogLevel, Overload, ServerConfig};
+use crate::error_page::error_response;
+use crate::handler_error::HandlerError;
+use crate::http::{
+    ConnectionInfo, Method, ParseError, ProxyInfo, Request, Response, StatusCode, forwarded_client,
+};
+use crate::listener::{Listener, Stream};
+use crate::proxy_protocol;
+#[cfg(unix)]
+use crate::{systemd, upgrade};
+use std::any::Any;
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Result as IoResult, Write}; // For reading from and writing to the connections
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::panic::{self, AssertUnwindSafe}; // For keeping the server alive when a handler panics
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(unix)]
+use std::{io::Error as IoError, os::fd::RawFd, process::Command};
+
+/// Request bodies up to this size are read and discarded so the connection can be reused,
+/// since no handler reads them yet. Connections sending larger bodies are closed after the response.
+const MAX_DISCARDED_BODY: u64 = 64 * 1024;
+
+/// How often the accept loops check whether the server is stopping.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long the accept loops wait after a failed accept, doubling while the errors go on (such as
+/// running out of file descriptors), up to `ACCEPT_INTERVAL` so that stopping is still noticed.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+
+/// A trait for handling HTTP requests. Instead of implementing handling logic over
+/// and over in the `Server` struct, we can implement it in a separate struct
+/// and pass it to the `Server` as a parameter, in order to reduce repetition.
+/// This is useful for testing and for implementing different handlers for different routes.
 ///
-/// This module defines the `Server` struct, which binds to a specified address,
-/// listens for incoming TCP connections, reads HTTP requests from clients, and
-/// attempts to parse them using the `Request` struct from the `http` module.
+/// # Note
+///
+/// The server answers requests from several worker threads at once, all sharing the same handler,
+/// so handlers take `&self` and keep any mutable state behind a `Mutex` or atomics.
+pub trait Handler: Send + Sync {
+    /// Answers a request.
+    ///
+    /// # Note
+    ///
+    /// An `Err` is sent as a response with the status of the error (see `HandlerError::status_code`).
+    /// A panic is caught by the server, logged and answered with 500 Internal Server Error,
+    /// and the server keeps serving other requests with the same handler.
+    fn handle_request(&self, request: &Request) -> Result<Response, HandlerError>;
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        println!("Error: parsing request\n{}", e);
+        Response::new(StatusCode::BadRequest, None)
+    }
+
+    /// Returns the methods `handle_request` knows how to answer, used to build the `Allow` header.
+    ///
+    /// # Note
+    ///
+    /// The server answers HEAD requests itself by running the GET path and dropping the body,
+    /// and answers OPTIONS requests with the `Allow` header, so neither needs to be listed here.
+    /// A handler that lists HEAD or OPTIONS receives those requests directly instead.
+    /// Requests with any other method get 405 Method Not Allowed without reaching the handler.
+    fn allowed_methods(&self) -> &[Method] {
+        &[Method::GET]
+    }
+
+    /// Renders the error page sent when a response with an error status has no body,
+    /// whether it comes from `handle_request`, `handle_bad_request` or the server itself (e.g. 405).
+    ///
+    /// # Note
+    ///
+    /// The default is a built-in HTML page showing the status and reason phrase,
+    /// or problem details as JSON (RFC 9457) for clients that prefer `application/json`.
+    /// Only the body and its headers are used; the status and other headers of the original response are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The error status.
+    /// * `accept` - The value of the request's `Accept` header, if there is one (never for unparseable requests).
+    fn render_error(&self, status: StatusCode, accept: Option<&str>) -> Response {
+        error_response(status, accept, None)
+    }
+}
+
+// Boxed and shared handlers are handlers too, so they can be stored in routers or kept by the caller.
+impl<H: Handler + ?Sized> Handler for Box<H> {
+    fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+        (**self).handle_request(request)
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        (**self).handle_bad_request(e)
+    }
+
+    fn allowed_methods(&self) -> &[Method] {
+        (**self).allowed_methods()
+    }
+
+    fn render_error(&self, status: StatusCode, accept: Option<&str>) -> Response {
+        (**self).render_error(status, accept)
+    }
+}
+
+impl<H: Handler + ?Sized> Handler for Arc<H> {
+    fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+        (**self).handle_request(request)
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        (**self).handle_bad_request(e)
+    }
+
+    fn allowed_methods(&self) -> &[Method] {
+        (**self).allowed_methods()
+    }
+
+    fn render_error(&self, status: StatusCode, accept: Option<&str>) -> Response {
+        (**self).render_error(status, accept)
+    }
+}
+
+/// Implements the `Handler` methods other than `handle_request` by calling the handler in the
+/// `handler` field, for handlers wrapping another one (rate limiting, access control, authentication),
+/// so that bad requests, allowed methods and error pages are those of the wrapped handler.
+macro_rules! delegate_handler {
+    () => {
+        fn handle_bad_request(&self, e: &$crate::http::ParseError) -> $crate::http::Response {
+            self.handler.handle_bad_request(e)
+        }
+
+        fn allowed_methods(&self) -> &[$crate::http::Method] {
+            self.handler.allowed_methods()
+        }
+
+        fn render_error(
+            &self,
+            status: $crate::http::StatusCode,
+            accept: Option<&str>,
+        ) -> $crate::http::Response {
+            self.handler.render_error(status, accept)
+        }
+    };
+}
+pub(crate) use delegate_handler;
+
+/// A handler that can be replaced while the server runs, e.g. when the configuration file is reloaded.
+/// Requests being handled when it is replaced finish with the old handler.
 ///
 /// # Example
 ///
 /// ```
-/// let server = Server::new("127.0.0.1:8080".to_string());
-/// server.run();
+/// use rust_server::WebsiteHandler;
+/// use rust_server::server::ReloadableHandler;
+/// use std::sync::Arc;
+///
+/// let handler = Arc::new(ReloadableHandler::new(WebsiteHandler::new("public".to_string())));
+/// // pass `Arc::clone(&handler)` to `Server::run`, then later:
+/// handler.replace(WebsiteHandler::new("public-v2".to_string()));
 /// ```
-use crate::http::{ParseError, Request, Response, StatusCode};
-use std::io::{Read, Write}; // For reading from the TCP stream
-use std::net::TcpListener; // For listening to TCP connections
+pub struct ReloadableHandler<H> {
+    current: RwLock<Arc<H>>,
+    methods: Vec<Method>, // the methods of the first handler, see `replace`
+}
 
-/// A trait for handling HTTP requests. Instead of implementing handling logic over
-/// and over in the `Server` struct, we can implement it in a separate struct
-/// and pass it to the `Server` as a parameter, in order to reduce repetition.
-/// This is useful for testing and for implementing different handlers for different routes.
-pub trait Handler {
-    fn handle_request(&mut self, request: &Request) -> Response;
-    fn handle_bad_request(&mut self, e: &ParseError) -> Response {
-        println!("Error: parsing request\n{}", e);
+impl<H: Handler> ReloadableHandler<H> {
+    pub fn new(handler: H) -> Self {
+        Self {
+            methods: handler.allowed_methods().to_vec(),
+            current: RwLock::new(Arc::new(handler)),
+        }
+    }
+
+    /// Returns the handler answering new requests.
+    pub fn current(&self) -> Arc<H> {
+        let current = self.current.read().unwrap_or_else(PoisonError::into_inner);
+        Arc::clone(&current)
+    }
+
+    /// Replaces the handler for the requests received from now on.
+    ///
+    /// # Note
+    ///
+    /// `allowed_methods` keeps returning the methods of the first handler, so the replacement
+    /// should answer the same methods.
+    pub fn replace(&self, handler: H) {
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(handler);
+    }
+}
+
+impl<H: Handler> Handler for ReloadableHandler<H> {
+    fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+        self.current().handle_request(request)
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.current().handle_bad_request(e)
+    }
+
+    fn allowed_methods(&self) -> &[Method] {
+        &self.methods
+    }
+
+    fn render_error(&self, status: StatusCode, accept: Option<&str>) -> Response {
+        self.current().render_error(status, accept)
+    }
+}
+
+/// Returns every method the server answers for the given handler, including the implied HEAD and OPTIONS.
+fn allowed_methods(handler: &impl Handler) -> Vec<Method> {
+    let mut methods = handler.allowed_methods().to_vec();
+    if methods.contains(&Method::GET) && !methods.contains(&Method::HEAD) {
+        methods.push(Method::HEAD);
+    }
+    if !methods.contains(&Method::OPTIONS) {
+        methods.push(Method::OPTIONS);
+    }
+    methods
+}
+
+/// Runs the handler on a request, turning errors and panics into error responses.
+///
+/// # Note
+///
+/// The handler is used again after a panic, so it should not leave its state half-updated
+/// in a way that breaks later requests.
+fn call_handler(handler: &impl Handler, request: &Request) -> Response {
+    match panic::catch_unwind(AssertUnwindSafe(|| handler.handle_request(request))) {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => {
+            println!(
+                "Error: handling {} {}\n{}",
+                request.method(),
+                request.path(),
+                e
+            );
+            Response::new(e.status_code(), None)
+        }
+        Err(payload) => {
+            println!(
+                "Error: handler panicked on {} {}\n{}",
+                request.method(),
+                request.path(),
+                panic_message(payload.as_ref())
+            );
+            Response::new(StatusCode::InternalServerError, None)
+        }
+    }
+}
+
+/// Extracts the message passed to `panic!`, which is a `&str` or a `String` in practice.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic payload"
+    }
+}
+
+/// Routes a parsed request to the handler, answering HEAD, OPTIONS and unsupported methods on its behalf.
+///
+/// # Arguments
+///
+/// * `handler` - The handler serving the request.
+/// * `request` - The parsed request.
+///
+/// # Returns
+///
+/// The `Response` to send. For HEAD requests the caller must only send the head of the response.
+fn dispatch(handler: &impl Handler, request: Request) -> Response {
+    let method = *request.method();
+    let allowed = allowed_methods(handler);
+    let allow_header = allowed
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    // the asterisk request-target ("OPTIONS * HTTP/1.1") is only valid for OPTIONS and targets the server itself
+    if request.path() == "*" && method != Method::OPTIONS {
+        return Response::new(StatusCode::BadRequest, None);
+    }
+    if handler.allowed_methods().contains(&method) && request.path() != "*" {
+        return call_handler(handler, &request);
+    }
+    match method {
+        Method::HEAD if allowed.contains(&Method::GET) => {
+            call_handler(handler, &request.with_method(Method::GET))
+        }
+        Method::OPTIONS => {
+            Response::new(StatusCode::NoContent, None).with_header("Allow", allow_header)
+        }
+        _ => Response::new(StatusCode::MethodNotAllowed, None).with_header("Allow", allow_header),
+    }
+}
+
+/// Fills in the body of an error response that has none with the page rendered by the handler,
+/// keeping the status and headers of the response (such as `Allow` or `Content-Range`).
+fn with_error_page(
+    handler: &impl Handler,
+    mut response: Response,
+    accept: Option<&str>,
+) -> Response {
+    let status = response.status_code();
+    if !status.is_error() || response.body().is_some() {
+        return response;
+    }
+    let page = handler.render_error(status, accept);
+    for (name, value) in page.headers() {
+        response.set_header(name, value.as_str());
+    }
+    response.set_body(page.body().unwrap_or(&[]).to_vec());
+    response
+}
+
+/// A request answered on a connection, along with what the connection loop needs to know about it.
+struct Exchange {
+    response: Response,
+    request_line: String,   // the first line of the request, for the access log
+    head_only: bool,        // true for HEAD requests, whose response is sent without a body
+    body_len: u64,          // length of the request body following the head, from `Content-Length`
+    keep_alive: bool,       // false if the connection must be closed after the response
+    client: Option<IpAddr>, // the client reported by a trusted proxy, for the access log
+}
+
+/// Parses the head of a request, runs it through the handler and decides whether the connection can be reused.
+///
+/// # Arguments
+///
+/// * `handler` - The handler serving the request.
+/// * `config` - The server configuration, for the header limits and keep-alive settings.
+/// * `head` - The raw bytes of the request line and headers, up to and including the empty line.
+/// * `connection` - The connection the request came on, passed on to the handler.
+/// * `sequence` - The position of the request on the connection, from 1.
+fn exchange(
+    handler: &impl Handler,
+    config: &ServerConfig,
+    head: &[u8],
+    connection: &ConnectionInfo,
+    sequence: u64,
+) -> Exchange {
+    let request_line = String::from_utf8_lossy(head)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let request = match Request::try_from(head) {
+        Ok(request) => with_client(config, request.with_connection(connection, sequence)),
+        Err(e) => {
+            let response = handler.handle_bad_request(&e);
+            return Exchange {
+                response: with_error_page(handler, response, None),
+                request_line,
+                head_only: false,
+                body_len: 0,
+                keep_alive: false, // the end of an unparseable request cannot be found
+                client: None,
+            };
+        }
+    };
+
+    let head_only = *request.method() == Method::HEAD;
+    let client = request.forwarded().map(|forwarded| forwarded.client);
+    let accept = request.header("Accept");
+    let content_length = request.header("Content-Length").map(str::parse::<u64>);
+    // a chunked body has no length known up front, so the connection is closed instead of reading it
+    let mut keep_alive = config.keep_alive
+        && request.header("Transfer-Encoding").is_none()
+        && !request.header("Connection").is_some_and(|value| {
+            value
+                .split(',')
+                .any(|option| option.trim().eq_ignore_ascii_case("close"))
+        });
+    let response = if request.headers().len() > config.max_headers {
+        keep_alive = false;
+        Response::new(StatusCode::RequestHeaderFieldsTooLarge, None)
+    } else if let Some(Err(_)) = content_length {
+        keep_alive = false;
         Response::new(StatusCode::BadRequest, None)
+    } else {
+        dispatch(handler, request)
+    };
+    Exchange {
+        response: with_error_page(handler, response, accept),
+        request_line,
+        head_only,
+        body_len: content_length.and_then(Result::ok).unwrap_or(0),
+        keep_alive,
+        client,
+    }
+}
+
+/// Tags a request with the client reported by the trusted proxy it came from, if any.
+fn with_client<'buf>(config: &ServerConfig, request: Request<'buf>) -> Request<'buf> {
+    if config.trusted_proxies.is_empty() {
+        return request;
+    }
+    let connection = request.connection();
+    let peer = match connection.and_then(ConnectionInfo::peer_ip) {
+        Some(ip) => ip,
+        // a Unix domain socket peer is on this host, and as trusted as the loopback address
+        None if connection.is_some() => {
+            let loopback = [Ipv4Addr::LOCALHOST.into(), Ipv6Addr::LOCALHOST.into()];
+            match loopback.into_iter().find(|ip| config.is_trusted_proxy(*ip)) {
+                Some(ip) => ip,
+                None => return request,
+            }
+        }
+        None => return request,
+    };
+    let forwarded = forwarded_client(&request, peer, |ip| config.is_trusted_proxy(ip));
+    match forwarded {
+        Some(forwarded) => request.with_forwarded(forwarded),
+        None => request,
+    }
+}
+
+/// A stream a request can be read from and a response written to: an accepted `Stream`,
+/// or an in-memory stream in tests.
+trait Connection: Read + Write {
+    /// Sets how long a read may block before failing with `ErrorKind::WouldBlock` or `ErrorKind::TimedOut`.
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> IoResult<()>;
+}
+
+impl Connection for Stream {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> IoResult<()> {
+        Stream::set_read_timeout(self, timeout)
+    }
+}
+
+/// The outcome of reading the head of the next request on a connection.
+enum Head {
+    Complete(usize), // the head is the given number of bytes at the start of the buffer
+    Closed,          // the client closed the connection, or left it idle for too long
+    Failed(StatusCode), // the head cannot be read; the error is answered and the connection closed
+}
+
+/// Returns the length of the request head at the start of the buffer (up to and including
+/// the empty line ending it), if it has been received completely.
+fn head_len(buffer: &[u8]) -> Option<usize> {
+    // lines end with CRLF: the request parser does not take a bare LF for the end of a line either
+    buffer
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|i| i + 4)
+}
+
+/// Reads from the connection until the buffer holds the head of a request.
+///
+/// # Arguments
+///
+/// * `stream` - The connection to read from.
+/// * `buffer` - The bytes received but not handled yet, which may already hold (part of) the head.
+/// * `config` - The server configuration, for the buffer size, the header size limit and the read timeout.
+/// * `idle_timeout` - How long to wait for the first byte of the request.
+fn read_head(
+    stream: &mut impl Connection,
+    buffer: &mut Vec<u8>,
+    config: &ServerConfig,
+    idle_timeout: Option<Duration>,
+) -> IoResult<Head> {
+    let mut chunk = vec![0; config.read_buffer_size];
+    loop {
+        // empty lines before a request line are ignored (RFC 9112, section 2.2)
+        let blank = buffer
+            .iter()
+            .take_while(|byte| **byte == b'\r' || **byte == b'\n')
+            .count();
+        buffer.drain(..blank);
+        match head_len(buffer) {
+            Some(len) if len > config.max_header_size => {
+                return Ok(Head::Failed(StatusCode::RequestHeaderFieldsTooLarge));
+            }
+            Some(len) => return Ok(Head::Complete(len)),
+            None if buffer.len() > config.max_header_size => {
+                return Ok(Head::Failed(StatusCode::RequestHeaderFieldsTooLarge));
+            }
+            None => {}
+        }
+
+        let timeout = if buffer.is_empty() {
+            idle_timeout
+        } else {
+            config.read_timeout
+        };
+        stream.set_read_timeout(timeout)?;
+        match stream.read(&mut chunk) {
+            // the client gave up, possibly in the middle of a request: there is no one left to answer
+            Ok(0) => return Ok(Head::Closed),
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                // an idle connection is closed silently, a request that stalls halfway is answered
+                return Ok(if buffer.is_empty() {
+                    Head::Closed
+                } else {
+                    Head::Failed(StatusCode::RequestTimeout)
+                });
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Reads and drops the body of a request, which follows its head on the connection.
+///
+/// # Returns
+///
+/// `true` if the whole body was skipped, or `false` if the connection closed or timed out first.
+fn skip_body(stream: &mut impl Connection, buffer: &mut Vec<u8>, len: u64) -> IoResult<bool> {
+    let mut remaining = len;
+    let mut chunk = [0; 8192];
+    loop {
+        let buffered = remaining.min(buffer.len() as u64);
+        buffer.drain(..buffered as usize);
+        remaining -= buffered;
+        if remaining == 0 {
+            return Ok(true);
+        }
+        match stream.read(&mut chunk) {
+            Ok(0) => return Ok(false),
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(false);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Serves the requests sent on a connection, one after the other, until the client closes it,
+/// leaves it idle for longer than the keep-alive timeout, or a limit of the configuration is reached.
+///
+/// # Arguments
+///
+/// * `handler` - The handler serving the requests.
+/// * `config` - The server configuration.
+/// * `stream` - The connection.
+/// * `connection` - What is known about the connection: the client address (for the logs),
+///   the listener that accepted it and so on, passed on to the handler with every request.
+/// * `stopping` - Set when the server shuts down, which closes the connection after the current response.
+fn serve_connection(
+    handler: &impl Handler,
+    config: &ServerConfig,
+    stream: &mut impl Connection,
+    connection: &ConnectionInfo,
+    stopping: &AtomicBool,
+) -> IoResult<()> {
+    let peer = connection.peer_addr.as_str();
+    let mut buffer = Vec::new(); // bytes received but not handled yet, e.g. pipelined requests
+    let mut served = 0;
+    loop {
+        let idle_timeout = if served == 0 {
+            config.read_timeout
+        } else {
+            Some(config.keep_alive_timeout)
+        };
+        let head_len = match read_head(stream, &mut buffer, config, idle_timeout)? {
+            Head::Complete(len) => len,
+            Head::Closed => return Ok(()),
+            Head::Failed(status) => {
+                let response = with_error_page(handler, Response::new(status, None), None)
+                    .with_header("Connection", "close");
+                log_access(config, peer, "-", &response, false);
+                return response.send(stream);
+            }
+        };
+        if config.log_level == LogLevel::Debug {
+            println!(
+                "========== Received request {} of connection {} from {} ==========\n{}",
+                served + 1,
+                connection.id,
+                peer,
+                String::from_utf8_lossy(&buffer[..head_len])
+            );
+        }
+        served += 1;
+        let mut exchange = exchange(
+            handler,
+            config,
+            &buffer[..head_len],
+            connection,
+            served as u64,
+        );
+        buffer.drain(..head_len);
+
+        let mut keep_alive = exchange.keep_alive
+            && served < config.max_requests_per_connection
+            && !stopping.load(Ordering::SeqCst);
+        if keep_alive && exchange.body_len > 0 {
+            keep_alive = exchange.body_len <= MAX_DISCARDED_BODY
+                && skip_body(stream, &mut buffer, exchange.body_len)?;
+        }
+        if !keep_alive {
+            exchange.response.set_header("Connection", "close");
+        }
+        // requests relayed by a proxy are logged under the client it reported
+        let client = exchange
+            .client
+            .map(|client| format!("{} via {}", client, peer));
+        log_access(
+            config,
+            client.as_deref().unwrap_or(peer),
+            &exchange.request_line,
+            &exchange.response,
+            exchange.head_only,
+        );
+        if exchange.head_only {
+            // a response to HEAD carries the same headers as GET, but never a body
+            exchange.response.send_head(stream)?;
+        } else {
+            exchange.response.send(stream)?;
+        }
+        stream.flush()?;
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+/// Logs a line for a response: the client address, the request line, the status and the body size,
+/// followed by the notes of the response.
+fn log_access(
+    config: &ServerConfig,
+    peer: &str,
+    request_line: &str,
+    response: &Response,
+    head_only: bool,
+) {
+    if config.log_level == LogLevel::Off {
+        return;
+    }
+    let body_len = match response.body() {
+        Some(body) if !head_only => body.len(),
+        _ => 0,
+    };
+    let mut line = format!(
+        "{} \"{}\" {} {}",
+        peer,
+        request_line,
+        response.status_code(),
+        body_len
+    );
+    for note in response.log_notes() {
+        line.push(' ');
+        line.push_str(note);
+    }
+    println!("{}", line);
+}
+
+/// Takes the sockets handed over by systemd or by the process upgrading to this one, if any,
+/// or else binds the configured listeners.
+fn open_listeners(config: &ServerConfig) -> IoResult<Vec<Listener>> {
+    #[cfg(unix)]
+    for take in [systemd::take_listen_fds, upgrade::take_listen_fds] {
+        let inherited = take()?;
+        if !inherited.is_empty() {
+            return inherited
+                .into_iter()
+                .map(|(name, fd)| {
+                    let mut listener = Listener::inherit(&name, fd)?;
+                    // the sockets are bound elsewhere, but the configured listener of the same name
+                    // tells whether its connections start with a PROXY header
+                    listener.proxy_protocol = config
+                        .listeners
+                        .iter()
+                        .any(|config| config.name == name && config.proxy_protocol);
+                    Ok(listener)
+                })
+                .collect();
+        }
+    }
+    // bind everything first, so a bad address fails the start instead of leaving a partial server
+    config.listeners.iter().map(Listener::bind).collect()
+}
+
+/// A connection waiting for a worker, what is known about it, whether it starts with
+/// a PROXY protocol header, and its place among the open connections.
+type Accepted<'s> = (Stream, ConnectionInfo, bool, Slot<'s>);
+
+/// Counters of the connections a server accepted and turned away, to size its capacity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ConnectionStats {
+    pub accepted: u64,        // connections served since the server was created
+    pub open: usize,          // connections being served or waiting for a worker
+    pub rejected: u64,        // connections answered with 503 because `max_connections` were open
+    pub rejected_per_ip: u64, // connections answered with 503 because of `max_connections_per_ip`
+    pub paused: Duration, // time spent not accepting because `max_connections` were open, summed over the listeners
+}
+
+/// The connections open at once, counted against the connection limits.
+#[derive(Debug, Default)]
+struct Load {
+    open: usize,
+    per_ip: HashMap<IpAddr, usize>, // only addresses with open connections are kept
+    stats: ConnectionStats,         // the counters other than `accepted` and `open`
+}
+
+/// A connection counted among the open connections until it is dropped.
+struct Slot<'s> {
+    server: &'s Server,
+    ip: Option<IpAddr>, // the address it is counted for against `max_connections_per_ip`
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let mut load = self.server.load();
+        load.open -= 1;
+        if let Some(ip) = self.ip
+            && let Some(count) = load.per_ip.get_mut(&ip)
+        {
+            *count -= 1;
+            if *count == 0 {
+                load.per_ip.remove(&ip);
+            }
+        }
+        self.server.freed.notify_all();
+    }
+}
+
+/// Why a connection is turned away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rejection {
+    Overloaded, // `max_connections` are open
+    PerIp,      // its address has `max_connections_per_ip` open
+}
+
+/// Answers a connection over the limits with 503 Service Unavailable and `Retry-After`.
+fn reject(
+    handler: &impl Handler,
+    config: &ServerConfig,
+    stream: &mut impl Write,
+    peer: &str,
+    rejection: Rejection,
+) -> IoResult<()> {
+    let retry_after = config.overload_retry_after.as_secs_f64().ceil();
+    let mut response = with_error_page(
+        handler,
+        Response::new(StatusCode::ServiceUnavailable, None),
+        None,
+    )
+    .with_header("Retry-After", retry_after.to_string())
+    .with_header("Connection", "close");
+    response.add_log_note(match rejection {
+        Rejection::Overloaded => "rejected=max_connections",
+        Rejection::PerIp => "rejected=max_connections_per_ip",
+    });
+    log_access(config, peer, "-", &response, false);
+    response.send(stream)?;
+    stream.flush()
+}
+
+/// Reads the PROXY protocol header a load balancer sends before the first request, and replaces
+/// the addresses of the connection with the ones it reports.
+fn read_proxy_header(
+    stream: &mut Stream,
+    connection: ConnectionInfo,
+    config: &ServerConfig,
+) -> IoResult<ConnectionInfo> {
+    stream.set_read_timeout(config.read_timeout)?;
+    let header = proxy_protocol::read_header(stream)?;
+    let proxy = ProxyInfo {
+        proxy_addr: connection.peer_addr.clone(),
+        tlvs: header.tlvs,
+    };
+    // health checks of the balancer (and UNKNOWN connections) keep the addresses of the socket
+    let connection = match header.addresses {
+        Some((source, destination)) => connection
+            .with_peer_addr(source)
+            .with_local_addr(destination),
+        None => connection,
+    };
+    Ok(connection.with_proxy(proxy))
+}
+
+/// Takes connections off the queue and serves them, until every accept loop is gone.
+fn worker(handler: &impl Handler, server: &Server, queue: &Mutex<Receiver<Accepted<'_>>>) {
+    loop {
+        // the lock is only held while waiting for the next connection
+        let next = queue.lock().unwrap_or_else(PoisonError::into_inner).recv();
+        // the slot is freed when the connection is done with
+        let Ok((mut stream, mut connection, proxy_protocol, _slot)) = next else {
+            return;
+        };
+        let peer = connection.peer_addr.clone();
+        // a connection keeps the settings it started with, even if they are updated meanwhile
+        let config = server.config();
+        let served = panic::catch_unwind(AssertUnwindSafe(|| {
+            stream.set_write_timeout(config.write_timeout)?;
+            if proxy_protocol {
+                connection = read_proxy_header(&mut stream, connection, &config)?;
+            }
+            serve_connection(handler, &config, &mut stream, &connection, &server.stopping)
+        }));
+        match served {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => println!("Error: connection with {}\n{}", peer, e),
+            // handler panics are answered with 500 already; this is a panic outside of the handler
+            Err(payload) => println!(
+                "Error: panic while serving {}\n{}",
+                peer,
+                panic_message(payload.as_ref())
+            ),
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct Server {
-    pub addr: String, // Address to bind the server to (e.g., "127.0.0.1:8080")
+    config: RwLock<Arc<ServerConfig>>, // validated settings, see `Server::new` and `Server::update_config`
+    stopping: AtomicBool,              // set by `Server::shutdown`
+    connections: AtomicU64,            // number of connections accepted, the last connection id
+    load: Mutex<Load>,                 // the open connections, see `max_connections`
+    freed: Condvar,                    // notified when a connection closes
+    #[cfg(unix)]
+    open: Mutex<Vec<(String, RawFd)>>, // names and descriptors of the listeners, while running
 }
+
 impl Server {
-    /// Creates a new Server instance with the given address.
+    /// Creates a new Server instance with the given configuration.
     ///
     /// # Arguments
     ///
-    /// * `addr` - The address to bind the server to (e.g., "127.0.0.1:8080").
-    pub fn new(addr: String) -> Self {
-        Self { addr }
-    }
-
-    /// Runs the server, listening for incoming TCP connections and handling requests.
-    pub fn run(&self, mut handler: impl Handler) {
-        println!("Listening on {}", self.addr);
-        // Bind the TCP listener to the specified address
-        let listener = TcpListener::bind(&self.addr).unwrap();
-        loop {
-            // Accept an incoming connection
-            match listener.accept() {
-                Ok((mut sock_stream, addr)) => {
-                    let mut buffer = [0; 1024]; // Buffer to store incoming request data
-                    // Read data from the TCP stream into the buffer
-                    match sock_stream.read(&mut buffer) {
-                        Ok(bytes_read) => {
-                            if bytes_read == 0 {
-                                // If no bytes were read, the client disconnected
-                                println!("========== Client disconnected ==========");
-                                break;
+    /// * `config` - The settings of the server, such as the addresses to bind to.
+    ///
+    /// # Returns
+    ///
+    /// The `Server`, or a `ConfigError` describing the first invalid setting.
+    pub fn new(config: ServerConfig) -> Result<Self, ConfigError> {
+        config.validate()?;
+        Ok(Self {
+            config: RwLock::new(Arc::new(config)),
+            stopping: AtomicBool::new(false),
+            connections: AtomicU64::new(0),
+            load: Mutex::new(Load::default()),
+            freed: Condvar::new(),
+            #[cfg(unix)]
+            open: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Returns the configuration the server runs with.
+    pub fn config(&self) -> Arc<ServerConfig> {
+        let config = self.config.read().unwrap_or_else(PoisonError::into_inner);
+        Arc::clone(&config)
+    }
+
+    /// Replaces the configuration of a running server, e.g. after its configuration file changed.
+    ///
+    /// # Note
+    ///
+    /// New connections use the new settings, while open connections finish with the old ones,
+    /// so none are dropped. The listeners and worker threads are not recreated: changes to
+    /// `listeners` and `workers` are ignored until the server restarts.
+    ///
+    /// # Returns
+    ///
+    /// The names of the ignored settings, or a `ConfigError` (leaving the configuration unchanged)
+    /// if the new configuration is invalid.
+    pub fn update_config(&self, config: ServerConfig) -> Result<Vec<&'static str>, ConfigError> {
+        config.validate()?;
+        let mut current = self.config.write().unwrap_or_else(PoisonError::into_inner);
+        let ignored = current.restart_required(&config);
+        *current = Arc::new(ServerConfig {
+            listeners: current.listeners.clone(),
+            workers: current.workers,
+            ..config
+        });
+        Ok(ignored)
+    }
+
+    /// Runs the server: binds every configured listener, then serves the connections accepted on
+    /// them with `config.workers` threads sharing the handler.
+    ///
+    /// # Note
+    ///
+    /// On most systems, a listener on `[::]` accepts IPv4 connections too, so listening on both
+    /// `0.0.0.0` and `[::]` with the same port fails with "address in use"; `[::]` alone serves both.
+    ///
+    /// Under systemd socket activation (`LISTEN_FDS` and `LISTEN_PID` are set for this process),
+    /// the passed sockets are used instead of the configured listeners, named after `LISTEN_FDNAMES`.
+    /// Once the server accepts connections, it sends `READY=1` to `NOTIFY_SOCKET` if it is set.
+    ///
+    /// Sockets handed over by a running server upgrading to this process (see `Server::upgrade`)
+    /// replace the configured listeners in the same way.
+    ///
+    /// # Returns
+    ///
+    /// An error if one of the listeners cannot be bound (e.g. its address is in use). Otherwise the server
+    /// keeps running until `Server::shutdown` is called, and returns once the accepted connections are served;
+    /// errors on single connections are logged, not returned.
+    pub fn run(&self, handler: impl Handler) -> IoResult<()> {
+        let config = self.config();
+        let listeners = open_listeners(&config)?;
+        for listener in &listeners {
+            println!(
+                "Listening on {} ({})",
+                listener.local_addr()?,
+                listener.name
+            );
+        }
+        #[cfg(unix)]
+        {
+            *self.open.lock().unwrap_or_else(PoisonError::into_inner) = listeners
+                .iter()
+                .map(|listener| (listener.name.to_string(), listener.raw_fd()))
+                .collect();
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let queue = Mutex::new(receiver);
+        let handler = &handler;
+        thread::scope(|scope| {
+            for _ in 0..config.workers {
+                scope.spawn(|| worker(handler, self, &queue));
+            }
+            for listener in &listeners {
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    let mut backoff = Duration::ZERO;
+                    while !self.stopping.load(Ordering::SeqCst) {
+                        // the limits can change while the server runs
+                        let config = self.config();
+                        if !self.wait_for_room(&config, ACCEPT_INTERVAL) {
+                            continue; // paused, the connections wait in the backlog
+                        }
+                        let accepted = listener.accept_timeout(ACCEPT_INTERVAL);
+                        if accepted.is_ok() {
+                            backoff = Duration::ZERO;
+                        }
+                        match accepted {
+                            Ok(Some((mut stream, peer))) => {
+                                let slot = match self.admit(&config, &peer, listener.proxy_protocol)
+                                {
+                                    Ok(slot) => slot,
+                                    Err(rejection) => {
+                                        let _ = stream.set_write_timeout(config.write_timeout);
+                                        if let Err(e) =
+                                            reject(handler, &config, &mut stream, &peer, rejection)
+                                        {
+                                            println!("Error: connection with {}\n{}", peer, e);
+                                        }
+                                        continue;
+                                    }
+                                };
+                                let connection = self.describe(&stream, peer, listener);
+                                let proxy_protocol = listener.proxy_protocol;
+                                if sender
+                                    .send((stream, connection, proxy_protocol, slot))
+                                    .is_err()
+                                {
+                                    return; // every worker is gone
+                                }
                             }
-                            // Print the raw HTTP request received
-                            println!(
-                                "========== Received a request ==========\n{}",
-                                String::from_utf8_lossy(&buffer[..bytes_read])
-                            );
-                            // Attempt to parse the HTTP request from the buffer
-                            let response = match Request::try_from(&buffer[..bytes_read]) {
-                                Ok(request) => handler.handle_request(&request),
-                                Err(e) => handler.handle_bad_request(&e),
-                            };
-
-                            // Write the response to the client
-                            if let Err(e) = response.send(&mut sock_stream) {
-                                // If there's an error writing to the client, print the error
-                                println!("Error: Failed to write response\n{}", e);
+                            Ok(None) => {}
+                            // Error accepting a new connection, e.g. too many open files; keep accepting,
+                            // but give the connections in progress time to close before trying again
+                            Err(e) => {
+                                backoff = (backoff * 2).clamp(ACCEPT_BACKOFF, ACCEPT_INTERVAL);
+                                println!(
+                                    "========== Error ==========\n{}\nRetrying in {:?}",
+                                    e, backoff
+                                );
+                                thread::sleep(backoff);
                             }
-
-                            // 2 ways to convert between Request and &[u8] using TryFrom and TryInto:
-                            // Request::try_from(&buffer[..bytes_read]);
-                            // let res: &Result<Request, _> = &buffer[..].try_into();
                         }
-                        Err(e) => println!("========== Error ==========\n{}", e), // Error reading from stream
                     }
-                }
-                Err(e) => {
-                    // Error accepting a new connection
-                    println!("========== Error ==========\n{}", e);
-                }
+                });
+            }
+            drop(sender);
+            // the service manager and an upgrading process are told separately, so that
+            // one of them failing does not leave the other thinking the server never started
+            #[cfg(unix)]
+            if let Err(e) = systemd::notify("READY=1") {
+                println!(
+                    "Error: cannot notify the service manager that the server is ready\n{}",
+                    e
+                );
+            }
+            #[cfg(unix)]
+            if let Err(e) = upgrade::report_ready() {
+                println!(
+                    "Error: cannot report to the upgrading process that the server is ready\n{}",
+                    e
+                );
+            }
+        });
+        // the workers are done: the remaining connections were served
+        #[cfg(unix)]
+        self.open
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+        Ok(())
+    }
+
+    /// Returns the counters of the connections accepted and turned away so far.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        let load = self.load();
+        ConnectionStats {
+            accepted: self.connections.load(Ordering::Relaxed),
+            open: load.open,
+            ..load.stats
+        }
+    }
+
+    fn load(&self) -> MutexGuard<'_, Load> {
+        self.load.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Waits up to `timeout` for fewer than `max_connections` to be open, if the server pauses
+    /// when they are.
+    ///
+    /// # Returns
+    ///
+    /// True if a connection can be accepted, false if the server is still full.
+    fn wait_for_room(&self, config: &ServerConfig, timeout: Duration) -> bool {
+        let (Some(max), Overload::Pause) = (config.max_connections, config.overload) else {
+            return true;
+        };
+        let mut load = self.load();
+        if load.open < max {
+            return true;
+        }
+        let start = Instant::now();
+        load = self
+            .freed
+            .wait_timeout_while(load, timeout, |load| load.open >= max)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0;
+        load.stats.paused += start.elapsed();
+        load.open < max
+    }
+
+    /// Counts a connection accepted from `peer` among the open ones, if the limits allow it.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The server configuration, for the limits.
+    /// * `peer` - The address of the socket.
+    /// * `proxied` - True if the connection comes from a listener with the PROXY protocol.
+    ///
+    /// # Note
+    ///
+    /// Connections from proxies (PROXY listeners and trusted proxies) are not counted against
+    /// `max_connections_per_ip`, since they carry the requests of many clients.
+    ///
+    /// When the server pauses, another listener may have taken the last free slot since
+    /// `wait_for_room`: the connection then waits here for a slot, and this listener with it.
+    fn admit(
+        &self,
+        config: &ServerConfig,
+        peer: &str,
+        proxied: bool,
+    ) -> Result<Slot<'_>, Rejection> {
+        let mut load = self.load();
+        if let Some(max) = config.max_connections
+            && load.open >= max
+        {
+            if config.overload == Overload::Reject {
+                load.stats.rejected += 1;
+                return Err(Rejection::Overloaded);
+            }
+            let start = Instant::now();
+            load = self
+                .freed
+                .wait_while(load, |load| load.open >= max)
+                .unwrap_or_else(PoisonError::into_inner);
+            load.stats.paused += start.elapsed();
+        }
+        let ip = peer
+            .parse::<SocketAddr>()
+            .map(|addr| addr.ip().to_canonical())
+            .ok()
+            .filter(|ip| !proxied && !config.is_trusted_proxy(*ip));
+        if let (Some(max), Some(ip)) = (config.max_connections_per_ip, ip) {
+            let count = load.per_ip.entry(ip).or_default();
+            if *count >= max {
+                load.stats.rejected_per_ip += 1;
+                return Err(Rejection::PerIp);
+            }
+            *count += 1;
+        }
+        load.open += 1;
+        Ok(Slot {
+            server: self,
+            ip: ip.filter(|_| config.max_connections_per_ip.is_some()),
+        })
+    }
+
+    /// Numbers an accepted connection and gathers what handlers may want to know about it.
+    fn describe(&self, stream: &Stream, peer: String, listener: &Listener) -> ConnectionInfo {
+        let id = self.connections.fetch_add(1, Ordering::Relaxed) + 1;
+        let connection = ConnectionInfo::new(id, &listener.name, stream.transport());
+        // the local address is only informative; it is left empty if the socket cannot tell it
+        connection
+            .with_peer_addr(peer)
+            .with_local_addr(stream.local_addr().unwrap_or_default())
+    }
+
+    /// Stops the server gracefully: the listeners stop accepting connections, the open ones are
+    /// closed after their current response (idle ones when their keep-alive timeout expires), and `run` returns once they are.
+    pub fn shutdown(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        #[cfg(unix)]
+        let _ = systemd::notify("STOPPING=1");
+    }
+
+    /// Upgrades the server to a new process without closing its listeners, e.g. to run a new binary:
+    /// starts `command` with the listening sockets, waits until it accepts connections on them,
+    /// then shuts this server down gracefully.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The new process, which runs a `Server` (e.g. the same binary with the same arguments).
+    ///   It takes over the sockets instead of binding its configured listeners, see `Server::run`.
+    /// * `ready_timeout` - How long the new process may take to start accepting connections.
+    ///
+    /// # Returns
+    ///
+    /// The process id of the new process, or an error (the server keeps running then) if the server
+    /// is not running or the new process could not be started, exited, or was not ready in time.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rust_server::config::ServerConfig;
+    /// use rust_server::{Server, WebsiteHandler};
+    /// use std::process::Command;
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let server = Arc::new(Server::new(ServerConfig::new()).unwrap());
+    /// let running = Arc::clone(&server);
+    /// std::thread::spawn(move || running.run(WebsiteHandler::new("public".to_string())));
+    /// // later, e.g. on a signal, once the binary has been replaced
+    /// let command = Command::new(std::env::current_exe().unwrap());
+    /// let pid = server.upgrade(command, Duration::from_secs(30)).unwrap();
+    /// println!("upgraded to process {}", pid);
+    /// ```
+    #[cfg(unix)]
+    pub fn upgrade(&self, command: Command, ready_timeout: Duration) -> IoResult<u32> {
+        // holding the lock keeps `run` from closing the sockets while they are handed over
+        let open = self.open.lock().unwrap_or_else(PoisonError::into_inner);
+        if open.is_empty() || self.stopping.load(Ordering::SeqCst) {
+            return Err(IoError::other("the server is not running"));
+        }
+        let child = upgrade::hand_over(command, &open, ready_timeout)?;
+        drop(open);
+        // under systemd, the new process becomes the main process of the service
+        let _ = systemd::notify(&format!("MAINPID={}", child.id()));
+        self.shutdown();
+        Ok(child.id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Transport;
+
+    /// A handler that answers every GET request with a fixed body.
+    struct HelloHandler;
+
+    impl Handler for HelloHandler {
+        fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+            assert_eq!(*request.method(), Method::GET);
+            Ok(Response::new(StatusCode::Ok, Some("hello".to_string())))
+        }
+    }
+
+    /// A handler that fails in various ways, depending on the path.
+    struct FaultyHandler;
+
+    impl Handler for FaultyHandler {
+        fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+            match request.path() {
+                "/missing" => Err(HandlerError::Status(StatusCode::NotFound)),
+                "/io" => Err(std::io::Error::other("disk on fire").into()),
+                "/panic" => panic!("handler bug"),
+                _ => Ok(Response::new(StatusCode::Ok, Some("fine".to_string()))),
             }
         }
     }
+
+    /// An in-memory connection, whose client sends `input` and then closes the connection
+    /// (or stops sending, if `stalls` is set).
+    struct MockConnection {
+        input: std::io::Cursor<Vec<u8>>,
+        stalls: bool,
+        output: Vec<u8>,
+    }
+
+    impl Read for MockConnection {
+        fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+            match self.input.read(buf)? {
+                0 if self.stalls => Err(ErrorKind::WouldBlock.into()),
+                read => Ok(read),
+            }
+        }
+    }
+
+    impl Write for MockConnection {
+        fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> IoResult<()> {
+            Ok(())
+        }
+    }
+
+    impl Connection for MockConnection {
+        fn set_read_timeout(&mut self, _timeout: Option<Duration>) -> IoResult<()> {
+            Ok(())
+        }
+    }
+
+    fn serve(handler: &impl Handler, config: &ServerConfig, raw: &str, stalls: bool) -> String {
+        let mut connection = MockConnection {
+            input: std::io::Cursor::new(raw.as_bytes().to_vec()),
+            stalls,
+            output: Vec::new(),
+        };
+        let stopping = AtomicBool::new(false);
+        serve_connection(
+            handler,
+            config,
+            &mut connection,
+            &test_connection(),
+            &stopping,
+        )
+        .unwrap();
+        String::from_utf8(connection.output).unwrap()
+    }
+
+    fn test_connection() -> ConnectionInfo {
+        ConnectionInfo::new(42, "test", Transport::Tcp)
+            .with_peer_addr("127.0.0.1:50000")
+            .with_local_addr("127.0.0.1:8080")
+    }
+
+    fn roundtrip_with(handler: &impl Handler, raw: &str) -> String {
+        let config = ServerConfig::new().with_log_level(LogLevel::Off);
+        serve(handler, &config, raw, false)
+    }
+
+    fn roundtrip(raw: &str) -> String {
+        roundtrip_with(&HelloHandler, raw)
+    }
+
+    #[test]
+    fn test_get_sends_body() {
+        let response = roundtrip("GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(
+            response,
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello"
+        );
+    }
+
+    #[test]
+    fn test_head_sends_headers_only() {
+        let response = roundtrip("HEAD / HTTP/1.1\r\n\r\n");
+        assert_eq!(response, "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n");
+    }
+
+    #[test]
+    fn test_options_lists_allowed_methods() {
+        let expected = "HTTP/1.1 204 No Content\r\nAllow: GET, HEAD, OPTIONS\r\n\r\n";
+        assert_eq!(roundtrip("OPTIONS /index.html HTTP/1.1\r\n\r\n"), expected);
+        assert_eq!(roundtrip("OPTIONS * HTTP/1.1\r\n\r\n"), expected);
+    }
+
+    #[test]
+    fn test_unsupported_method_is_not_allowed() {
+        let response = roundtrip("DELETE / HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        assert!(response.contains("Allow: GET, HEAD, OPTIONS\r\n"));
+        let response = roundtrip("GET * HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn test_handler_errors_become_error_responses() {
+        let handler = FaultyHandler;
+        let response = roundtrip_with(&handler, "GET /missing HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let response = roundtrip_with(&handler, "GET /io HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(response.contains("<h1>500 Internal Server Error</h1>"));
+    }
+
+    #[test]
+    fn test_panics_become_internal_server_errors() {
+        let handler = FaultyHandler;
+        let response = roundtrip_with(&handler, "GET /panic HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        // the handler keeps serving requests after a panic
+        let response = roundtrip_with(&handler, "GET / HTTP/1.1\r\n\r\n");
+        assert!(response.ends_with("\r\n\r\nfine"));
+    }
+
+    #[test]
+    fn test_errors_get_a_page() {
+        let response = roundtrip("DELETE / HTTP/1.1\r\n\r\n");
+        assert!(response.contains("Allow: GET, HEAD, OPTIONS\r\n"));
+        assert!(response.contains("Content-Type: text/html; charset=utf-8\r\n"));
+        assert!(response.contains("<h1>405 Method Not Allowed</h1>"));
+
+        let response = roundtrip("DELETE / HTTP/1.1\r\nAccept: application/json\r\n\r\n");
+        assert!(response.contains("Content-Type: application/problem+json\r\n"));
+        assert!(
+            response
+                .ends_with(r#"{"type":"about:blank","title":"Method Not Allowed","status":405}"#)
+        );
+
+        let response = roundtrip("GET\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(response.contains("<h1>400 Bad Request</h1>"));
+    }
+
+    #[test]
+    fn test_keep_alive_serves_pipelined_requests() {
+        let response =
+            roundtrip("GET / HTTP/1.1\r\n\r\nHEAD / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n");
+        assert_eq!(response.matches("HTTP/1.1 200 OK\r\n").count(), 3);
+        assert!(!response.contains("Connection: close"));
+
+        // a request body is skipped, and the connection closes when the client asks for it
+        let response = roundtrip(
+            "GET / HTTP/1.1\r\nContent-Length: 3\r\n\r\nabcGET / HTTP/1.1\r\nConnection: close\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+        );
+        assert_eq!(response.matches("HTTP/1.1 200 OK\r\n").count(), 2);
+        assert!(response.ends_with("Connection: close\r\nContent-Length: 5\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn test_connection_limits() {
+        let config = ServerConfig::new()
+            .with_log_level(LogLevel::Off)
+            .with_max_requests_per_connection(2);
+        let response = serve(
+            &HelloHandler,
+            &config,
+            &"GET / HTTP/1.1\r\n\r\n".repeat(3),
+            false,
+        );
+        assert_eq!(response.matches("HTTP/1.1 200 OK\r\n").count(), 2);
+
+        let config = ServerConfig::new()
+            .with_log_level(LogLevel::Off)
+            .with_keep_alive(false);
+        let response = serve(
+            &HelloHandler,
+            &config,
+            &"GET / HTTP/1.1\r\n\r\n".repeat(2),
+            false,
+        );
+        assert_eq!(response.matches("HTTP/1.1 200 OK\r\n").count(), 1);
+        assert!(response.contains("Connection: close\r\n"));
+    }
+
+    #[test]
+    fn test_oversized_heads_are_rejected() {
+        let config = ServerConfig::new()
+            .with_log_level(LogLevel::Off)
+            .with_max_header_size(1024)
+            .with_max_headers(2);
+        let long = format!("GET / HTTP/1.1\r\nCookie: {}\r\n\r\n", "a".repeat(2000));
+        let response = serve(&HelloHandler, &config, &long, false);
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+        let many = "GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n";
+        let response = serve(&HelloHandler, &config, many, false);
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+        assert!(response.contains("Connection: close\r\n"));
+    }
+
+    #[test]
+    fn test_stalled_requests_time_out() {
+        let config = ServerConfig::new().with_log_level(LogLevel::Off);
+        let response = serve(&HelloHandler, &config, "GET / HTTP/1.1\r\nHost: loc", true);
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+        // an idle connection is closed without a response
+        let response = serve(&HelloHandler, &config, "GET / HTTP/1.1\r\n\r\n", true);
+        assert_eq!(response.matches("HTTP/1.1").count(), 1);
+    }
+
+    #[test]
+    fn test_handlers_see_the_connection() {
+        struct ConnectionHandler;
+
+        impl Handler for ConnectionHandler {
+            fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+                let connection = request.connection().unwrap();
+                let body = format!(
+                    "{} {} {} {} {:?} {}.",
+                    request.listener().unwrap(),
+                    connection.transport,
+                    connection.id,
+                    request.sequence(),
+                    connection.peer_ip(),
+                    connection.local_addr,
+                );
+                Ok(Response::new(StatusCode::Ok, Some(body)))
+            }
+        }
+
+        let response = roundtrip_with(
+            &ConnectionHandler,
+            "GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+        );
+        assert!(response.contains("\r\n\r\ntest tcp 42 1 Some(127.0.0.1) 127.0.0.1:8080."));
+        assert!(response.ends_with("\r\n\r\ntest tcp 42 2 Some(127.0.0.1) 127.0.0.1:8080."));
+    }
+
+    #[test]
+    fn test_trusted_proxies_report_the_client() {
+        struct ClientHandler;
+
+        impl Handler for ClientHandler {
+            fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+                let client = request.client_ip().map(|ip| ip.to_string());
+                let body = format!(
+                    "{} {}://{}",
+                    client.unwrap_or_default(),
+                    request.scheme(),
+                    request.host().unwrap_or_default()
+                );
+                Ok(Response::new(StatusCode::Ok, Some(body)))
+            }
+        }
+
+        let raw = "GET / HTTP/1.1\r\nHost: internal:8080\r\nX-Forwarded-For: 203.0.113.9\r\nX-Forwarded-Proto: https\r\nX-Forwarded-Host: example.com\r\n\r\n";
+        let config = ServerConfig::new().with_log_level(LogLevel::Off);
+        let response = serve(&ClientHandler, &config, raw, false);
+        assert!(response.ends_with("\r\n\r\n127.0.0.1 http://internal:8080"));
+
+        let config = config.with_trusted_proxies(["127.0.0.0/8".parse().unwrap()]);
+        let response = serve(&ClientHandler, &config, raw, false);
+        assert!(response.ends_with("\r\n\r\n203.0.113.9 https://example.com"));
+    }
+
+    #[test]
+    fn test_stopping_closes_connections_after_the_current_response() {
+        let config = ServerConfig::new().with_log_level(LogLevel::Off);
+        let mut connection = MockConnection {
+            input: std::io::Cursor::new(b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n".to_vec()),
+            stalls: false,
+            output: Vec::new(),
+        };
+        let stopping = AtomicBool::new(true);
+        serve_connection(
+            &HelloHandler,
+            &config,
+            &mut connection,
+            &test_connection(),
+            &stopping,
+        )
+        .unwrap();
+        let response = String::from_utf8(connection.output).unwrap();
+        assert_eq!(response.matches("HTTP/1.1 200 OK\r\n").count(), 1);
+        assert!(response.contains("Connection: close\r\n"));
+    }
+
+    #[test]
+    fn test_shutdown_ends_run() {
+        let config = ServerConfig::new()
+            .with_bind_addr("127.0.0.1:0")
+            .with_log_level(LogLevel::Off);
+        let server = Arc::new(Server::new(config).unwrap());
+        let running = Arc::clone(&server);
+        let run = thread::spawn(move || running.run(HelloHandler));
+        server.shutdown();
+        run.join().unwrap().unwrap();
+        // a stopped server has nothing to hand over
+        #[cfg(unix)]
+        assert!(
+            server
+                .upgrade(Command::new("true"), Duration::from_secs(1))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_max_connections_reject_connections() {
+        let config = ServerConfig::new()
+            .with_log_level(LogLevel::Off)
+            .with_max_connections(Some(2), Overload::Reject)
+            .with_max_connections_per_ip(Some(1));
+        let server = Server::new(config).unwrap();
+        let config = server.config();
+        let first = server.admit(&config, "192.0.2.1:1000", false).unwrap();
+        assert_eq!(
+            server.admit(&config, "192.0.2.1:1001", false).err(),
+            Some(Rejection::PerIp)
+        );
+        let second = server
+            .admit(&config, "[::ffff:192.0.2.2]:1000", false)
+            .unwrap();
+        assert_eq!(
+            server.admit(&config, "unix:/run/app.sock", false).err(),
+            Some(Rejection::Overloaded)
+        );
+        drop(first);
+        let third = server.admit(&config, "192.0.2.1:1002", false).unwrap();
+        let stats = server.connection_stats();
+        assert_eq!(
+            (stats.open, stats.rejected, stats.rejected_per_ip),
+            (2, 1, 1)
+        );
+        drop((second, third));
+        assert_eq!(server.connection_stats().open, 0);
+        assert!(server.load().per_ip.is_empty());
+
+        let mut output = Vec::new();
+        reject(
+            &HelloHandler,
+            &config,
+            &mut output,
+            "192.0.2.3:1000",
+            Rejection::Overloaded,
+        )
+        .unwrap();
+        let response = String::from_utf8(output).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(response.contains("Retry-After: 5\r\nConnection: close\r\n"));
+    }
+
+    #[test]
+    fn test_proxies_are_not_limited_per_ip() {
+        let config = ServerConfig::new()
+            .with_log_level(LogLevel::Off)
+            .with_trusted_proxies(["10.0.0.0/8".parse().unwrap()])
+            .with_max_connections_per_ip(Some(1));
+        let server = Server::new(config).unwrap();
+        let config = server.config();
+        let balancer = [
+            server.admit(&config, "10.0.0.1:1000", false).unwrap(),
+            server.admit(&config, "10.0.0.1:1001", false).unwrap(),
+            server.admit(&config, "192.0.2.1:1000", true).unwrap(),
+            server.admit(&config, "192.0.2.1:1001", true).unwrap(),
+        ];
+        let _client = server.admit(&config, "192.0.2.1:1002", false).unwrap();
+        assert_eq!(
+            server.admit(&config, "192.0.2.1:1003", false).err(),
+            Some(Rejection::PerIp)
+        );
+        drop(balancer);
+        assert_eq!(server.load().per_ip.len(), 1);
+    }
+
+    #[test]
+    fn test_max_connections_pause_accepting() {
+        let config = ServerConfig::new().with_max_connections(Some(1), Overload::Pause);
+        let server = Server::new(config).unwrap();
+        let config = server.config();
+        let slot = server.admit(&config, "192.0.2.1:1000", false).unwrap();
+        assert!(!server.wait_for_room(&config, Duration::from_millis(10)));
+        assert!(server.connection_stats().paused >= Duration::from_millis(10));
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                drop(slot);
+            });
+            // the listener resumes as soon as the connection closes
+            assert!(server.wait_for_room(&config, Duration::from_secs(10)));
+        });
+        assert_eq!(server.connection_stats().rejected, 0);
+    }
+
+    #[test]
+    fn test_server_validates_its_configuration() {
+        let config = ServerConfig::new().with_bind_addr("nowhere");
+        assert!(Server::new(config).is_err());
+        let server = Server::new(ServerConfig::new().with_workers(2)).unwrap();
+        assert_eq!(server.config().workers, 2);
+    }
 }
@@ -0,0 +1,100 @@
+// Unix signals, recorded by a minimal handler and picked up by polling, so that no server code
+// ever runs inside a signal handler. Only the C library's `signal` is used, which keeps the crate
+// free of dependencies and of platform-specific `sigaction` layouts.
+
+use std::ffi::c_int;
+use std::io::{Error as IoError, Result as IoResult};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The signals the server reacts to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Signal {
+    Hangup,    // SIGHUP: reload the configuration
+    Interrupt, // SIGINT: Ctrl+C in a terminal
+    Terminate, // SIGTERM: polite request to stop, e.g. from a service manager
+    User2,     // SIGUSR2: upgrade to a new binary without closing the listeners
+}
+
+impl Signal {
+    /// Returns the signal number. The first three are the same on every Unix, SIGUSR2 is not.
+    fn number(self) -> c_int {
+        match self {
+            Signal::Hangup => 1,
+            Signal::Interrupt => 2,
+            Signal::Terminate => 15,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Signal::User2 => 12,
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            Signal::User2 => 31, // macOS and the BSDs
+        }
+    }
+}
+
+/// Value returned by `signal` when it fails.
+const SIG_ERR: usize = usize::MAX;
+
+unsafe extern "C" {
+    #[link_name = "signal"]
+    fn install(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+}
+
+/// Whether each signal (by number) has been received since it was last taken.
+static RECEIVED: [AtomicBool; 32] = [const { AtomicBool::new(false) }; 32];
+
+/// The signal handler: only stores a flag, which is async-signal-safe.
+extern "C" fn record(signum: c_int) {
+    if let Some(flag) = usize::try_from(signum).ok().and_then(|n| RECEIVED.get(n)) {
+        flag.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Starts recording a signal instead of applying its default action (which, for the signals
+/// above, terminates the process).
+///
+/// # Example
+///
+/// ```no_run
+/// use rust_server::signal::{self, Signal};
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// signal::watch(Signal::Hangup).unwrap();
+/// loop {
+///     if signal::take(Signal::Hangup) {
+///         println!("reloading");
+///     }
+///     thread::sleep(Duration::from_millis(250));
+/// }
+/// ```
+pub fn watch(signal: Signal) -> IoResult<()> {
+    // SAFETY: `record` only touches atomics, so it can run at any point of any thread
+    let previous = unsafe { install(signal.number(), record) };
+    if previous == SIG_ERR {
+        return Err(IoError::last_os_error());
+    }
+    Ok(())
+}
+
+/// Returns true if the signal was received since the last call, and clears it.
+pub fn take(signal: Signal) -> bool {
+    RECEIVED[signal.number() as usize].swap(false, Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe extern "C" {
+        fn raise(signum: c_int) -> c_int;
+    }
+
+    #[test]
+    fn test_watched_signals_are_recorded() {
+        watch(Signal::Hangup).unwrap();
+        assert!(!take(Signal::Hangup));
+        // SAFETY: SIGHUP is recorded by the handler installed above instead of terminating the process
+        assert_eq!(unsafe { raise(Signal::Hangup.number()) }, 0);
+        assert!(take(Signal::Hangup));
+        assert!(!take(Signal::Hangup));
+    }
+}
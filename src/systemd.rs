@@ -0,0 +1,245 @@
+// Integration with systemd (or any service manager following its protocols): socket activation,
+// where the manager binds the listening sockets and passes them as file descriptors, and readiness
+// notification over the `NOTIFY_SOCKET` datagram socket. Both are plain environment variables and
+// file descriptors, so they need no library, and are skipped when the variables are not set.
+
+use std::env;
+use std::ffi::c_int;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The first file descriptor passed by the service manager; the others follow it.
+pub const LISTEN_FDS_START: RawFd = 3;
+
+const F_SETFD: c_int = 2;
+const FD_CLOEXEC: c_int = 1;
+
+unsafe extern "C" {
+    fn fcntl(fd: c_int, cmd: c_int, ...) -> c_int;
+}
+
+/// Whether the passed file descriptors have been taken, so they are never owned twice.
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Returns true if the service manager passed sockets to this process, without taking them.
+pub fn is_socket_activated() -> bool {
+    listen_fds_count().is_ok_and(|count| count > 0)
+}
+
+/// Takes the sockets passed by the service manager, like `sd_listen_fds_with_names`.
+///
+/// # Returns
+///
+/// The sockets along with their names, from `LISTEN_FDNAMES` (`fd:3`, `fd:4`... for the ones
+/// without a name). The list is empty if no sockets were passed, if they were meant for another
+/// process (`LISTEN_PID` is not ours), or if they were taken already.
+///
+/// # Note
+///
+/// The variables are left in the environment, which the standard library cannot change safely
+/// in a multithreaded process. Child processes ignore them anyway, because of `LISTEN_PID`.
+///
+/// # Example
+///
+/// ```no_run
+/// use rust_server::systemd;
+/// use std::net::TcpListener;
+///
+/// for (name, fd) in systemd::take_listen_fds().unwrap() {
+///     let listener = TcpListener::from(fd);
+///     println!("{} is {}", name, listener.local_addr().unwrap());
+/// }
+/// ```
+pub fn take_listen_fds() -> IoResult<Vec<(String, OwnedFd)>> {
+    let count = listen_fds_count()?;
+    let names = env::var("LISTEN_FDNAMES").unwrap_or_default();
+    let names = names.split(':').filter(|name| !name.is_empty());
+    adopt_fds(count, names)
+}
+
+/// Takes ownership of the descriptors passed from `LISTEN_FDS_START` on, by systemd or by the
+/// process starting an upgrade, and names them (`fd:3`, `fd:4`... when names run out).
+pub(crate) fn adopt_fds<'n>(
+    count: RawFd,
+    mut names: impl Iterator<Item = &'n str>,
+) -> IoResult<Vec<(String, OwnedFd)>> {
+    if count == 0 || TAKEN.swap(true, Ordering::SeqCst) {
+        return Ok(Vec::new());
+    }
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            // the passed descriptors must not leak into the processes we start
+            set_close_on_exec(fd).map_err(|e| {
+                IoError::new(
+                    e.kind(),
+                    format!("cannot use passed descriptor {}: {}", fd, e),
+                )
+            })?;
+            let name = names
+                .next()
+                .map_or_else(|| format!("fd:{}", fd), str::to_string);
+            // SAFETY: the descriptor was passed to us, and TAKEN ensures it is only wrapped once
+            Ok((name, unsafe { OwnedFd::from_raw_fd(fd) }))
+        })
+        .collect()
+}
+
+pub(crate) fn set_close_on_exec(fd: RawFd) -> IoResult<()> {
+    // SAFETY: fcntl with F_SETFD only changes the flags of the descriptor
+    if unsafe { fcntl(fd, F_SETFD, FD_CLOEXEC) } == -1 {
+        return Err(IoError::last_os_error());
+    }
+    Ok(())
+}
+
+/// Returns the number of sockets passed to this process, from `LISTEN_FDS` and `LISTEN_PID`.
+fn listen_fds_count() -> IoResult<RawFd> {
+    let invalid = |name: &str| {
+        IoError::new(
+            ErrorKind::InvalidData,
+            format!("{} is not a valid number", name),
+        )
+    };
+    let Ok(pid) = env::var("LISTEN_PID") else {
+        return Ok(0);
+    };
+    if pid.parse::<u32>().map_err(|_| invalid("LISTEN_PID"))? != process::id() {
+        return Ok(0);
+    }
+    let count = env::var("LISTEN_FDS").unwrap_or_default();
+    let count = count.parse::<RawFd>().map_err(|_| invalid("LISTEN_FDS"))?;
+    Ok(count.max(0))
+}
+
+/// Sends a state change to the service manager, like `sd_notify`: for example `READY=1` once
+/// the server accepts connections, or `STATUS=...` with a line to show in `systemctl status`.
+///
+/// # Returns
+///
+/// `true` if the message was sent, or `false` if the process was not started by a service manager
+/// expecting notifications (`NOTIFY_SOCKET` is not set).
+pub fn notify(state: &str) -> IoResult<bool> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    let socket = UnixDatagram::unbound()?;
+    let bytes = path.as_encoded_bytes();
+    match bytes.first() {
+        Some(b'/') => socket.send_to(state.as_bytes(), &path)?,
+        // a name starting with `@` is in the abstract namespace, which only Linux has
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        Some(b'@') => {
+            #[cfg(target_os = "android")]
+            use std::os::android::net::SocketAddrExt;
+            #[cfg(target_os = "linux")]
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(&bytes[1..])?;
+            socket.send_to_addr(state.as_bytes(), &addr)?
+        }
+        _ => {
+            return Err(IoError::new(
+                ErrorKind::InvalidInput,
+                "NOTIFY_SOCKET is not a socket path",
+            ));
+        }
+    };
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listener::Listener;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+    use std::time::Duration;
+
+    unsafe extern "C" {
+        fn dup2(old: c_int, new: c_int) -> c_int;
+    }
+
+    const CHILD: &str = "RUST_SERVER_TEST_SOCKET_ACTIVATION";
+
+    /// Runs this test again in a child process, started the way systemd starts services:
+    /// with a listening socket as descriptor 3 and the variables describing it.
+    #[test]
+    fn test_socket_activation_in_child_process() {
+        if env::var_os(CHILD).is_some() {
+            return serve_activated_socket();
+        }
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let notify_path = env::temp_dir().join(format!("rust_server_notify_{}", process::id()));
+        let _ = std::fs::remove_file(&notify_path);
+        let notifications = UnixDatagram::bind(&notify_path).unwrap();
+        notifications
+            .set_read_timeout(Some(Duration::from_secs(30)))
+            .unwrap();
+
+        let fd = listener.as_raw_fd();
+        let mut command = Command::new("/bin/sh");
+        // LISTEN_PID must be the pid of the child, which `exec` keeps from the shell
+        command
+            .args(["-c", "LISTEN_PID=$$ exec \"$0\" \"$@\""])
+            .arg(env::current_exe().unwrap())
+            .args([
+                "systemd::tests::test_socket_activation_in_child_process",
+                "--exact",
+                "--quiet",
+            ])
+            .env(CHILD, "1")
+            .env("LISTEN_FDS", "1")
+            .env("LISTEN_FDNAMES", "web")
+            .env("NOTIFY_SOCKET", &notify_path)
+            .stdout(Stdio::piped());
+        // SAFETY: dup2 and fcntl are async-signal-safe; the copy made by dup2 has no
+        // close-on-exec flag, which fcntl clears when the socket is descriptor 3 already
+        unsafe {
+            command.pre_exec(move || {
+                let result = if fd == LISTEN_FDS_START {
+                    fcntl(fd, F_SETFD, 0)
+                } else {
+                    dup2(fd, LISTEN_FDS_START)
+                };
+                if result == -1 {
+                    return Err(IoError::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = command.spawn().unwrap();
+
+        let mut message = [0; 64];
+        let len = notifications.recv(&mut message).unwrap();
+        assert_eq!(&message[..len], b"READY=1");
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        assert_eq!(reply, "hello from web");
+        let output = child.wait_with_output().unwrap();
+        print!("{}", String::from_utf8_lossy(&output.stdout));
+        assert!(output.status.success());
+        std::fs::remove_file(&notify_path).unwrap();
+    }
+
+    /// The child side: takes the socket, reports readiness and answers one connection.
+    fn serve_activated_socket() {
+        assert!(is_socket_activated());
+        let mut fds = take_listen_fds().unwrap();
+        assert!(take_listen_fds().unwrap().is_empty());
+        assert_eq!(fds.len(), 1);
+        let (name, fd) = fds.remove(0);
+        let listener = Listener::inherit(&name, fd).unwrap();
+        assert!(notify("READY=1").unwrap());
+        let (mut stream, _) = listener
+            .accept_timeout(Duration::from_secs(30))
+            .unwrap()
+            .unwrap();
+        write!(stream, "hello from {}", listener.name).unwrap();
+    }
+}
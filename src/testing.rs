@@ -0,0 +1,39 @@
+// Handlers shared by the tests of the handlers wrapping another one, such as rate limiting.
+
+use crate::handler_error::HandlerError;
+use crate::http::{Request, Response, StatusCode};
+use crate::json::Value;
+use crate::server::Handler;
+
+/// Answers every request with 200 OK.
+pub(crate) struct OkHandler;
+
+impl Handler for OkHandler {
+    fn handle_request(&self, _request: &Request) -> Result<Response, HandlerError> {
+        Ok(Response::new(StatusCode::Ok, None))
+    }
+}
+
+/// Answers with the name of the principal, followed by its `email` claim if it has one,
+/// to check that they reach the handler.
+pub(crate) struct WhoAmI;
+
+impl Handler for WhoAmI {
+    fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
+        let body = request.principal().map(|principal| {
+            match principal.claim("email").and_then(Value::as_str) {
+                Some(email) => format!("{} {}", principal.name, email),
+                None => principal.name.clone(),
+            }
+        });
+        Ok(Response::new(StatusCode::Ok, body))
+    }
+}
+
+/// Sends a GET request for `target`, with a header line such as `Authorization: Basic ...` if given.
+pub(crate) fn get(handler: &impl Handler, target: &str, header: Option<&str>) -> Response {
+    let header = header.map_or(String::new(), |header| format!("{}\r\n", header));
+    let source = format!("GET {} HTTP/1.1\r\n{}\r\n", target, header);
+    let request = Request::try_from(source.as_bytes()).unwrap();
+    handler.handle_request(&request).unwrap()
+}
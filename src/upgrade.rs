@@ -0,0 +1,200 @@
+// Zero-downtime upgrades: the running server starts the new binary with its listening sockets,
+// as file descriptors 3, 4..., and waits until the new process accepts connections before it stops
+// accepting and drains its own. The sockets stay open the whole time, so no connection is refused.
+//
+// The handover follows the systemd protocol, under names of our own: `LISTEN_PID` cannot be set
+// to the pid of a process started with `std::process::Command`, so the new process checks that it
+// was started by `RUST_SERVER_PARENT_PID` instead. It reports that it is ready by writing to a pipe,
+// whose other end the old process reads: an early exit closes the pipe, and is noticed at once.
+
+use crate::systemd::{self, LISTEN_FDS_START};
+use std::env;
+use std::ffi::c_int;
+use std::fs::File;
+use std::io::{self, Error as IoError, ErrorKind, Read, Result as IoResult, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::{CommandExt, parent_id};
+use std::process::{self, Child, Command};
+use std::sync::{Mutex, PoisonError, mpsc};
+use std::thread;
+use std::time::Duration;
+
+const LISTEN_FDS: &str = "RUST_SERVER_LISTEN_FDS"; // number of sockets, from descriptor 3 on
+const LISTEN_FDNAMES: &str = "RUST_SERVER_LISTEN_FDNAMES"; // their names, one per line
+const READY_FD: &str = "RUST_SERVER_READY_FD"; // the pipe to write `READY=1` to
+const PARENT_PID: &str = "RUST_SERVER_PARENT_PID"; // the process handing the sockets over
+
+const F_DUPFD: c_int = 0;
+
+unsafe extern "C" {
+    fn fcntl(fd: c_int, cmd: c_int, ...) -> c_int;
+    fn dup2(old: c_int, new: c_int) -> c_int;
+    fn close(fd: c_int) -> c_int;
+}
+
+/// The pipe to report readiness on, taken along with the sockets.
+static READY: Mutex<Option<OwnedFd>> = Mutex::new(None);
+
+/// Takes the sockets handed over by the process that started this one for an upgrade.
+///
+/// # Returns
+///
+/// The sockets and their names, or an empty list if this process was not started for an upgrade.
+pub(crate) fn take_listen_fds() -> IoResult<Vec<(String, OwnedFd)>> {
+    let parent = env::var(PARENT_PID).ok().and_then(|pid| pid.parse().ok());
+    if parent != Some(parent_id()) {
+        return Ok(Vec::new());
+    }
+    let invalid = |name: &str| {
+        IoError::new(
+            ErrorKind::InvalidData,
+            format!("{} is not a valid number", name),
+        )
+    };
+    let count = env::var(LISTEN_FDS).unwrap_or_default();
+    let count: RawFd = count.parse().map_err(|_| invalid(LISTEN_FDS))?;
+    let names = env::var(LISTEN_FDNAMES).unwrap_or_default();
+    let listeners = systemd::adopt_fds(count, names.lines())?;
+    if listeners.is_empty() {
+        return Ok(listeners); // taken already
+    }
+    let ready = env::var(READY_FD).unwrap_or_default();
+    let ready: RawFd = ready.parse().map_err(|_| invalid(READY_FD))?;
+    systemd::set_close_on_exec(ready)?;
+    // SAFETY: the descriptor was passed to us, right after the sockets taken once above
+    let ready = unsafe { OwnedFd::from_raw_fd(ready) };
+    *READY.lock().unwrap_or_else(PoisonError::into_inner) = Some(ready);
+    Ok(listeners)
+}
+
+/// Tells the process that handed the sockets over that this one accepts connections,
+/// if it was started for an upgrade.
+pub(crate) fn report_ready() -> IoResult<()> {
+    let ready = READY.lock().unwrap_or_else(PoisonError::into_inner).take();
+    match ready {
+        // the pipe is closed once written, which the other process waits for
+        Some(fd) => File::from(fd).write_all(b"READY=1\n"),
+        None => Ok(()),
+    }
+}
+
+/// Starts a process with the given listening sockets, and waits until it reports that it is ready.
+///
+/// # Arguments
+///
+/// * `command` - The new binary, with its arguments.
+/// * `listeners` - The names and descriptors of the sockets, passed as descriptors 3, 4...
+/// * `timeout` - How long the process may take to start accepting connections.
+///
+/// # Returns
+///
+/// The new process, or an error if it could not be started, exited, or was not ready in time
+/// (it is killed then).
+pub(crate) fn hand_over(
+    mut command: Command,
+    listeners: &[(String, RawFd)],
+    timeout: Duration,
+) -> IoResult<Child> {
+    let (mut reader, writer) = io::pipe()?;
+    let sources: Vec<RawFd> = listeners
+        .iter()
+        .map(|(_, fd)| *fd)
+        .chain([writer.as_raw_fd()])
+        .collect();
+    let names: Vec<&str> = listeners.iter().map(|(name, _)| name.as_str()).collect();
+    command
+        .env(LISTEN_FDS, listeners.len().to_string())
+        .env(LISTEN_FDNAMES, names.join("\n"))
+        .env(
+            READY_FD,
+            (LISTEN_FDS_START + listeners.len() as RawFd).to_string(),
+        )
+        .env(PARENT_PID, process::id().to_string())
+        // sockets passed by systemd were meant for this process only
+        .env_remove("LISTEN_FDS")
+        .env_remove("LISTEN_FDNAMES")
+        .env_remove("LISTEN_PID");
+    let mut copies = vec![0; sources.len()];
+    // SAFETY: between fork and exec, only async-signal-safe calls are made (fcntl, dup2, close)
+    // and nothing is allocated, `copies` having its final size already
+    unsafe {
+        command.pre_exec(move || {
+            // copy every descriptor above the target range first, so that moving one into place
+            // never overwrites another that is still to be moved
+            let above = LISTEN_FDS_START + sources.len() as RawFd;
+            for (copy, source) in copies.iter_mut().zip(&sources) {
+                *copy = fcntl(*source, F_DUPFD, above);
+                if *copy == -1 {
+                    return Err(IoError::last_os_error());
+                }
+            }
+            // the copies made by dup2 are not closed on exec, unlike every other descriptor
+            for (target, copy) in (LISTEN_FDS_START..).zip(&copies) {
+                if dup2(*copy, target) == -1 {
+                    return Err(IoError::last_os_error());
+                }
+                close(*copy);
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn()?;
+    drop(writer); // otherwise the pipe never reports the end of the new process
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut report = String::new();
+        let _ = reader.read_to_string(&mut report);
+        let _ = sender.send(report);
+    });
+    let failure = match receiver.recv_timeout(timeout) {
+        Ok(report) if report.lines().any(|line| line == "READY=1") => return Ok(child),
+        Ok(_) => match child.wait() {
+            Ok(status) => format!("the new process exited before it was ready ({})", status),
+            Err(e) => format!("the new process failed before it was ready: {}", e),
+        },
+        Err(_) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            format!(
+                "the new process was not ready after {} seconds and was stopped",
+                timeout.as_secs()
+            )
+        }
+    };
+    Err(IoError::other(failure))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn shell(script: &str) -> Command {
+        let mut command = Command::new("/bin/sh");
+        command.args(["-c", script]);
+        command
+    }
+
+    #[test]
+    fn test_hand_over_waits_for_the_new_process() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let listeners = [("web".to_string(), listener.as_raw_fd())];
+        // the socket is descriptor 3 and the pipe comes right after it
+        let script = r#"[ "$RUST_SERVER_LISTEN_FDS" = 1 ] && [ "$RUST_SERVER_LISTEN_FDNAMES" = web ] \
+            && [ "$RUST_SERVER_READY_FD" = 4 ] && true <&3 && echo READY=1 >&4"#;
+        let mut child = hand_over(shell(script), &listeners, Duration::from_secs(30)).unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn test_hand_over_fails_when_the_new_process_exits() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let listeners = [("web".to_string(), listener.as_raw_fd())];
+        let e = hand_over(shell("exit 3"), &listeners, Duration::from_secs(30)).unwrap_err();
+        assert!(e.to_string().contains("exited before it was ready"));
+
+        let e = hand_over(shell("sleep 30"), &listeners, Duration::from_millis(100)).unwrap_err();
+        assert!(e.to_string().contains("was not ready"));
+    }
+}
@@ -1,9 +1,54 @@
 use super::server::Handler;
-use crate::http::{Method, Request, Response, StatusCode};
-use std::fs;
+use crate::compression::ContentEncoding;
+use crate::error_page::error_response;
+use crate::file_cache::{CacheStats, CachedFile, CachedVariant, FileCache, Stamp};
+use crate::handler_error::HandlerError;
+use crate::http::{
+    Method, Request, Response, StatusCode, format_http_date, is_compressible, mime_type,
+};
+use crate::representation::{Contents, Representation, encoded_etag, is_spa_route};
+use std::fs::{self, File, Metadata};
+use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::UNIX_EPOCH;
+
+/// Files smaller than this are sent uncompressed by default, since the savings would not
+/// make up for the framing overhead and the work of compressing them.
+pub const DEFAULT_COMPRESSION_MIN_SIZE: u64 = 1024;
+
+/// Files larger than this are never compressed on the fly, because the whole file has to be
+/// held in memory while it is compressed.
+const MAX_COMPRESSION_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Content codings applied on the fly, most preferred first.
+const DYNAMIC_ENCODINGS: [ContentEncoding; 2] = [ContentEncoding::Gzip, ContentEncoding::Deflate];
+
+/// Precompressed siblings looked up next to every file (e.g. `app.js.br` for `app.js`),
+/// most preferred first. They are preferred over compressing on the fly.
+const PRECOMPRESSED_ENCODINGS: [(ContentEncoding, &str); 2] = [
+    (ContentEncoding::Brotli, "br"),
+    (ContentEncoding::Gzip, "gz"),
+];
 
 pub struct WebsiteHandler {
-    public_path: String, // path to the public directory
+    public_path: String,                    // path to the public directory
+    compression_min_size: Option<u64>,      // smallest file to compress, or None to never compress
+    cache: Option<Mutex<FileCache>>,        // files kept in memory between requests, if enabled
+    spa_fallback: Option<String>,           // document served for client-side routes, if enabled
+    error_pages: Vec<(StatusCode, String)>, // documents sent as the body of error responses
+}
+
+/// Computes a strong entity tag for a file from its size and modification time,
+/// so it changes whenever the file is rewritten (similar to what nginx and Apache do).
+fn etag_for(metadata: &Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos())
+        .unwrap_or(0);
+    format!("\"{:x}-{:x}\"", modified, metadata.len())
 }
 
 impl WebsiteHandler {
@@ -16,41 +61,416 @@ impl WebsiteHandler {
     /// # Returns
     ///
     /// A `WebsiteHandler` object.
+    ///
+    /// # Note
+    ///
+    /// The path is made canonical here, since requested files are checked to be under it once they are
+    /// made canonical themselves. A relative path is resolved against the current directory, so it must
+    /// exist when the handler is created: otherwise every request is answered with 404 Not Found.
     pub fn new(public_path: String) -> Self {
-        Self { public_path }
+        let public_path = fs::canonicalize(&public_path)
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or(public_path);
+        Self {
+            public_path,
+            compression_min_size: Some(DEFAULT_COMPRESSION_MIN_SIZE),
+            cache: None,
+            spa_fallback: None,
+            error_pages: Vec::new(),
+        }
+    }
+
+    /// Compresses text files of at least `min_size` bytes with gzip or deflate,
+    /// for clients that accept one of them. By default files of 1 KiB and up are compressed.
+    ///
+    /// # Arguments
+    ///
+    /// * `min_size` - The size, in bytes, from which files are compressed.
+    pub fn with_compression(mut self, min_size: u64) -> Self {
+        self.compression_min_size = Some(min_size);
+        self
+    }
+
+    /// Sends every file uncompressed, regardless of `Accept-Encoding`.
+    pub fn without_compression(mut self) -> Self {
+        self.compression_min_size = None;
+        self
+    }
+
+    /// Enables single-page application mode: requests for missing paths that look like client-side
+    /// routes (no extension, and the client accepts HTML) get the fallback document with 200 OK
+    /// instead of 404 Not Found. Missing assets such as `/app.js` still get 404.
+    ///
+    /// # Arguments
+    ///
+    /// * `fallback` - The path of the fallback document relative to the public directory, usually `index.html`.
+    pub fn with_spa_fallback(mut self, fallback: &str) -> Self {
+        self.spa_fallback = Some(fallback.to_string());
+        self
+    }
+
+    /// Sends a document from the public directory as the body of error responses with the given status,
+    /// instead of the built-in page. Clients preferring JSON still get problem details.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The error status, e.g. `StatusCode::NotFound`.
+    /// * `file_path` - The path of the HTML document relative to the public directory, e.g. `404.html`.
+    pub fn with_error_page(mut self, status: StatusCode, file_path: &str) -> Self {
+        self.error_pages
+            .retain(|(page_status, _)| *page_status != status);
+        self.error_pages.push((status, file_path.to_string()));
+        self
+    }
+
+    /// Keeps files in memory between requests, along with their compressed versions.
+    /// Cached files are checked against the disk on every request, so changes are picked up right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache` - The cache to use, which sets the memory budget.
+    pub fn with_cache(mut self, cache: FileCache) -> Self {
+        self.cache = Some(Mutex::new(cache));
+        self
     }
 
-    /// Reads a file from the public directory and returns its contents as a string.
+    /// Returns the hit and miss counters of the cache, if caching is enabled.
+    pub fn cache_stats(&self) -> Option<Arc<CacheStats>> {
+        self.lock_cache().map(|cache| cache.stats())
+    }
+
+    /// Resolves a path relative to the public directory.
     /// This method provides a basic level of security by checking for directory traversal attacks.
     ///
     /// # Arguments
     ///
-    /// * `file_path` - The path to the file to read.
+    /// * `file_path` - The path to resolve.
     ///
     /// # Returns
     ///
-    /// A `String` containing the contents of the file if it exists, otherwise `None`.
-    fn read_file(&self, file_path: &str) -> Option<String> {
+    /// The canonical path, or an error if it does not exist or points outside of the public directory.
+    fn resolve(&self, file_path: &str) -> IoResult<PathBuf> {
         let raw_path = format!("{}/{}", self.public_path, file_path);
         // For security reasons, we need to check if the requested path is under the public path
-        match fs::canonicalize(&raw_path) {
+        let path = fs::canonicalize(&raw_path)?;
+        if path.starts_with(&self.public_path) {
+            Ok(path)
+        } else {
+            Err(IoError::new(
+                ErrorKind::PermissionDenied,
+                "path is outside of the public directory",
+            ))
+        }
+    }
+
+    /// Opens a file from the public directory for reading.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - The path to the file to open.
+    ///
+    /// # Returns
+    ///
+    /// The open `File` along with its metadata if it exists and is a regular file, otherwise `None`.
+    fn open_file(&self, file_path: &str) -> Option<(File, Metadata)> {
+        match self.resolve(file_path) {
             Ok(path) => {
-                if path.starts_with(&self.public_path) {
-                    // ok() returns an Option<String>
-                    // if the file is not found, ok() returns None
-                    // if the file is found, ok() returns Some(String)
-                    fs::read_to_string(path).ok()
-                } else {
-                    println!("Directory Traversal Attack Attempted: {}", file_path);
-                    None
-                }
+                // ok() turns the Result into an Option, so a file that cannot be opened becomes None
+                let file = File::open(path).ok()?;
+                let metadata = file.metadata().ok()?;
+                // directories can be opened too, but they have no contents to serve
+                metadata.is_file().then_some((file, metadata))
+            }
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                println!("Directory Traversal Attack Attempted: {}", file_path);
+                None
             }
             Err(e) => {
-                println!("Error resolving path: {}, error: {}", &raw_path, e);
+                println!(
+                    "Error resolving path: {}/{}, error: {}",
+                    self.public_path, file_path, e
+                );
                 None
             }
         }
     }
+
+    /// Returns true if a file of the given type and size should be compressed on the fly.
+    fn is_dynamically_compressible(&self, content_type: &str, len: u64) -> bool {
+        is_compressible(content_type)
+            && self
+                .compression_min_size
+                .is_some_and(|min_size| (min_size..=MAX_COMPRESSION_SIZE).contains(&len))
+    }
+
+    /// Looks for precompressed siblings of a file, such as `app.js.gz` next to `app.js`.
+    ///
+    /// # Note
+    ///
+    /// A sibling older than the file itself is ignored, since it was most likely
+    /// compressed from a previous version of the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - The path of the original file relative to the public directory.
+    /// * `metadata` - The metadata of the original file.
+    ///
+    /// # Returns
+    ///
+    /// The siblings found, along with the coding they use, most preferred first.
+    fn find_precompressed(
+        &self,
+        file_path: &str,
+        metadata: &Metadata,
+    ) -> Vec<(ContentEncoding, File, Metadata)> {
+        PRECOMPRESSED_ENCODINGS
+            .iter()
+            .filter_map(|(encoding, extension)| {
+                // a missing sibling is the common case, so it is not worth logging
+                let path = self.resolve(&format!("{}.{}", file_path, extension)).ok()?;
+                let file = File::open(path).ok()?;
+                let sibling = file.metadata().ok()?;
+                let fresh = match (sibling.modified(), metadata.modified()) {
+                    (Ok(sibling_time), Ok(original_time)) => sibling_time >= original_time,
+                    _ => true,
+                };
+                (sibling.is_file() && fresh).then_some((*encoding, file, sibling))
+            })
+            .collect()
+    }
+
+    /// Serves a file from the public directory, compressed if the client accepts it.
+    ///
+    /// # Note
+    ///
+    /// Precompressed siblings (`.br` and `.gz`) are served when the client accepts their coding.
+    /// Otherwise text files of at least the configured size are compressed on the fly with gzip
+    /// or deflate, and everything else is sent as is. Responses that could have been encoded
+    /// carry `Vary: Accept-Encoding`, each encoded representation gets its own `ETag`,
+    /// and ranges refer to the encoded bytes. When caching is enabled, files that fit in the
+    /// cache are served from memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request being answered.
+    /// * `file_path` - The path of the file relative to the public directory.
+    ///
+    /// # Returns
+    ///
+    /// A `Response` object containing the file (or part of it), or 404 Not Found if the file cannot be read.
+    fn serve_file(&self, request: &Request, file_path: &str) -> Response {
+        let representation = match self.cached_representation(request, file_path) {
+            Some(representation) => Some(representation),
+            None => self.read_representation(request, file_path),
+        };
+        let Some(representation) = representation else {
+            return Response::new(StatusCode::NotFound, None);
+        };
+
+        representation.respond(request, file_path)
+    }
+
+    /// Selects the representation of a file to send, reading it from disk.
+    ///
+    /// # Returns
+    ///
+    /// The representation, or `None` if the file cannot be read.
+    fn read_representation(&self, request: &Request, file_path: &str) -> Option<Representation> {
+        let (mut file, metadata) = self.open_file(file_path)?;
+        let content_type = mime_type(file_path);
+        let last_modified = metadata.modified().ok().map(format_http_date);
+
+        let precompressed = self.find_precompressed(file_path, &metadata);
+        let mut available: Vec<ContentEncoding> = precompressed
+            .iter()
+            .map(|(encoding, _, _)| *encoding)
+            .collect();
+        if self.is_dynamically_compressible(content_type, metadata.len()) {
+            for encoding in DYNAMIC_ENCODINGS {
+                if !available.contains(&encoding) {
+                    available.push(encoding);
+                }
+            }
+        }
+        let encoding = ContentEncoding::negotiate(request.header("Accept-Encoding"), &available);
+        let varies = !available.is_empty();
+
+        let precompressed = encoding.and_then(|encoding| {
+            precompressed
+                .into_iter()
+                .find(|(sibling_encoding, _, _)| *sibling_encoding == encoding)
+        });
+        let representation = match (encoding, precompressed) {
+            (Some(encoding), Some((_, sibling, sibling_metadata))) => Representation {
+                len: sibling_metadata.len(),
+                contents: Contents::File(sibling),
+                content_type,
+                encoding: Some(encoding),
+                etag: encoded_etag(&etag_for(&sibling_metadata), encoding),
+                last_modified,
+                varies,
+            },
+            (Some(encoding), None) => {
+                let mut bytes = Vec::new();
+                if let Err(e) = file.read_to_end(&mut bytes) {
+                    println!("Error reading file: {}, error: {}", file_path, e);
+                    return None;
+                }
+                // only dynamic codings are left once precompressed siblings are ruled out
+                let encoded = encoding.encode(&bytes).unwrap_or(bytes);
+                Representation {
+                    len: encoded.len() as u64,
+                    contents: Contents::Bytes(encoded.into()),
+                    content_type,
+                    encoding: Some(encoding),
+                    etag: encoded_etag(&etag_for(&metadata), encoding),
+                    last_modified,
+                    varies,
+                }
+            }
+            (None, _) => Representation {
+                len: metadata.len(),
+                contents: Contents::File(file),
+                content_type,
+                encoding: None,
+                etag: etag_for(&metadata),
+                last_modified,
+                varies,
+            },
+        };
+        Some(representation)
+    }
+
+    /// Selects the representation of a file to send from the cache, loading the file into
+    /// the cache first if it is not there yet.
+    ///
+    /// # Returns
+    ///
+    /// The representation, or `None` if caching is disabled or the file cannot be cached
+    /// (because it is missing, too large, or unreadable), in which case it is read from disk instead.
+    fn cached_representation(&self, request: &Request, file_path: &str) -> Option<Representation> {
+        let max_file_size = self.lock_cache()?.max_file_size();
+        // resolution errors are logged when falling back to reading from disk
+        let path = self.resolve(file_path).ok()?;
+        let cached = self.lock_cache()?.get(&path).cloned();
+        let cached = match cached {
+            Some(cached) => cached,
+            None => {
+                // the lock is not held while loading, so other workers keep using the cache;
+                // two workers missing the same file at once both load it, and the last insert wins
+                let cached = self.load_cached(file_path, &path, max_file_size)?;
+                self.lock_cache()?.insert(path, cached.clone());
+                cached
+            }
+        };
+
+        let available: Vec<ContentEncoding> = cached
+            .variants
+            .iter()
+            .map(|variant| variant.encoding)
+            .collect();
+        let encoding = ContentEncoding::negotiate(request.header("Accept-Encoding"), &available);
+        let variant = encoding.and_then(|encoding| {
+            cached
+                .variants
+                .iter()
+                .find(|variant| variant.encoding == encoding)
+        });
+        let (bytes, etag) = match variant {
+            Some(variant) => (Arc::clone(&variant.bytes), variant.etag.clone()),
+            None => (Arc::clone(&cached.bytes), cached.etag.clone()),
+        };
+        Some(Representation {
+            len: bytes.len() as u64,
+            contents: Contents::Bytes(bytes),
+            content_type: cached.content_type,
+            encoding,
+            etag,
+            last_modified: cached.last_modified,
+            varies: !available.is_empty(),
+        })
+    }
+
+    /// Locks the cache, if caching is enabled.
+    ///
+    /// # Note
+    ///
+    /// A worker panicking while holding the lock leaves the cache consistent (entries are
+    /// inserted whole), so a poisoned lock is used as is rather than disabling the cache.
+    fn lock_cache(&self) -> Option<MutexGuard<'_, FileCache>> {
+        let cache = self.cache.as_ref()?;
+        Some(cache.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Reads a file into memory, along with its precompressed siblings, and compresses it
+    /// with every dynamic coding if it is worth it, so the cache can answer any `Accept-Encoding`.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - The path of the file relative to the public directory.
+    /// * `path` - The resolved path of the file.
+    /// * `max_file_size` - The size of the largest file to load.
+    ///
+    /// # Returns
+    ///
+    /// The file ready to be cached, or `None` if it is not a regular file, is too large, or cannot be read.
+    fn load_cached(&self, file_path: &str, path: &Path, max_file_size: u64) -> Option<CachedFile> {
+        let mut file = File::open(path).ok()?;
+        let metadata = file.metadata().ok()?;
+        if !metadata.is_file() || metadata.len() > max_file_size {
+            return None;
+        }
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).ok()?;
+        let content_type = mime_type(file_path);
+        let etag = etag_for(&metadata);
+
+        // the siblings are watched even when missing, so adding one later invalidates the entry
+        let mut sources = vec![(path.to_path_buf(), Some(Stamp::from_metadata(&metadata)))];
+        for (_, extension) in PRECOMPRESSED_ENCODINGS {
+            let sibling =
+                PathBuf::from(format!("{}/{}.{}", self.public_path, file_path, extension));
+            let stamp = Stamp::of(&sibling);
+            sources.push((sibling, stamp));
+        }
+
+        let mut variants = Vec::new();
+        for (encoding, mut sibling, sibling_metadata) in
+            self.find_precompressed(file_path, &metadata)
+        {
+            let mut sibling_bytes = Vec::new();
+            if sibling.read_to_end(&mut sibling_bytes).is_ok() {
+                variants.push(CachedVariant {
+                    encoding,
+                    bytes: sibling_bytes.into(),
+                    etag: encoded_etag(&etag_for(&sibling_metadata), encoding),
+                });
+            }
+        }
+        if self.is_dynamically_compressible(content_type, metadata.len()) {
+            for encoding in DYNAMIC_ENCODINGS {
+                if variants.iter().any(|variant| variant.encoding == encoding) {
+                    continue;
+                }
+                if let Some(encoded) = encoding.encode(&bytes) {
+                    variants.push(CachedVariant {
+                        encoding,
+                        bytes: encoded.into(),
+                        etag: encoded_etag(&etag, encoding),
+                    });
+                }
+            }
+        }
+
+        Some(CachedFile {
+            bytes: bytes.into(),
+            content_type,
+            etag,
+            last_modified: metadata.modified().ok().map(format_http_date),
+            variants,
+            sources,
+        })
+    }
 }
 
 impl Handler for WebsiteHandler {
@@ -59,8 +479,10 @@ impl Handler for WebsiteHandler {
     /// # Note
     ///
     /// This method is called by the `Server` when a request is received.
-    /// It handles GET requests for the root path ("/") and returns a default body.
-    /// For other paths, it returns a 404 Not Found response.
+    /// It serves `index.html` for the root path ("/") and the file at the requested path otherwise,
+    /// or the fallback document for client-side routes if single-page application mode is enabled.
+    /// Missing files get a 404 Not Found response without a body, which the server fills in
+    /// with `render_error` (the configured error page, or the built-in one).
     ///
     /// # Arguments
     ///
@@ -68,21 +490,333 @@ impl Handler for WebsiteHandler {
     ///
     /// # Returns
     ///
-    /// A `Response` object containing the HTTP response.
-    fn handle_request(&mut self, request: &Request) -> Response {
+    /// A `Response` object containing the HTTP response, or a `HandlerError` for methods other than GET.
+    fn handle_request(&self, request: &Request) -> Result<Response, HandlerError> {
         // Use double nested match to handle both the method and the path
-        match request.method() {
+        let response = match request.method() {
             Method::GET => match request.path() {
-                "/" => Response::new(StatusCode::Ok, self.read_file("index.html")),
-                "/hello" => Response::new(StatusCode::Ok, self.read_file("hello.html")),
-                path => match self.read_file(path) {
-                    // if the file exists, read the contents and return a 200 OK response
-                    Some(contents) => Response::new(StatusCode::Ok, Some(contents)),
-                    // if the file does not exist, return a 404 Not Found response
-                    None => Response::new(StatusCode::NotFound, None),
-                },
+                "/" => self.serve_file(request, "index.html"),
+                "/hello" => self.serve_file(request, "hello.html"),
+                // if the file exists, serve (part of) its contents, otherwise return a 404 Not Found response
+                // (or the fallback document, for client-side routes of a single-page application)
+                path => {
+                    let response = self.serve_file(request, path);
+                    match &self.spa_fallback {
+                        Some(fallback)
+                            if response.status_code() == StatusCode::NotFound
+                                && is_spa_route(request, path) =>
+                        {
+                            self.serve_file(request, fallback)
+                        }
+                        _ => response,
+                    }
+                }
             },
-            _ => Response::new(StatusCode::NotFound, None),
+            _ => return Err(HandlerError::Status(StatusCode::NotFound)),
+        };
+        Ok(response)
+    }
+
+    /// Renders error pages from the documents configured with `with_error_page`,
+    /// falling back to the built-in page if there is none or it cannot be read.
+    fn render_error(&self, status: StatusCode, accept: Option<&str>) -> Response {
+        let document = self
+            .error_pages
+            .iter()
+            .find(|(page_status, _)| *page_status == status)
+            .and_then(
+                |(_, file_path)| match self.resolve(file_path).and_then(fs::read) {
+                    Ok(bytes) => Some(bytes),
+                    Err(e) => {
+                        println!("Error reading error page: {}, error: {}", file_path, e);
+                        None
+                    }
+                },
+            );
+        error_response(status, accept, document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Creates an empty public directory for a test under the system temporary directory.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rust_server_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn get(handler: &WebsiteHandler, target: &str, headers: &[&str]) -> Response {
+        let headers: String = headers
+            .iter()
+            .map(|header| format!("{}\r\n", header))
+            .collect();
+        let source = format!("GET {} HTTP/1.1\r\n{}\r\n", target, headers);
+        let request = Request::try_from(source.as_bytes()).unwrap();
+        handler.handle_request(&request).unwrap()
+    }
+
+    #[test]
+    fn test_serves_files_under_a_relative_root() {
+        // tests run from the directory of the crate, which has a `public` directory
+        for root in ["public", "./public", "public/"] {
+            let handler = WebsiteHandler::new(root.to_string());
+            assert_eq!(
+                get(&handler, "/hello.html", &[]).status_code(),
+                StatusCode::Ok
+            );
+            assert_eq!(
+                get(&handler, "/../Cargo.toml", &[]).status_code(),
+                StatusCode::NotFound
+            );
+        }
+    }
+
+    /// Returns a handler serving files from `dir`, and the same with a file cache.
+    fn handlers(dir: &Path) -> [WebsiteHandler; 2] {
+        let root = dir.to_string_lossy().into_owned();
+        [
+            WebsiteHandler::new(root.clone()),
+            WebsiteHandler::new(root).with_cache(FileCache::new(1024 * 1024)),
+        ]
+    }
+
+    #[test]
+    fn test_precompressed_siblings() {
+        let dir = temp_dir("website_precompressed");
+        fs::write(dir.join("app.js"), "console.log(1);").unwrap();
+        fs::write(dir.join("app.js.gz"), "gzip bytes").unwrap();
+        fs::write(dir.join("app.js.br"), "brotli bytes").unwrap();
+        for handler in handlers(&dir) {
+            let identity = get(&handler, "/app.js", &[]);
+            assert_eq!(identity.body(), Some(&b"console.log(1);"[..]));
+            assert_eq!(identity.header("Content-Encoding"), None);
+            assert_eq!(identity.header("Vary"), Some("Accept-Encoding"));
+            let etag = identity.header("ETag").unwrap();
+
+            for (accept, encoding, body) in [
+                ("gzip", "gzip", &b"gzip bytes"[..]),
+                ("gzip, br", "br", &b"brotli bytes"[..]),
+                ("br;q=0.5, gzip", "gzip", &b"gzip bytes"[..]),
+            ] {
+                let header = format!("Accept-Encoding: {}", accept);
+                let response = get(&handler, "/app.js", &[&header]);
+                assert_eq!(response.body(), Some(body));
+                assert_eq!(response.header("Content-Encoding"), Some(encoding));
+                // the type is the one of the original file, not of a .gz or .br file
+                assert_eq!(
+                    response.header("Content-Type"),
+                    Some("text/javascript; charset=utf-8")
+                );
+                assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+                // every encoding is a representation of its own, with its own tag
+                let tag = response.header("ETag").unwrap();
+                assert_ne!(tag, etag);
+                assert!(tag.ends_with(&format!("-{}\"", encoding)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_stale_precompressed_siblings_are_ignored() {
+        let dir = temp_dir("website_stale_precompressed");
+        fs::write(dir.join("app.js"), "console.log(2);").unwrap();
+        fs::write(dir.join("app.js.gz"), "gzip bytes of version 1").unwrap();
+        let modified = fs::metadata(dir.join("app.js"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        let sibling = File::options()
+            .write(true)
+            .open(dir.join("app.js.gz"))
+            .unwrap();
+        sibling
+            .set_modified(modified - Duration::from_secs(60))
+            .unwrap();
+        for handler in handlers(&dir) {
+            let response = get(&handler, "/app.js", &["Accept-Encoding: gzip"]);
+            assert_eq!(response.body(), Some(&b"console.log(2);"[..]));
+            assert_eq!(response.header("Content-Encoding"), None);
+        }
+    }
+
+    #[test]
+    fn test_cached_files_are_checked_against_the_disk() {
+        let dir = temp_dir("website_cache");
+        fs::write(dir.join("a.txt"), "old").unwrap();
+        let handler = WebsiteHandler::new(dir.to_string_lossy().into_owned())
+            .with_cache(FileCache::new(1024));
+        let stats = handler.cache_stats().unwrap();
+
+        assert_eq!(get(&handler, "/a.txt", &[]).body(), Some(&b"old"[..]));
+        assert_eq!(get(&handler, "/a.txt", &[]).body(), Some(&b"old"[..]));
+        assert_eq!((stats.hits(), stats.misses()), (1, 1));
+
+        thread::sleep(Duration::from_millis(10)); // make sure the modification time moves on
+        fs::write(dir.join("a.txt"), "new contents").unwrap();
+        assert_eq!(
+            get(&handler, "/a.txt", &[]).body(),
+            Some(&b"new contents"[..])
+        );
+        assert_eq!(stats.invalidations(), 1);
+        assert_eq!(get(&handler, "/a.txt", &[]).status_code(), StatusCode::Ok);
+        assert_eq!((stats.hits(), stats.misses()), (2, 2));
+    }
+
+    #[test]
+    fn test_least_recently_used_files_are_evicted() {
+        let dir = temp_dir("website_cache_eviction");
+        for name in ["0.bin", "1.bin", "2.bin"] {
+            fs::write(dir.join(name), "0123456789").unwrap();
+        }
+        // room for two files of 10 bytes
+        let handler =
+            WebsiteHandler::new(dir.to_string_lossy().into_owned()).with_cache(FileCache::new(25));
+        let stats = handler.cache_stats().unwrap();
+
+        for target in ["/0.bin", "/1.bin", "/0.bin", "/2.bin"] {
+            assert_eq!(get(&handler, target, &[]).status_code(), StatusCode::Ok);
+        }
+        // 1.bin was the least recently used when 2.bin came in
+        assert_eq!(stats.evictions(), 1);
+        assert_eq!((stats.hits(), stats.misses()), (1, 3));
+        get(&handler, "/0.bin", &[]);
+        assert_eq!((stats.hits(), stats.misses()), (2, 3));
+        get(&handler, "/1.bin", &[]);
+        assert_eq!((stats.hits(), stats.misses()), (2, 4));
+    }
+
+    #[test]
+    fn test_spa_fallback() {
+        let dir = temp_dir("website_spa");
+        fs::write(dir.join("index.html"), "app").unwrap();
+        fs::write(dir.join("app.js"), "start();").unwrap();
+        let handler =
+            WebsiteHandler::new(dir.to_string_lossy().into_owned()).with_spa_fallback("index.html");
+        let html = "Accept: text/html,application/xhtml+xml,*/*;q=0.8";
+
+        let response = get(&handler, "/reports/42", &[html]);
+        assert_eq!(response.status_code(), StatusCode::Ok);
+        assert_eq!(response.body(), Some(&b"app"[..]));
+        assert_eq!(
+            get(&handler, "/app.js", &[html]).body(),
+            Some(&b"start();"[..])
+        );
+        // missing assets and requests that do not want HTML are not client-side routes
+        assert_eq!(
+            get(&handler, "/missing.js", &[html]).status_code(),
+            StatusCode::NotFound
+        );
+        assert_eq!(
+            get(&handler, "/reports/42", &["Accept: application/json"]).status_code(),
+            StatusCode::NotFound
+        );
+        let handler = WebsiteHandler::new(dir.to_string_lossy().into_owned());
+        assert_eq!(
+            get(&handler, "/reports/42", &[html]).status_code(),
+            StatusCode::NotFound
+        );
+    }
+
+    #[test]
+    fn test_error_pages() {
+        let dir = temp_dir("website_error_pages");
+        fs::write(dir.join("404.html"), "lost").unwrap();
+        fs::write(dir.join("500.html"), "broken").unwrap();
+        let handler = WebsiteHandler::new(dir.to_string_lossy().into_owned())
+            .with_error_page(StatusCode::NotFound, "404.html")
+            .with_error_page(StatusCode::InternalServerError, "500.html")
+            .with_error_page(StatusCode::ServiceUnavailable, "503.html");
+
+        // the handler answers without a body, which the server fills in with `render_error`
+        let response = get(&handler, "/missing.html", &[]);
+        assert_eq!(response.status_code(), StatusCode::NotFound);
+        assert_eq!(response.body(), None);
+        let page = handler.render_error(StatusCode::NotFound, Some("text/html"));
+        assert_eq!(page.body(), Some(&b"lost"[..]));
+        assert_eq!(
+            page.header("Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+        let page = handler.render_error(StatusCode::InternalServerError, None);
+        assert_eq!(page.body(), Some(&b"broken"[..]));
+
+        // statuses without a page, or whose page is missing, get the built-in one
+        for status in [StatusCode::Forbidden, StatusCode::ServiceUnavailable] {
+            let page = handler.render_error(status, None);
+            let body = String::from_utf8(page.body().unwrap().to_vec()).unwrap();
+            assert!(body.contains(&format!("<h1>{} {}</h1>", status, status.reason_phrase())));
+        }
+        // clients preferring JSON get problem details rather than the page
+        let page = handler.render_error(StatusCode::NotFound, Some("application/json"));
+        assert_eq!(
+            page.header("Content-Type"),
+            Some("application/problem+json")
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        let dir = temp_dir("website_ranges");
+        let contents: Vec<u8> = (0..1000).map(|i| b'a' + (i % 26) as u8).collect();
+        fs::write(dir.join("data.bin"), &contents).unwrap();
+        let handler = WebsiteHandler::new(dir.to_string_lossy().into_owned());
+
+        let response = get(&handler, "/data.bin", &["Range: bytes=10-19"]);
+        assert_eq!(response.status_code(), StatusCode::PartialContent);
+        assert_eq!(response.header("Content-Range"), Some("bytes 10-19/1000"));
+        assert_eq!(response.body(), Some(&contents[10..20]));
+
+        let response = get(&handler, "/data.bin", &["Range: bytes=0-9, -10"]);
+        assert_eq!(response.status_code(), StatusCode::PartialContent);
+        let content_type = response.header("Content-Type").unwrap();
+        assert!(content_type.starts_with("multipart/byteranges; boundary="));
+        let body = String::from_utf8(response.body().unwrap().to_vec()).unwrap();
+        assert!(body.contains("Content-Range: bytes 0-9/1000\r\n\r\nabcdefghij\r\n"));
+        assert!(body.contains("Content-Range: bytes 990-999/1000\r\n\r\ncdefghijkl\r\n"));
+
+        // overlapping, repeated and adjacent ranges are merged, so no byte is sent twice
+        let repeated = format!("Range: bytes={}", vec!["0-"; 32].join(","));
+        let response = get(&handler, "/data.bin", &[&repeated]);
+        assert_eq!(response.header("Content-Range"), Some("bytes 0-999/1000"));
+        assert_eq!(response.body().unwrap().len(), 1000);
+        let response = get(&handler, "/data.bin", &["Range: bytes=50-99,0-59,100-109"]);
+        assert_eq!(response.header("Content-Range"), Some("bytes 0-109/1000"));
+        // ranges whose multipart body outweighs the whole file get the whole file
+        let response = get(&handler, "/data.bin", &["Range: bytes=0-490,492-999"]);
+        assert_eq!(response.status_code(), StatusCode::Ok);
+        assert_eq!(response.body(), Some(&contents[..]));
+
+        let response = get(&handler, "/data.bin", &["Range: bytes=1000-"]);
+        assert_eq!(response.status_code(), StatusCode::RangeNotSatisfiable);
+        assert_eq!(response.header("Content-Range"), Some("bytes */1000"));
+    }
+
+    #[test]
+    fn test_if_range() {
+        let dir = temp_dir("website_if_range");
+        fs::write(dir.join("data.bin"), "0123456789").unwrap();
+        let handler = WebsiteHandler::new(dir.to_string_lossy().into_owned());
+        let etag = get(&handler, "/data.bin", &[])
+            .header("ETag")
+            .unwrap()
+            .to_string();
+
+        let if_range = format!("If-Range: {}", etag);
+        let response = get(&handler, "/data.bin", &["Range: bytes=2-3", &if_range]);
+        assert_eq!(response.status_code(), StatusCode::PartialContent);
+        assert_eq!(response.body(), Some(&b"23"[..]));
+
+        // a stale or weak entity tag gets the whole file
+        for if_range in ["If-Range: \"stale\"", "If-Range: W/\"stale\""] {
+            let response = get(&handler, "/data.bin", &["Range: bytes=2-3", if_range]);
+            assert_eq!(response.status_code(), StatusCode::Ok);
+            assert_eq!(response.body(), Some(&b"0123456789"[..]));
         }
     }
 }